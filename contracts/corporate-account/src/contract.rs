@@ -0,0 +1,391 @@
+//! Corporate Smart Account Contract.
+//!
+//! A custom account for a company treasury trading on the marketplace. Its
+//! officers hold ed25519 keys with weights, and any authorization needs
+//! signatures whose weights add up to the threshold. Calls to the
+//! marketplace-escrow-v1 entrypoints that commit funds can additionally be
+//! capped per function, and what the account moves out of it in a token
+//! capped per period, whichever entrypoint pulls the funds. Signers,
+//! threshold and limits are changed by the account itself, so every change
+//! needs the same signatures as a payment.
+
+use soroban_sdk::{
+    auth::{Context, ContractContext, CustomAccountInterface},
+    contract, contracterror, contractimpl, contracttype,
+    crypto::Hash,
+    panic_with_error, Address, BytesN, Env, Map, Symbol, TryFromVal, Val, Vec,
+};
+
+/// Most signers an account may have
+pub const MAX_SIGNERS: u32 = 20;
+
+#[contracttype]
+pub enum DataKey {
+    Signers,
+    Threshold,
+    SpendLimit(Address, Symbol),
+    TokenLimit(Address),
+    Spent(Address),
+}
+
+#[contracterror]
+#[derive(Copy, Clone, Debug, Eq, PartialEq, PartialOrd, Ord)]
+#[repr(u32)]
+pub enum AccountError {
+    UnknownSigner = 1,
+    SignaturesNotSorted = 2,
+    InsufficientWeight = 3,
+    InvalidThreshold = 4,
+    InvalidWeight = 5,
+    SignerAlreadyExists = 6,
+    TooManySigners = 7,
+    SpendLimitExceeded = 8,
+    UnsupportedFunction = 9,
+    InvalidAmount = 10,
+    InvalidPeriod = 11,
+}
+
+/// Officer key and how much its signature counts towards the threshold
+#[contracttype]
+#[derive(Clone, Debug, Eq, PartialEq)]
+pub struct Signer {
+    pub public_key: BytesN<32>,
+    pub weight: u32,
+}
+
+/// Signature by one of the account's signers over the authorization payload
+#[contracttype]
+#[derive(Clone, Debug, Eq, PartialEq)]
+pub struct Signature {
+    pub public_key: BytesN<32>,
+    pub signature: BytesN<64>,
+}
+
+/// Most of a token the account may transfer or approve within each period
+#[contracttype]
+#[derive(Clone, Debug, Eq, PartialEq)]
+pub struct TokenLimit {
+    pub limit: i128,
+    /// Length of a spending period in seconds
+    pub period: u64,
+}
+
+/// What the account has spent of a token in the current period
+#[contracttype]
+#[derive(Clone, Debug, Eq, PartialEq)]
+pub struct SpendWindow {
+    pub started_at: u64,
+    pub spent: i128,
+}
+
+#[contract]
+pub struct CorporateAccount;
+
+#[contractimpl]
+impl CorporateAccount {
+    pub fn __constructor(e: &Env, signers: Vec<Signer>, threshold: u32) {
+        let mut weights = Map::new(e);
+        for signer in signers.iter() {
+            if weights.contains_key(signer.public_key.clone()) {
+                panic_with_error!(e, AccountError::SignerAlreadyExists);
+            }
+            if signer.weight == 0 {
+                panic_with_error!(e, AccountError::InvalidWeight);
+            }
+            weights.set(signer.public_key, signer.weight);
+        }
+        if let Err(error) = Self::store_signers(e, &weights, threshold) {
+            panic_with_error!(e, error);
+        }
+    }
+
+    /// Add an officer key (account only)
+    pub fn add_signer(e: &Env, public_key: BytesN<32>, weight: u32) -> Result<(), AccountError> {
+        e.current_contract_address().require_auth();
+
+        let mut weights = Self::get_signers(e);
+        if weights.contains_key(public_key.clone()) {
+            return Err(AccountError::SignerAlreadyExists);
+        }
+        if weight == 0 {
+            return Err(AccountError::InvalidWeight);
+        }
+        weights.set(public_key, weight);
+        Self::store_signers(e, &weights, Self::get_threshold(e))
+    }
+
+    /// Remove an officer key, as long as the rest can still meet the threshold (account only)
+    pub fn remove_signer(e: &Env, public_key: BytesN<32>) -> Result<(), AccountError> {
+        e.current_contract_address().require_auth();
+
+        let mut weights = Self::get_signers(e);
+        weights.remove(public_key).ok_or(AccountError::UnknownSigner)?;
+        Self::store_signers(e, &weights, Self::get_threshold(e))
+    }
+
+    /// Replace an officer's key with a new one of the same weight (account only)
+    pub fn rotate_signer(
+        e: &Env,
+        old_key: BytesN<32>,
+        new_key: BytesN<32>,
+    ) -> Result<(), AccountError> {
+        e.current_contract_address().require_auth();
+
+        let mut weights = Self::get_signers(e);
+        if weights.contains_key(new_key.clone()) {
+            return Err(AccountError::SignerAlreadyExists);
+        }
+        let weight = weights.get(old_key.clone()).ok_or(AccountError::UnknownSigner)?;
+        weights.remove(old_key);
+        weights.set(new_key, weight);
+        Self::store_signers(e, &weights, Self::get_threshold(e))
+    }
+
+    /// Set the signature weight every authorization needs (account only)
+    pub fn set_threshold(e: &Env, threshold: u32) -> Result<(), AccountError> {
+        e.current_contract_address().require_auth();
+
+        Self::store_signers(e, &Self::get_signers(e), threshold)
+    }
+
+    /// Cap what a single call to a marketplace entrypoint may commit (account only)
+    ///
+    /// Supported entrypoints are `create_trade` (the PO total), `fund_escrow`,
+    /// `top_up_escrow`, `authorize_advance` and `accept_change_order` (its
+    /// `max_payment`).
+    pub fn set_spend_limit(
+        e: &Env,
+        marketplace: Address,
+        fn_name: Symbol,
+        limit: i128,
+    ) -> Result<(), AccountError> {
+        e.current_contract_address().require_auth();
+
+        if amount_arg(e, &fn_name).is_none() {
+            return Err(AccountError::UnsupportedFunction);
+        }
+        if limit < 0 {
+            return Err(AccountError::InvalidAmount);
+        }
+        e.storage().instance().set(&DataKey::SpendLimit(marketplace, fn_name), &limit);
+        Ok(())
+    }
+
+    /// Lift the cap on a marketplace entrypoint (account only)
+    pub fn remove_spend_limit(e: &Env, marketplace: Address, fn_name: Symbol) {
+        e.current_contract_address().require_auth();
+
+        e.storage().instance().remove(&DataKey::SpendLimit(marketplace, fn_name));
+    }
+
+    /// Cap what the account may transfer or approve of a token per period (account only)
+    ///
+    /// Every transfer out of the account counts, so the cap holds whichever
+    /// marketplace entrypoint pulls the funds. Changing the limit starts a
+    /// new period.
+    pub fn set_token_limit(
+        e: &Env,
+        token: Address,
+        limit: i128,
+        period: u64,
+    ) -> Result<(), AccountError> {
+        e.current_contract_address().require_auth();
+
+        if limit < 0 {
+            return Err(AccountError::InvalidAmount);
+        }
+        if period == 0 {
+            return Err(AccountError::InvalidPeriod);
+        }
+        e.storage()
+            .instance()
+            .set(&DataKey::TokenLimit(token.clone()), &TokenLimit { limit, period });
+        e.storage().instance().remove(&DataKey::Spent(token));
+        Ok(())
+    }
+
+    /// Lift the cap on a token (account only)
+    pub fn remove_token_limit(e: &Env, token: Address) {
+        e.current_contract_address().require_auth();
+
+        e.storage().instance().remove(&DataKey::TokenLimit(token.clone()));
+        e.storage().instance().remove(&DataKey::Spent(token));
+    }
+
+    pub fn get_signers(e: &Env) -> Map<BytesN<32>, u32> {
+        e.storage().instance().get(&DataKey::Signers).unwrap_or(Map::new(e))
+    }
+
+    pub fn get_threshold(e: &Env) -> u32 {
+        e.storage().instance().get(&DataKey::Threshold).unwrap_or(0)
+    }
+
+    pub fn get_spend_limit(e: &Env, marketplace: Address, fn_name: Symbol) -> Option<i128> {
+        e.storage().instance().get(&DataKey::SpendLimit(marketplace, fn_name))
+    }
+
+    pub fn get_token_limit(e: &Env, token: Address) -> Option<TokenLimit> {
+        e.storage().instance().get(&DataKey::TokenLimit(token))
+    }
+
+    /// Amount of a token spent in the current period
+    pub fn get_spent(e: &Env, token: Address) -> i128 {
+        let Some(limit) = Self::get_token_limit(e, token.clone()) else {
+            return 0;
+        };
+        current_window(e, &token, &limit).spent
+    }
+
+    /// Store signers and threshold after checking the signers can meet it
+    fn store_signers(
+        e: &Env,
+        weights: &Map<BytesN<32>, u32>,
+        threshold: u32,
+    ) -> Result<(), AccountError> {
+        if weights.len() > MAX_SIGNERS {
+            return Err(AccountError::TooManySigners);
+        }
+        let total =
+            weights.values().iter().fold(0u32, |total, weight| total.saturating_add(weight));
+        if threshold == 0 || threshold > total {
+            return Err(AccountError::InvalidThreshold);
+        }
+
+        e.storage().instance().set(&DataKey::Signers, weights);
+        e.storage().instance().set(&DataKey::Threshold, &threshold);
+        Ok(())
+    }
+}
+
+#[contractimpl]
+impl CustomAccountInterface for CorporateAccount {
+    type Signature = Vec<Signature>;
+    type Error = AccountError;
+
+    /// Verify enough signer weight signed, and that no call or token exceeds its spending limit
+    ///
+    /// Signatures must be sorted by public key, so none is counted twice.
+    fn __check_auth(
+        env: Env,
+        signature_payload: Hash<32>,
+        signatures: Vec<Signature>,
+        auth_contexts: Vec<Context>,
+    ) -> Result<(), AccountError> {
+        let weights = Self::get_signers(&env);
+        let mut weight = 0u32;
+        let mut previous: Option<BytesN<32>> = None;
+        for signature in signatures.iter() {
+            if previous.is_some_and(|previous| previous >= signature.public_key) {
+                return Err(AccountError::SignaturesNotSorted);
+            }
+            let signer_weight =
+                weights.get(signature.public_key.clone()).ok_or(AccountError::UnknownSigner)?;
+            env.crypto().ed25519_verify(
+                &signature.public_key,
+                &signature_payload.clone().into(),
+                &signature.signature,
+            );
+            weight = weight.saturating_add(signer_weight);
+            previous = Some(signature.public_key);
+        }
+        if weight < Self::get_threshold(&env) {
+            return Err(AccountError::InsufficientWeight);
+        }
+
+        for context in auth_contexts.iter() {
+            if let Context::Contract(call) = context {
+                check_spend_limit(&env, &call)?;
+                record_spend(&env, &call)?;
+            }
+        }
+
+        Ok(())
+    }
+}
+
+/// Position of the amount committed among an entrypoint's arguments
+fn amount_arg(env: &Env, fn_name: &Symbol) -> Option<u32> {
+    let supported = ["create_trade", "fund_escrow", "top_up_escrow", "authorize_advance"];
+    if supported.iter().any(|name| fn_name == &Symbol::new(env, name)) {
+        Some(2)
+    } else if fn_name == &Symbol::new(env, "accept_change_order") {
+        // (caller, trade_id, proposal, max_payment)
+        Some(3)
+    } else {
+        None
+    }
+}
+
+/// Fail if a call commits more than the limit set for its entrypoint
+fn check_spend_limit(env: &Env, call: &ContractContext) -> Result<(), AccountError> {
+    let key = DataKey::SpendLimit(call.contract.clone(), call.fn_name.clone());
+    let Some(limit) = env.storage().instance().get::<_, i128>(&key) else {
+        return Ok(());
+    };
+
+    let arg = amount_arg(env, &call.fn_name)
+        .and_then(|index| call.args.get(index))
+        .ok_or(AccountError::SpendLimitExceeded)?;
+    let amount = if call.fn_name == Symbol::new(env, "create_trade") {
+        // The order value is the total price on the purchase order details
+        Map::<Symbol, Val>::try_from_val(env, &arg)
+            .ok()
+            .and_then(|details| details.get(Symbol::new(env, "total_price")))
+            .and_then(|total| i128::try_from_val(env, &total).ok())
+    } else {
+        i128::try_from_val(env, &arg).ok()
+    };
+
+    match amount {
+        Some(amount) if amount <= limit => Ok(()),
+        _ => Err(AccountError::SpendLimitExceeded),
+    }
+}
+
+/// Amount a token call moves out of the account, if it is a transfer or approval from it
+fn spend_amount(env: &Env, call: &ContractContext) -> Result<Option<i128>, AccountError> {
+    let spends = ["transfer", "approve"];
+    if !spends.iter().any(|name| call.fn_name == Symbol::new(env, name)) {
+        return Ok(None);
+    }
+    let from = call.args.get(0).and_then(|from| Address::try_from_val(env, &from).ok());
+    if from != Some(env.current_contract_address()) {
+        return Ok(None);
+    }
+
+    // Both take (from, to or spender, amount, ...)
+    call.args
+        .get(2)
+        .and_then(|amount| i128::try_from_val(env, &amount).ok())
+        .filter(|amount| *amount >= 0)
+        .map(Some)
+        .ok_or(AccountError::InvalidAmount)
+}
+
+/// Spending window for a token, restarted once its period has run out
+fn current_window(env: &Env, token: &Address, limit: &TokenLimit) -> SpendWindow {
+    let now = env.ledger().timestamp();
+    let window: Option<SpendWindow> = env.storage().instance().get(&DataKey::Spent(token.clone()));
+    match window {
+        Some(window) if now < window.started_at.saturating_add(limit.period) => window,
+        _ => SpendWindow { started_at: now, spent: 0 },
+    }
+}
+
+/// Count a token call against its token limit, failing once the period's limit is passed
+fn record_spend(env: &Env, call: &ContractContext) -> Result<(), AccountError> {
+    let Some(limit) = CorporateAccount::get_token_limit(env, call.contract.clone()) else {
+        return Ok(());
+    };
+    let Some(amount) = spend_amount(env, call)? else {
+        return Ok(());
+    };
+
+    let mut window = current_window(env, &call.contract, &limit);
+    window.spent = amount
+        .checked_add(window.spent)
+        .filter(|spent| *spent <= limit.limit)
+        .ok_or(AccountError::SpendLimitExceeded)?;
+    env.storage().instance().set(&DataKey::Spent(call.contract.clone()), &window);
+    Ok(())
+}
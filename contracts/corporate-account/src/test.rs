@@ -0,0 +1,451 @@
+#![cfg(test)]
+
+extern crate std;
+
+use ed25519_dalek::{Signer as _, SigningKey};
+use marketplace_escrow_v1::{
+    BrokerCommission, DeliveryTerms, Incoterm, MarketplaceEscrowV1, MarketplaceEscrowV1Client,
+    ProductId, PurchaseOrderDetails,
+};
+use soroban_sdk::{
+    auth::{Context, ContractContext},
+    testutils::{Address as _, Ledger as _},
+    token::StellarAssetClient,
+    xdr::{
+        Hash, HashIdPreimage, HashIdPreimageSorobanAuthorization, InvokeContractArgs, Limits,
+        ScVal, ScVec, SorobanAddressCredentials, SorobanAuthorizationEntry,
+        SorobanAuthorizedFunction, SorobanAuthorizedInvocation, SorobanCredentials, WriteXdr,
+    },
+    Address, Bytes, BytesN, Env, IntoVal, String, Symbol, TryFromVal, Val, Vec,
+};
+
+use crate::contract::{AccountError, CorporateAccount, CorporateAccountClient, Signature, Signer};
+
+fn signing_key(seed: u8) -> SigningKey {
+    SigningKey::from_bytes(&[seed; 32])
+}
+
+fn public_key(e: &Env, key: &SigningKey) -> BytesN<32> {
+    BytesN::from_array(e, &key.verifying_key().to_bytes())
+}
+
+/// Register an account whose signers are `keys` with the matching `weights`
+fn create_account<'a>(
+    e: &Env,
+    keys: &[&SigningKey],
+    weights: &[u32],
+    threshold: u32,
+) -> CorporateAccountClient<'a> {
+    let mut signers = Vec::new(e);
+    for (key, weight) in keys.iter().zip(weights) {
+        signers.push_back(Signer { public_key: public_key(e, key), weight: *weight });
+    }
+    let address = e.register(CorporateAccount, (signers, threshold));
+    CorporateAccountClient::new(e, &address)
+}
+
+/// Signatures by `keys` over `payload`, in the public key order the account expects
+fn sign(e: &Env, keys: &[&SigningKey], payload: &BytesN<32>) -> Vec<Signature> {
+    let mut keys = std::vec::Vec::from(keys);
+    keys.sort_by_key(|key| key.verifying_key().to_bytes());
+
+    let mut signatures = Vec::new(e);
+    for key in keys {
+        signatures.push_back(Signature {
+            public_key: public_key(e, key),
+            signature: BytesN::from_array(e, &key.sign(&payload.to_array()).to_bytes()),
+        });
+    }
+    signatures
+}
+
+fn check_auth(
+    e: &Env,
+    account: &Address,
+    keys: &[&SigningKey],
+    contexts: &Vec<Context>,
+) -> Result<(), Result<AccountError, soroban_sdk::InvokeError>> {
+    let payload = BytesN::from_array(e, &[7; 32]);
+    let signatures = sign(e, keys, &payload);
+    e.try_invoke_contract_check_auth::<AccountError>(
+        account,
+        &payload,
+        signatures.into_val(e),
+        contexts,
+    )
+}
+
+/// Authorization context of a call to `fn_name` on `contract`
+fn call(e: &Env, contract: &Address, fn_name: &str, args: Vec<Val>) -> Context {
+    Context::Contract(ContractContext {
+        contract: contract.clone(),
+        fn_name: Symbol::new(e, fn_name),
+        args,
+    })
+}
+
+#[test]
+fn signatures_must_carry_threshold_weight() {
+    let e = Env::default();
+    let (cfo, controller, clerk) = (signing_key(1), signing_key(2), signing_key(3));
+    let account = create_account(&e, &[&cfo, &controller, &clerk], &[2, 1, 1], 3);
+    let none = Vec::new(&e);
+
+    assert_eq!(
+        check_auth(&e, &account.address, &[&cfo], &none),
+        Err(Ok(AccountError::InsufficientWeight))
+    );
+    assert_eq!(check_auth(&e, &account.address, &[&cfo, &clerk], &none), Ok(()));
+    assert_eq!(
+        check_auth(&e, &account.address, &[&cfo, &signing_key(9)], &none),
+        Err(Ok(AccountError::UnknownSigner))
+    );
+
+    // The same signer twice is not counted twice
+    let payload = BytesN::from_array(&e, &[7; 32]);
+    let mut twice = sign(&e, &[&cfo], &payload);
+    twice.append(&twice.clone());
+    assert_eq!(
+        e.try_invoke_contract_check_auth::<AccountError>(
+            &account.address,
+            &payload,
+            twice.into_val(&e),
+            &none,
+        ),
+        Err(Ok(AccountError::SignaturesNotSorted))
+    );
+
+    // Rotating a key keeps its weight and retires the old key
+    e.mock_all_auths();
+    let new_cfo = signing_key(4);
+    account.rotate_signer(&public_key(&e, &cfo), &public_key(&e, &new_cfo));
+    assert_eq!(account.get_signers().get(public_key(&e, &new_cfo)), Some(2));
+    assert_eq!(
+        check_auth(&e, &account.address, &[&cfo, &clerk], &none),
+        Err(Ok(AccountError::UnknownSigner))
+    );
+    assert_eq!(check_auth(&e, &account.address, &[&new_cfo, &clerk], &none), Ok(()));
+
+    // Signers can never be left unable to reach the threshold
+    assert_eq!(
+        account.try_remove_signer(&public_key(&e, &new_cfo)),
+        Err(Ok(AccountError::InvalidThreshold))
+    );
+    assert_eq!(account.try_set_threshold(&5), Err(Ok(AccountError::InvalidThreshold)));
+}
+
+#[test]
+fn spend_limits_cap_marketplace_calls() {
+    let e = Env::default();
+    let (cfo, controller) = (signing_key(1), signing_key(2));
+    let account = create_account(&e, &[&cfo, &controller], &[1, 1], 2);
+    let marketplace = Address::generate(&e);
+    let keys = [&cfo, &controller];
+
+    e.mock_all_auths();
+    account.set_spend_limit(&marketplace, &Symbol::new(&e, "fund_escrow"), &10_000);
+    assert_eq!(
+        account.try_set_spend_limit(&marketplace, &Symbol::new(&e, "accept_trade"), &10_000),
+        Err(Ok(AccountError::UnsupportedFunction))
+    );
+
+    let fund = |amount: i128| {
+        let args = (account.address.clone(), 1u64, amount).into_val(&e);
+        Vec::from_array(&e, [call(&e, &marketplace, "fund_escrow", args)])
+    };
+    assert_eq!(check_auth(&e, &account.address, &keys, &fund(10_000)), Ok(()));
+    assert_eq!(
+        check_auth(&e, &account.address, &keys, &fund(10_001)),
+        Err(Ok(AccountError::SpendLimitExceeded))
+    );
+
+    // A change order is capped on the most the account agrees to pay for it
+    account.set_spend_limit(&marketplace, &Symbol::new(&e, "accept_change_order"), &2_000);
+    let accept = |max_payment: i128| {
+        let args = (account.address.clone(), 1u64, 1u32, max_payment).into_val(&e);
+        Vec::from_array(&e, [call(&e, &marketplace, "accept_change_order", args)])
+    };
+    assert_eq!(check_auth(&e, &account.address, &keys, &accept(2_000)), Ok(()));
+    assert_eq!(
+        check_auth(&e, &account.address, &keys, &accept(2_001)),
+        Err(Ok(AccountError::SpendLimitExceeded))
+    );
+
+    // Limits apply per marketplace contract
+    let other = Address::generate(&e);
+    let args = (account.address.clone(), 1u64, 10_001i128).into_val(&e);
+    let elsewhere = Vec::from_array(&e, [call(&e, &other, "fund_escrow", args)]);
+    assert_eq!(check_auth(&e, &account.address, &keys, &elsewhere), Ok(()));
+
+    account.remove_spend_limit(&marketplace, &Symbol::new(&e, "fund_escrow"));
+    assert_eq!(account.get_spend_limit(&marketplace, &Symbol::new(&e, "fund_escrow")), None);
+    assert_eq!(check_auth(&e, &account.address, &keys, &fund(10_001)), Ok(()));
+}
+
+#[test]
+fn token_limits_cap_transfers() {
+    let e = Env::default();
+    let (cfo, controller) = (signing_key(1), signing_key(2));
+    let account = create_account(&e, &[&cfo, &controller], &[1, 1], 2);
+    let marketplace = Address::generate(&e);
+    let token = Address::generate(&e);
+    let keys = [&cfo, &controller];
+
+    e.mock_all_auths();
+    assert_eq!(
+        account.try_set_token_limit(&token, &10_000, &0),
+        Err(Ok(AccountError::InvalidPeriod))
+    );
+    account.set_token_limit(&token, &10_000, &86400);
+
+    // Whatever marketplace entrypoint pulls the funds, the transfer counts
+    let pay = |fn_name: &str, amount: i128| {
+        let args = (account.address.clone(), 1u64).into_val(&e);
+        let transfer = (account.address.clone(), marketplace.clone(), amount).into_val(&e);
+        Vec::from_array(
+            &e,
+            [call(&e, &marketplace, fn_name, args), call(&e, &token, "transfer", transfer)],
+        )
+    };
+    assert_eq!(check_auth(&e, &account.address, &keys, &pay("fund_escrow", 6_000)), Ok(()));
+    assert_eq!(account.get_spent(&token), 6_000);
+    assert_eq!(
+        check_auth(&e, &account.address, &keys, &pay("pay_deferred", 4_001)),
+        Err(Ok(AccountError::SpendLimitExceeded))
+    );
+    assert_eq!(
+        check_auth(&e, &account.address, &keys, &pay("accept_change_order", 4_001)),
+        Err(Ok(AccountError::SpendLimitExceeded))
+    );
+
+    // Approving a spender counts the same as a transfer
+    let args = (account.address.clone(), marketplace.clone(), 4_001i128, 1000u32).into_val(&e);
+    let approve = Vec::from_array(&e, [call(&e, &token, "approve", args)]);
+    assert_eq!(
+        check_auth(&e, &account.address, &keys, &approve),
+        Err(Ok(AccountError::SpendLimitExceeded))
+    );
+
+    // Other tokens are unlimited
+    let other = Address::generate(&e);
+    let args = (account.address.clone(), marketplace.clone(), 20_000i128).into_val(&e);
+    let elsewhere = Vec::from_array(&e, [call(&e, &other, "transfer", args)]);
+    assert_eq!(check_auth(&e, &account.address, &keys, &elsewhere), Ok(()));
+
+    // The limit renews each period
+    e.ledger().set_timestamp(86400);
+    assert_eq!(account.get_spent(&token), 0);
+    assert_eq!(check_auth(&e, &account.address, &keys, &pay("pay_deferred", 10_000)), Ok(()));
+
+    account.remove_token_limit(&token);
+    assert_eq!(account.get_token_limit(&token), None);
+    assert_eq!(check_auth(&e, &account.address, &keys, &pay("fund_escrow", 10_001)), Ok(()));
+}
+
+/// Authorized invocation of `fn_name` on `contract`
+fn invocation(
+    contract: &Address,
+    fn_name: &str,
+    args: Vec<Val>,
+    sub_invocations: std::vec::Vec<SorobanAuthorizedInvocation>,
+) -> SorobanAuthorizedInvocation {
+    SorobanAuthorizedInvocation {
+        function: SorobanAuthorizedFunction::ContractFn(InvokeContractArgs {
+            contract_address: contract.into(),
+            function_name: fn_name.try_into().unwrap(),
+            args: ScVec::from(&args).0,
+        }),
+        sub_invocations: sub_invocations.try_into().unwrap(),
+    }
+}
+
+/// Auth entry for `invocation` signed by the account's `keys`
+fn authorize(
+    e: &Env,
+    account: &Address,
+    keys: &[&SigningKey],
+    nonce: i64,
+    invocation: SorobanAuthorizedInvocation,
+) -> SorobanAuthorizationEntry {
+    let signature_expiration_ledger = e.ledger().sequence() + 100;
+    let preimage = HashIdPreimage::SorobanAuthorization(HashIdPreimageSorobanAuthorization {
+        network_id: Hash(e.ledger().network_id().to_array()),
+        nonce,
+        signature_expiration_ledger,
+        invocation: invocation.clone(),
+    });
+    let preimage = preimage.to_xdr(Limits::none()).unwrap();
+    let payload = e.crypto().sha256(&Bytes::from_slice(e, &preimage)).into();
+    let signatures = sign(e, keys, &payload);
+
+    SorobanAuthorizationEntry {
+        credentials: SorobanCredentials::Address(SorobanAddressCredentials {
+            address: account.into(),
+            nonce,
+            signature_expiration_ledger,
+            signature: ScVal::try_from_val(e, &signatures.to_val()).unwrap(),
+        }),
+        root_invocation: invocation,
+    }
+}
+
+#[test]
+fn account_creates_and_funds_trades_as_buyer() {
+    let e = Env::default();
+    let (cfo, controller) = (signing_key(1), signing_key(2));
+    let account = create_account(&e, &[&cfo, &controller], &[1, 1], 2);
+    let keys = [&cfo, &controller];
+    let seller = Address::generate(&e);
+
+    // Marketplace setup by its owner, with the account registered as a buyer
+    e.mock_all_auths();
+    let treasury = Address::generate(&e);
+    let marketplace_id = e.register(MarketplaceEscrowV1, (&treasury, 25u32));
+    let marketplace = MarketplaceEscrowV1Client::new(&e, &marketplace_id);
+    let token = e.register_stellar_asset_contract_v2(Address::generate(&e)).address();
+    StellarAssetClient::new(&e, &token).mint(&account.address, &100000_0000000);
+    marketplace.set_payment_token(&token);
+    marketplace.register_buyer(
+        &account.address,
+        &String::from_str(&e, "Tommy Hilfiger"),
+        &String::from_str(&e, "549300VGEJK8QMIYGZ34"),
+    );
+    marketplace.register_seller(
+        &seller,
+        &String::from_str(&e, "Jupiter Knitting"),
+        &String::from_str(&e, "213800ABCDEF1234XYZ"),
+    );
+    account.set_spend_limit(&marketplace_id, &Symbol::new(&e, "create_trade"), &20000_0000000);
+    account.set_token_limit(&token, &16000_0000000, &(30 * 86400));
+
+    let now = e.ledger().timestamp();
+    let details = PurchaseOrderDetails {
+        po_description: String::from_str(&e, "Cotton T-shirts"),
+        product: ProductId {
+            gtin: Some(String::from_str(&e, "00012345600012")),
+            hs_code: Some(String::from_str(&e, "610910")),
+            sku: None,
+        },
+        quantity: 1000,
+        unit_price: 15_0000000,
+        total_price: 15000_0000000,
+        po_json_ipfs_hash: String::from_str(&e, "QmYwAPJzv5CZsnA625s3Xf2nemtYgPpHdWEz79ojWnPbdG"),
+        po_json_hash: BytesN::from_array(&e, &[1; 32]),
+        delivery: DeliveryTerms {
+            incoterm: Incoterm::Dap,
+            named_place: String::from_str(&e, "Port of Rotterdam"),
+            window_start: now,
+            window_end: now + 60 * 86400,
+        },
+        requires_inspection: false,
+        inspection_agency: None,
+        payment_schedule: Vec::new(&e),
+        performance_bond_rate: 0,
+        payment_terms_days: 0,
+        early_payment_discount_bps: 0,
+        discount_window_days: 0,
+        late_penalty_bps_per_day: 0,
+        requires_title_receipt: false,
+    };
+    let buyer_lei_ipfs = String::from_str(&e, "QmBuyerLEI");
+    let seller_lei_ipfs = String::from_str(&e, "QmSellerLEI");
+
+    // From here on the account authorizes with its officers' signatures
+    let create_args = (
+        account.address.clone(),
+        seller.clone(),
+        details.clone(),
+        buyer_lei_ipfs.clone(),
+        seller_lei_ipfs.clone(),
+        None::<BrokerCommission>,
+    )
+        .into_val(&e);
+    let create = invocation(&marketplace_id, "create_trade", create_args, std::vec![]);
+    e.set_auths(&[authorize(&e, &account.address, &keys, 1, create)]);
+    let trade_id = marketplace.create_trade(
+        &account.address,
+        &seller,
+        &details,
+        &buyer_lei_ipfs,
+        &seller_lei_ipfs,
+        &None,
+    );
+    assert_eq!(marketplace.get_trade(&trade_id).buyer, account.address);
+
+    let cost = marketplace.calculate_escrow_cost(&account.address, &seller, &details.total_price);
+    let transfer_args =
+        (account.address.clone(), marketplace_id.clone(), cost.total_required).into_val(&e);
+    let transfer = invocation(&token, "transfer", transfer_args, std::vec![]);
+    let fund_args = (account.address.clone(), trade_id, cost.total_required).into_val(&e);
+    let fund = invocation(&marketplace_id, "fund_escrow", fund_args, std::vec![transfer]);
+    e.set_auths(&[authorize(&e, &account.address, &keys, 2, fund)]);
+    marketplace.fund_escrow(&account.address, &trade_id, &cost.total_required);
+    assert_eq!(marketplace.get_trade(&trade_id).escrow_balance, cost.total_required);
+
+    // The seller asks for more, and the shortfall would take the account
+    // past its monthly limit however many officers sign
+    e.mock_all_auths();
+    let mut larger = details.clone();
+    larger.quantity = 1200;
+    larger.total_price = 18000_0000000;
+    marketplace.propose_change_order(&seller, &trade_id, &larger);
+    let repriced = marketplace.calculate_escrow_cost(&account.address, &seller, &18000_0000000);
+    let shortfall = repriced.total_required - cost.total_required;
+
+    let transfer_args = (account.address.clone(), marketplace_id.clone(), shortfall).into_val(&e);
+    let transfer = invocation(&token, "transfer", transfer_args, std::vec![]);
+    let accept_args = (account.address.clone(), trade_id, 1u32, shortfall).into_val(&e);
+    let accept =
+        invocation(&marketplace_id, "accept_change_order", accept_args, std::vec![transfer]);
+    e.set_auths(&[authorize(&e, &account.address, &keys, 3, accept.clone())]);
+    let accept_larger =
+        || marketplace.try_accept_change_order(&account.address, &trade_id, &1, &shortfall);
+    assert!(accept_larger().is_err());
+    assert_eq!(account.get_spent(&token), cost.total_required);
+
+    // Lifting the limit takes the officers' signatures too
+    let lift_args = (token.clone(),).into_val(&e);
+    let lift = invocation(&account.address, "remove_token_limit", lift_args, std::vec![]);
+    e.set_auths(&[authorize(&e, &account.address, &keys, 4, lift)]);
+    account.remove_token_limit(&token);
+
+    e.set_auths(&[authorize(&e, &account.address, &keys, 5, accept)]);
+    assert!(accept_larger().is_ok());
+    assert_eq!(marketplace.get_trade(&trade_id).escrow_balance, repriced.total_required);
+
+    // An order over the account's per-order limit is refused even with every signature
+    let mut large = details.clone();
+    large.quantity = 2000;
+    large.total_price = 30000_0000000;
+    let create_args = (
+        account.address.clone(),
+        seller.clone(),
+        large.clone(),
+        buyer_lei_ipfs.clone(),
+        seller_lei_ipfs.clone(),
+        None::<BrokerCommission>,
+    )
+        .into_val(&e);
+    let create = invocation(&marketplace_id, "create_trade", create_args, std::vec![]);
+    e.set_auths(&[authorize(&e, &account.address, &keys, 6, create.clone())]);
+    let create_large = || {
+        marketplace.try_create_trade(
+            &account.address,
+            &seller,
+            &large,
+            &buyer_lei_ipfs,
+            &seller_lei_ipfs,
+            &None,
+        )
+    };
+    assert!(create_large().is_err());
+
+    let fn_name = Symbol::new(&e, "create_trade");
+    let lift_args = (marketplace_id.clone(), fn_name.clone()).into_val(&e);
+    let lift = invocation(&account.address, "remove_spend_limit", lift_args, std::vec![]);
+    e.set_auths(&[authorize(&e, &account.address, &keys, 7, lift)]);
+    account.remove_spend_limit(&marketplace_id, &fn_name);
+
+    e.set_auths(&[authorize(&e, &account.address, &keys, 8, create)]);
+    assert!(create_large().is_ok());
+}
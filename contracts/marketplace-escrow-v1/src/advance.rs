@@ -0,0 +1,196 @@
+//! Seller working-capital advances and the debts left by unwound trades
+
+use soroban_sdk::{Address, Env, Vec};
+
+use crate::approval;
+use crate::errors::ContractError;
+use crate::settlement;
+use crate::storage::DataKey;
+use crate::trade;
+use crate::types::{ApprovalAction, PurchaseOrder, SellerDebt, TradeEscrow, TradeState};
+
+/// Largest share of a trade's amount the owner may allow as an advance (100%)
+pub const MAX_ADVANCE_RATE: u32 = 10000;
+
+/// Get the largest advance allowed, in basis points of the trade amount
+///
+/// Defaults to 0, so advances are off until the owner configures a rate.
+pub fn get_max_advance_rate(env: &Env) -> u32 {
+    env.storage()
+        .instance()
+        .get(&DataKey::MaxAdvanceRate)
+        .unwrap_or(0)
+}
+
+/// Pay part of a funded trade's escrow to the seller ahead of production
+///
+/// Advances may be authorized in several steps while the trade is open, up
+/// to the configured share of the amount and never more than the seller's
+/// net payout. The total advanced is deducted at settlement. Trades with a
+/// payment schedule already release funds in stages and take no advances.
+/// Over the buyer's approval threshold the advance is held until its
+/// approvers sign off.
+pub fn authorize_advance(
+    env: &Env,
+    buyer: &Address,
+    trade_id: u64,
+    amount: i128,
+) -> Result<(), ContractError> {
+    let mut trade: TradeEscrow = env
+        .storage()
+        .instance()
+        .get(&DataKey::Trade(trade_id))
+        .ok_or(ContractError::TradeNotFound)?;
+
+    // Verify caller is buyer
+    if &trade.buyer != buyer {
+        return Err(ContractError::NotBuyer);
+    }
+
+    // Working capital is only useful before the goods are delivered
+    if trade.state != TradeState::Ordered {
+        return Err(ContractError::TradeNotOrdered);
+    }
+    if trade.escrow_balance == 0 {
+        return Err(ContractError::EscrowNotFunded);
+    }
+    if trade.escrow_balance < trade::required_escrow(&trade) {
+        return Err(ContractError::InsufficientEscrowFunding);
+    }
+    approval::require_none_pending(env, trade_id)?;
+
+    let po: PurchaseOrder = env
+        .storage()
+        .instance()
+        .get(&DataKey::PurchaseOrder(trade_id))
+        .ok_or(ContractError::PurchaseOrderNotFound)?;
+    if !po.payment_schedule.is_empty() {
+        return Err(ContractError::AdvanceNotAllowed);
+    }
+
+    if amount <= 0 {
+        return Err(ContractError::InvalidAmount);
+    }
+
+    if approval::requires_approval(env, &trade) {
+        // Approvers only see advances that can still be paid
+        let advance_paid = trade
+            .advance_paid
+            .checked_add(amount)
+            .ok_or(ContractError::OverflowError)?;
+        check_advance_limit(
+            env,
+            &TradeEscrow {
+                advance_paid,
+                ..trade
+            },
+        )?;
+        return approval::hold(env, trade_id, ApprovalAction::AuthorizeAdvance(amount));
+    }
+
+    pay_advance(env, &mut trade, amount)?;
+
+    env.storage()
+        .instance()
+        .set(&DataKey::Trade(trade_id), &trade);
+
+    Ok(())
+}
+
+/// Pay an authorized advance out of a trade's escrow to the seller
+pub fn pay_advance(env: &Env, trade: &mut TradeEscrow, amount: i128) -> Result<(), ContractError> {
+    trade.advance_paid = trade
+        .advance_paid
+        .checked_add(amount)
+        .ok_or(ContractError::OverflowError)?;
+    check_advance_limit(env, trade)?;
+
+    pay_seller(env, trade, amount)?;
+
+    trade.escrow_balance -= amount;
+    trade.updated_at = env.ledger().timestamp();
+
+    Ok(())
+}
+
+/// Fail if a trade's advance exceeds the configured rate or the seller's net payout
+///
+/// Rechecked when a change order reprices the trade.
+pub fn check_advance_limit(env: &Env, trade: &TradeEscrow) -> Result<(), ContractError> {
+    if trade.advance_paid == 0 {
+        return Ok(());
+    }
+
+    let limit = trade
+        .amount
+        .checked_mul(get_max_advance_rate(env) as i128)
+        .ok_or(ContractError::OverflowError)?
+        / 10000;
+    let net = trade.amount - trade.seller_fee - trade.broker_commission;
+    if trade.advance_paid > limit || trade.advance_paid > net {
+        return Err(ContractError::AdvanceLimitExceeded);
+    }
+
+    Ok(())
+}
+
+/// Pay the seller of a trade, settling their outstanding debts first
+///
+/// Debts in the trade's payment token are repaid oldest first to the buyers
+/// who funded them, and only the remainder reaches the seller.
+pub fn pay_seller(env: &Env, trade: &TradeEscrow, amount: i128) -> Result<(), ContractError> {
+    let mut remaining = amount;
+    let mut debts = get_seller_debts(env, &trade.seller);
+
+    if !debts.is_empty() && remaining > 0 {
+        let mut unpaid = Vec::new(env);
+        for mut debt in debts.iter() {
+            if remaining > 0 && debt.payment_token == trade.payment_token {
+                let repaid = debt.outstanding.min(remaining);
+                settlement::disburse(env, trade, &debt.creditor, repaid)?;
+                debt.outstanding -= repaid;
+                remaining -= repaid;
+            }
+            if debt.outstanding > 0 {
+                unpaid.push_back(debt);
+            }
+        }
+        debts = unpaid;
+
+        env.storage()
+            .instance()
+            .set(&DataKey::SellerDebts(trade.seller.clone()), &debts);
+    }
+
+    settlement::disburse(env, trade, &trade.seller, remaining)
+}
+
+/// Record what the seller of an unwound trade was already paid as their debt
+///
+/// Covers the advance and any milestones released, since the buyer's refund
+/// is only what is left in escrow.
+pub fn record_debt(env: &Env, trade: &TradeEscrow) {
+    let outstanding = trade.advance_paid + trade.released_to_seller;
+    if outstanding <= 0 {
+        return;
+    }
+
+    let mut debts = get_seller_debts(env, &trade.seller);
+    debts.push_back(SellerDebt {
+        trade_id: trade.trade_id,
+        creditor: trade.buyer.clone(),
+        payment_token: trade.payment_token.clone(),
+        outstanding,
+    });
+    env.storage()
+        .instance()
+        .set(&DataKey::SellerDebts(trade.seller.clone()), &debts);
+}
+
+/// Get a seller's outstanding debts from unwound trades, oldest first
+pub fn get_seller_debts(env: &Env, seller: &Address) -> Vec<SellerDebt> {
+    env.storage()
+        .instance()
+        .get(&DataKey::SellerDebts(seller.clone()))
+        .unwrap_or(Vec::new(env))
+}
@@ -0,0 +1,244 @@
+//! Multi-signature approval of high-value trades
+//!
+//! A buyer may register an approval policy naming the officers who sign off
+//! on its trades above a threshold amount, and how many of them must. Once
+//! a trade is over the threshold, creating it, funding or topping up its
+//! escrow, accepting a change order, paying the seller an advance or a
+//! milestone, and accepting it are each held pending until enough officers
+//! approve, and the last approval carries the action out. A change order
+//! that takes a trade over the threshold is held the same way.
+
+use soroban_sdk::{Address, Env, Vec};
+
+use crate::advance;
+use crate::change_order;
+use crate::errors::ContractError;
+use crate::matching;
+use crate::milestones;
+use crate::registry;
+use crate::settlement;
+use crate::storage::DataKey;
+use crate::trade;
+use crate::types::{ApprovalAction, ApprovalPolicy, PendingApproval, PurchaseOrder, TradeEscrow};
+
+/// Most approvers a policy may name
+pub const MAX_APPROVERS: u32 = 10;
+
+/// Get a participant's approval policy, if one is registered
+pub fn get_approval_policy(env: &Env, participant: &Address) -> Option<ApprovalPolicy> {
+    env.storage()
+        .instance()
+        .get(&DataKey::ApprovalPolicy(participant.clone()))
+}
+
+/// Register or replace a buyer's approval policy
+///
+/// The policy names 1 to `MAX_APPROVERS` distinct approvers and needs
+/// between one and all of them. Replacing a policy is itself a high-value
+/// action: `cosigners` must include enough approvers of the current policy,
+/// each of whom authorizes the change.
+pub fn set_approval_policy(
+    env: &Env,
+    participant: &Address,
+    policy: ApprovalPolicy,
+    cosigners: Vec<Address>,
+) -> Result<(), ContractError> {
+    registry::get_buyer_info(env, participant)?;
+    check_policy(&policy)?;
+
+    if let Some(current) = get_approval_policy(env, participant) {
+        let mut signed: Vec<Address> = Vec::new(env);
+        for cosigner in cosigners.iter() {
+            if !current.approvers.contains(&cosigner) {
+                return Err(ContractError::NotApprover);
+            }
+            if signed.contains(&cosigner) {
+                return Err(ContractError::ApprovalAlreadyGiven);
+            }
+            cosigner.require_auth();
+            signed.push_back(cosigner);
+        }
+        if signed.len() < current.required {
+            return Err(ContractError::InsufficientApprovals);
+        }
+    }
+
+    env.storage()
+        .instance()
+        .set(&DataKey::ApprovalPolicy(participant.clone()), &policy);
+
+    Ok(())
+}
+
+/// Whether a trade's buyer needs its approvers to sign off on the trade
+pub fn requires_approval(env: &Env, trade: &TradeEscrow) -> bool {
+    get_approval_policy(env, &trade.buyer).is_some_and(|policy| trade.amount > policy.threshold)
+}
+
+/// Get the action a trade is waiting on approval for, if any
+pub fn get_pending_approval(env: &Env, trade_id: u64) -> Option<PendingApproval> {
+    env.storage()
+        .instance()
+        .get(&DataKey::PendingApproval(trade_id))
+}
+
+/// Fail if a trade has an action waiting on approval
+pub fn require_none_pending(env: &Env, trade_id: u64) -> Result<(), ContractError> {
+    if get_pending_approval(env, trade_id).is_some() {
+        return Err(ContractError::ApprovalPending);
+    }
+
+    Ok(())
+}
+
+/// Hold a buyer action on a trade until its approvers sign off
+pub fn hold(env: &Env, trade_id: u64, action: ApprovalAction) -> Result<(), ContractError> {
+    require_none_pending(env, trade_id)?;
+
+    let pending = PendingApproval {
+        action,
+        approvals: Vec::new(env),
+        requested_at: env.ledger().timestamp(),
+    };
+    env.storage()
+        .instance()
+        .set(&DataKey::PendingApproval(trade_id), &pending);
+
+    Ok(())
+}
+
+/// Sign off on a trade's pending action as one of its buyer's approvers
+///
+/// The approval that brings the count up to the policy's requirement
+/// carries the action out: a held trade opens, parked funds are credited to
+/// escrow, a change order takes effect, the seller is paid its advance or
+/// milestone, or the trade settles through the usual DvP check. Returns
+/// whether the action was carried out.
+pub fn approve(
+    env: &Env,
+    approver: &Address,
+    trade_id: u64,
+    platform_treasury: &Address,
+    marketplace_fee_rate: u32,
+) -> Result<bool, ContractError> {
+    let mut trade: TradeEscrow = env
+        .storage()
+        .instance()
+        .get(&DataKey::Trade(trade_id))
+        .ok_or(ContractError::TradeNotFound)?;
+    let mut pending =
+        get_pending_approval(env, trade_id).ok_or(ContractError::NoPendingApproval)?;
+    let policy = get_approval_policy(env, &trade.buyer).ok_or(ContractError::NotApprover)?;
+
+    if !policy.approvers.contains(approver) {
+        return Err(ContractError::NotApprover);
+    }
+    if pending.approvals.contains(approver) {
+        return Err(ContractError::ApprovalAlreadyGiven);
+    }
+    pending.approvals.push_back(approver.clone());
+
+    if pending.approvals.len() < policy.required {
+        env.storage()
+            .instance()
+            .set(&DataKey::PendingApproval(trade_id), &pending);
+        return Ok(false);
+    }
+
+    env.storage()
+        .instance()
+        .remove(&DataKey::PendingApproval(trade_id));
+
+    match pending.action {
+        ApprovalAction::CreateTrade => {}
+        ApprovalAction::FundEscrow(cost) => {
+            trade::credit_escrow(env, &mut trade, &cost);
+            env.storage()
+                .instance()
+                .set(&DataKey::Trade(trade_id), &trade);
+        }
+        ApprovalAction::AcceptTrade => {
+            matching::dvp_check(env, trade_id)?;
+            trade::settle(env, &mut trade, approver, platform_treasury)?;
+        }
+        ApprovalAction::AuthorizeAdvance(amount) => {
+            advance::pay_advance(env, &mut trade, amount)?;
+            env.storage()
+                .instance()
+                .set(&DataKey::Trade(trade_id), &trade);
+        }
+        ApprovalAction::ReleaseMilestone(index) => {
+            let po: PurchaseOrder = env
+                .storage()
+                .instance()
+                .get(&DataKey::PurchaseOrder(trade_id))
+                .ok_or(ContractError::PurchaseOrderNotFound)?;
+            let milestone = po
+                .payment_schedule
+                .get(index)
+                .ok_or(ContractError::InvalidTradeState)?;
+            milestones::pay_milestone(env, &mut trade, &po, &milestone)?;
+            env.storage()
+                .instance()
+                .set(&DataKey::Trade(trade_id), &trade);
+        }
+        ApprovalAction::TopUpEscrow(amount) => {
+            trade.escrow_balance += amount;
+            trade.updated_at = env.ledger().timestamp();
+            env.storage()
+                .instance()
+                .set(&DataKey::Trade(trade_id), &trade);
+        }
+        ApprovalAction::AcceptChangeOrder(proposal, parked) => {
+            change_order::apply_approved(env, &mut trade, proposal, parked, marketplace_fee_rate)?;
+        }
+    }
+
+    Ok(true)
+}
+
+/// Drop a trade's pending action as the trade unwinds
+///
+/// Funds parked by a held funding, top-up or change order go back to the
+/// buyer.
+pub fn cancel_pending(env: &Env, trade: &TradeEscrow) -> Result<(), ContractError> {
+    let Some(pending) = get_pending_approval(env, trade.trade_id) else {
+        return Ok(());
+    };
+
+    let parked = match pending.action {
+        ApprovalAction::FundEscrow(cost) => cost.total_required,
+        ApprovalAction::TopUpEscrow(amount) => amount,
+        ApprovalAction::AcceptChangeOrder(_, parked) => parked,
+        _ => 0,
+    };
+    if parked > 0 {
+        settlement::disburse(env, trade, &trade.buyer, parked)?;
+    }
+    env.storage()
+        .instance()
+        .remove(&DataKey::PendingApproval(trade.trade_id));
+
+    Ok(())
+}
+
+/// Check a policy's approvers are distinct and its requirement achievable
+fn check_policy(policy: &ApprovalPolicy) -> Result<(), ContractError> {
+    let approvers = &policy.approvers;
+    if approvers.is_empty()
+        || approvers.len() > MAX_APPROVERS
+        || policy.required == 0
+        || policy.required > approvers.len()
+        || policy.threshold < 0
+    {
+        return Err(ContractError::InvalidApprovalPolicy);
+    }
+
+    for (index, approver) in approvers.iter().enumerate() {
+        if approvers.first_index_of(&approver) != Some(index as u32) {
+            return Err(ContractError::InvalidApprovalPolicy);
+        }
+    }
+
+    Ok(())
+}
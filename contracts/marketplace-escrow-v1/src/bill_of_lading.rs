@@ -0,0 +1,124 @@
+//! Carrier-issued bills of lading used as shipment evidence
+
+use soroban_sdk::{Address, Env};
+
+use crate::delivery;
+use crate::documents;
+use crate::errors::ContractError;
+use crate::registry::is_carrier_active;
+use crate::storage::DataKey;
+use crate::types::{BillOfLading, BillOfLadingDetails, PurchaseOrder, TradeEscrow, TradeState};
+
+/// Designate the carrier that will issue a trade's bill of lading
+///
+/// The party contracting the main carriage nominates it: the seller under
+/// the C-rules, the buyer under the F-rules. The nomination can change
+/// until the bill of lading is submitted.
+pub fn nominate_carrier(
+    env: &Env,
+    caller: &Address,
+    trade_id: u64,
+    carrier: &Address,
+) -> Result<(), ContractError> {
+    is_carrier_active(env, carrier)?;
+
+    let (trade, po) = get_shipping_trade(env, trade_id)?;
+    if delivery::seller_books_carriage(po.delivery.incoterm) {
+        if caller != &trade.seller {
+            return Err(ContractError::NotSeller);
+        }
+    } else if caller != &trade.buyer {
+        return Err(ContractError::NotBuyer);
+    }
+
+    env.storage()
+        .instance()
+        .set(&DataKey::DesignatedCarrier(trade_id), carrier);
+
+    Ok(())
+}
+
+/// Get the carrier designated for a trade, if any
+pub fn get_designated_carrier(env: &Env, trade_id: u64) -> Option<Address> {
+    env.storage()
+        .instance()
+        .get(&DataKey::DesignatedCarrier(trade_id))
+}
+
+/// Record the bill of lading for a trade whose Incoterm requires shipment evidence
+///
+/// Only the registered, active carrier designated for the trade may submit
+/// it, once, any time before the trade settles.
+pub fn submit_bill_of_lading(
+    env: &Env,
+    carrier: &Address,
+    trade_id: u64,
+    details: BillOfLadingDetails,
+) -> Result<(), ContractError> {
+    is_carrier_active(env, carrier)?;
+    get_shipping_trade(env, trade_id)?;
+
+    if get_designated_carrier(env, trade_id).as_ref() != Some(carrier) {
+        return Err(ContractError::CarrierNotDesignated);
+    }
+
+    if details.quantity == 0 {
+        return Err(ContractError::InvalidAmount);
+    }
+    documents::check_document_reference(&details.bl_json_ipfs_hash, &details.bl_json_hash)?;
+
+    let bill = BillOfLading {
+        carrier: carrier.clone(),
+        vessel: details.vessel,
+        voyage: details.voyage,
+        port_of_loading: details.port_of_loading,
+        port_of_discharge: details.port_of_discharge,
+        quantity: details.quantity,
+        container_ids: details.container_ids,
+        bl_json_ipfs_hash: details.bl_json_ipfs_hash,
+        bl_json_hash: details.bl_json_hash,
+        issued_at: env.ledger().timestamp(),
+    };
+
+    env.storage()
+        .instance()
+        .set(&DataKey::BillOfLading(trade_id), &bill);
+
+    Ok(())
+}
+
+/// Load a trade and its PO while shipment evidence can still be recorded
+fn get_shipping_trade(
+    env: &Env,
+    trade_id: u64,
+) -> Result<(TradeEscrow, PurchaseOrder), ContractError> {
+    let trade: TradeEscrow = env
+        .storage()
+        .instance()
+        .get(&DataKey::Trade(trade_id))
+        .ok_or(ContractError::TradeNotFound)?;
+
+    // Goods ship between ordering and settlement
+    if trade.state != TradeState::Ordered && trade.state != TradeState::Fulfilled {
+        return Err(ContractError::InvalidTradeState);
+    }
+
+    let po: PurchaseOrder = env
+        .storage()
+        .instance()
+        .get(&DataKey::PurchaseOrder(trade_id))
+        .ok_or(ContractError::PurchaseOrderNotFound)?;
+    if !delivery::requires_shipment_evidence(po.delivery.incoterm) {
+        return Err(ContractError::BillOfLadingNotRequired);
+    }
+
+    if env
+        .storage()
+        .instance()
+        .has(&DataKey::BillOfLading(trade_id))
+    {
+        return Err(ContractError::BillOfLadingAlreadySubmitted);
+    }
+
+    Ok((trade, po))
+}
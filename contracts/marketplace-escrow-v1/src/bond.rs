@@ -0,0 +1,146 @@
+//! Seller performance bonds held against delivery
+
+use soroban_sdk::{Address, Env};
+
+use crate::errors::ContractError;
+use crate::settlement;
+use crate::state;
+use crate::storage::DataKey;
+use crate::trade;
+use crate::types::{PurchaseOrder, TradeEscrow, TradeState};
+
+/// Largest performance bond a PO may ask for (100% of the order value)
+pub const MAX_BOND_RATE: u32 = 10000;
+
+/// Check a PO's performance bond rate is a share of the order value
+pub fn check_bond_rate(bond_rate: u32) -> Result<(), ContractError> {
+    if bond_rate > MAX_BOND_RATE {
+        return Err(ContractError::InvalidBondRate);
+    }
+
+    Ok(())
+}
+
+/// Deposit the performance bond the PO asks of the seller
+///
+/// The bond is the PO's rate applied to the order value at the time of
+/// posting, and the PO's current delivery window end is recorded with it as
+/// the delivery date. The buyer must have covered the escrow first, so the
+/// seller never bonds an order that isn't paid for. Returns the amount
+/// posted.
+pub fn post_performance_bond(
+    env: &Env,
+    seller: &Address,
+    trade_id: u64,
+) -> Result<i128, ContractError> {
+    let mut trade: TradeEscrow = env
+        .storage()
+        .instance()
+        .get(&DataKey::Trade(trade_id))
+        .ok_or(ContractError::TradeNotFound)?;
+
+    // Verify caller is seller
+    if &trade.seller != seller {
+        return Err(ContractError::NotSeller);
+    }
+
+    // The bond secures fulfillment, so it is posted before it
+    state::require_state(&trade, TradeState::Ordered)?;
+
+    if trade.bond_balance > 0 {
+        return Err(ContractError::PerformanceBondAlreadyPosted);
+    }
+
+    let po: PurchaseOrder = env
+        .storage()
+        .instance()
+        .get(&DataKey::PurchaseOrder(trade_id))
+        .ok_or(ContractError::PurchaseOrderNotFound)?;
+    if po.performance_bond_rate == 0 {
+        return Err(ContractError::PerformanceBondNotRequired);
+    }
+    trade::require_covered_escrow(&trade)?;
+
+    let bond = trade
+        .amount
+        .checked_mul(po.performance_bond_rate as i128)
+        .ok_or(ContractError::OverflowError)?
+        / 10000;
+    if bond <= 0 {
+        return Err(ContractError::InvalidAmount);
+    }
+
+    settlement::collect_from(env, &trade, seller, bond)?;
+
+    trade.bond_balance = bond;
+    trade.bond_deadline = po.delivery.window_end;
+    trade.updated_at = env.ledger().timestamp();
+
+    env.storage()
+        .instance()
+        .set(&DataKey::Trade(trade_id), &trade);
+
+    Ok(bond)
+}
+
+/// Forfeit the bond of a seller who missed the delivery date
+///
+/// Once the recorded delivery date has passed without fulfillment, the
+/// buyer takes the bond and the trade is cancelled with the escrow
+/// refunded. A buyer whose escrow no longer covers the order can't claim,
+/// since the seller couldn't have fulfilled it. Returns the amount
+/// forfeited.
+pub fn claim_performance_bond(
+    env: &Env,
+    buyer: &Address,
+    trade_id: u64,
+) -> Result<i128, ContractError> {
+    let mut trade: TradeEscrow = env
+        .storage()
+        .instance()
+        .get(&DataKey::Trade(trade_id))
+        .ok_or(ContractError::TradeNotFound)?;
+
+    // Verify caller is buyer
+    if &trade.buyer != buyer {
+        return Err(ContractError::NotBuyer);
+    }
+
+    // A fulfilled trade met its delivery date
+    state::require_state(&trade, TradeState::Ordered)?;
+
+    if trade.bond_balance == 0 {
+        return Err(ContractError::PerformanceBondNotPosted);
+    }
+    if env.ledger().timestamp() <= trade.bond_deadline {
+        return Err(ContractError::DeliveryDeadlineNotPassed);
+    }
+    trade::require_covered_escrow(&trade)?;
+
+    let bond = trade.bond_balance;
+    state::transition(env, &mut trade, TradeState::Cancelled, buyer)?;
+    forfeit(env, &mut trade)?;
+    trade::refund_buyer(env, &mut trade)?;
+
+    env.storage()
+        .instance()
+        .set(&DataKey::Trade(trade_id), &trade);
+
+    Ok(bond)
+}
+
+/// Pay a trade's bond back to the seller
+pub fn release(env: &Env, trade: &mut TradeEscrow) -> Result<(), ContractError> {
+    settlement::disburse(env, trade, &trade.seller, trade.bond_balance)?;
+    trade.bond_balance = 0;
+
+    Ok(())
+}
+
+/// Pay a trade's bond to the buyer
+pub fn forfeit(env: &Env, trade: &mut TradeEscrow) -> Result<(), ContractError> {
+    settlement::disburse(env, trade, &trade.buyer, trade.bond_balance)?;
+    trade.bond_balance = 0;
+
+    Ok(())
+}
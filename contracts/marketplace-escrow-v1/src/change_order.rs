@@ -0,0 +1,356 @@
+//! Purchase order change orders agreed by both parties
+
+use soroban_sdk::{Address, Env};
+
+use crate::advance;
+use crate::approval;
+use crate::bond;
+use crate::deferred;
+use crate::delivery;
+use crate::documents;
+use crate::errors::ContractError;
+use crate::fees;
+use crate::inspection;
+use crate::matching;
+use crate::milestones;
+use crate::settlement;
+use crate::state;
+use crate::storage::DataKey;
+use crate::trade;
+use crate::types::{
+    ApprovalAction, ChangeOrder, PurchaseOrder, PurchaseOrderDetails, TradeEscrow, TradeState,
+};
+
+/// Propose new purchase order terms on an open trade
+///
+/// Either party may propose; a new proposal replaces any still pending and
+/// gets the next proposal number. Returns the PO version the change order
+/// would create.
+pub fn propose_change_order(
+    env: &Env,
+    caller: &Address,
+    trade_id: u64,
+    terms: PurchaseOrderDetails,
+) -> Result<u32, ContractError> {
+    let trade = get_trade(env, trade_id)?;
+
+    // Verify caller is a party to the trade
+    if caller != &trade.buyer && caller != &trade.seller {
+        return Err(ContractError::NotTradeParty);
+    }
+
+    // Terms can only change before the seller delivers
+    state::require_state(&trade, TradeState::Ordered)?;
+    // Approvers sign off on the terms they were shown
+    approval::require_none_pending(env, trade_id)?;
+
+    // Verify the new terms identify the product, add up and reference their content
+    matching::check_document_identity(&terms.po_description, &terms.product)?;
+    matching::check_document_totals(terms.quantity, terms.unit_price, terms.total_price)?;
+    documents::check_document_reference(&terms.po_json_ipfs_hash, &terms.po_json_hash)?;
+    delivery::check_delivery_terms(env, &terms.delivery)?;
+    inspection::check_inspection_terms(env, &terms)?;
+    milestones::check_payment_schedule(&terms)?;
+    bond::check_bond_rate(terms.performance_bond_rate)?;
+    deferred::check_payment_terms(&terms)?;
+
+    let count_key = DataKey::ChangeOrderCount(trade_id);
+    let proposal: u32 = env.storage().instance().get(&count_key).unwrap_or(0) + 1;
+    env.storage().instance().set(&count_key, &proposal);
+
+    let change_order = ChangeOrder {
+        version: trade.po_version + 1,
+        proposal,
+        terms,
+        proposed_by: caller.clone(),
+        proposed_at: env.ledger().timestamp(),
+    };
+
+    env.storage()
+        .instance()
+        .set(&DataKey::PendingChangeOrder(trade_id), &change_order);
+
+    Ok(change_order.version)
+}
+
+/// Accept the pending change order as the counterparty
+///
+/// The superseded PO is archived under its version and the trade is
+/// repriced. On a funded trade the fee is recalculated, any surplus is
+/// refunded to the buyer straight away, and a shortfall is pulled from the
+/// buyer when they are the one accepting, up to `max_payment`. Otherwise the
+/// buyer must cover it with `top_up_escrow` before the seller can fulfill.
+///
+/// `proposal` is the number of the change order the caller reviewed; if it
+/// has since been replaced, acceptance fails.
+///
+/// A change order that leaves the trade over the buyer's approval threshold
+/// is held until its approvers sign off, with any shortfall the buyer pays
+/// parked outside the escrow meanwhile. Returns the PO version in force,
+/// which is unchanged while acceptance is held.
+pub fn accept_change_order(
+    env: &Env,
+    caller: &Address,
+    trade_id: u64,
+    proposal: u32,
+    max_payment: i128,
+    marketplace_fee_rate: u32,
+) -> Result<u32, ContractError> {
+    let mut trade = get_trade(env, trade_id)?;
+
+    // Verify caller is a party to the trade
+    if caller != &trade.buyer && caller != &trade.seller {
+        return Err(ContractError::NotTradeParty);
+    }
+
+    state::require_state(&trade, TradeState::Ordered)?;
+    approval::require_none_pending(env, trade_id)?;
+
+    let change_order = get_change_order(env, trade_id, proposal)?;
+
+    // Consent has to come from the other side
+    if &change_order.proposed_by == caller {
+        return Err(ContractError::CannotAcceptOwnChangeOrder);
+    }
+
+    // Released milestones were paid against the terms in force
+    if trade.milestones_released > 0 {
+        return Err(ContractError::MilestonesAlreadyReleased);
+    }
+
+    // The buyer covers any shortfall when accepting; the seller leaves it for a top-up
+    let payment = (caller == &trade.buyer).then_some(max_payment);
+
+    let mut repriced = trade.clone();
+    reprice(&mut repriced, change_order.terms.total_price)?;
+    if approval::requires_approval(env, &repriced) {
+        let mut parked = 0;
+        if let Some(max_payment) = payment.filter(|_| trade.escrow_balance > 0) {
+            let cost = fees::escrow_cost(
+                env,
+                &trade.buyer,
+                &trade.seller,
+                repriced.amount,
+                marketplace_fee_rate,
+            )?;
+            repriced.marketplace_fee = cost.marketplace_fee;
+            repriced.seller_fee = cost.seller_fee;
+            parked = (trade::required_escrow(&repriced) - trade.escrow_balance).max(0);
+            if max_payment < parked {
+                return Err(ContractError::InsufficientEscrowFunding);
+            }
+            if parked > 0 {
+                settlement::collect(env, &trade, parked)?;
+            }
+        }
+        approval::hold(
+            env,
+            trade_id,
+            ApprovalAction::AcceptChangeOrder(proposal, parked),
+        )?;
+        return Ok(trade.po_version);
+    }
+
+    apply_change_order(env, &mut trade, change_order, payment, marketplace_fee_rate)
+}
+
+/// Carry out a change order held for approval
+///
+/// Funds parked when the buyer accepted are credited to the escrow before
+/// it is repriced; anything they no longer need to cover is refunded.
+pub fn apply_approved(
+    env: &Env,
+    trade: &mut TradeEscrow,
+    proposal: u32,
+    parked: i128,
+    marketplace_fee_rate: u32,
+) -> Result<u32, ContractError> {
+    let change_order = get_change_order(env, trade.trade_id, proposal)?;
+    trade.escrow_balance += parked;
+
+    apply_change_order(env, trade, change_order, None, marketplace_fee_rate)
+}
+
+fn apply_change_order(
+    env: &Env,
+    trade: &mut TradeEscrow,
+    change_order: ChangeOrder,
+    payment: Option<i128>,
+    marketplace_fee_rate: u32,
+) -> Result<u32, ContractError> {
+    let trade_id = trade.trade_id;
+
+    // Archive the PO being replaced
+    let current: PurchaseOrder = env
+        .storage()
+        .instance()
+        .get(&DataKey::PurchaseOrder(trade_id))
+        .ok_or(ContractError::PurchaseOrderNotFound)?;
+    env.storage().instance().set(
+        &DataKey::PurchaseOrderVersion(trade_id, trade.po_version),
+        &current,
+    );
+
+    let terms = change_order.terms;
+
+    // A trade stays on escrow or on open account for its whole life
+    if (terms.payment_terms_days > 0) != (current.payment_terms_days > 0) {
+        return Err(ContractError::InvalidPaymentTerms);
+    }
+    let po = PurchaseOrder {
+        po_description: terms.po_description,
+        product: terms.product,
+        quantity: terms.quantity,
+        unit_price: terms.unit_price,
+        total_price: terms.total_price,
+        po_json_ipfs_hash: terms.po_json_ipfs_hash,
+        po_json_hash: terms.po_json_hash,
+        delivery: terms.delivery,
+        requires_inspection: terms.requires_inspection,
+        inspection_agency: terms.inspection_agency,
+        payment_schedule: terms.payment_schedule,
+        performance_bond_rate: terms.performance_bond_rate,
+        payment_terms_days: terms.payment_terms_days,
+        early_payment_discount_bps: terms.early_payment_discount_bps,
+        discount_window_days: terms.discount_window_days,
+        late_penalty_bps_per_day: terms.late_penalty_bps_per_day,
+        requires_title_receipt: terms.requires_title_receipt,
+        created_by: change_order.proposed_by,
+        created_at: env.ledger().timestamp(),
+    };
+
+    // Reprice the trade against the new total
+    reprice(trade, po.total_price)?;
+    if trade.escrow_balance > 0 {
+        reprice_escrow(env, trade, payment, marketplace_fee_rate)?;
+    }
+    advance::check_advance_limit(env, trade)?;
+    if po.payment_terms_days > 0 {
+        deferred::reserve_credit(env, trade)?;
+    }
+    trade.po_version = change_order.version;
+    trade.updated_at = env.ledger().timestamp();
+
+    env.storage()
+        .instance()
+        .set(&DataKey::PurchaseOrder(trade_id), &po);
+    env.storage()
+        .instance()
+        .remove(&DataKey::PendingChangeOrder(trade_id));
+    // A new Incoterm may move carriage to the other party, who nominates afresh
+    if po.delivery.incoterm != current.delivery.incoterm {
+        env.storage()
+            .instance()
+            .remove(&DataKey::DesignatedCarrier(trade_id));
+    }
+    env.storage()
+        .instance()
+        .set(&DataKey::Trade(trade_id), trade);
+
+    Ok(trade.po_version)
+}
+
+/// Get the change order awaiting consent on a trade, if any
+pub fn get_pending_change_order(env: &Env, trade_id: u64) -> Option<ChangeOrder> {
+    env.storage()
+        .instance()
+        .get(&DataKey::PendingChangeOrder(trade_id))
+}
+
+/// Get a purchase order as it stood at a given version
+pub fn get_purchase_order_version(
+    env: &Env,
+    trade_id: u64,
+    version: u32,
+) -> Result<PurchaseOrder, ContractError> {
+    let trade = get_trade(env, trade_id)?;
+
+    let key = if version == trade.po_version {
+        DataKey::PurchaseOrder(trade_id)
+    } else {
+        DataKey::PurchaseOrderVersion(trade_id, version)
+    };
+
+    env.storage()
+        .instance()
+        .get(&key)
+        .ok_or(ContractError::PurchaseOrderVersionNotFound)
+}
+
+/// Set a trade's amount and the broker commission on it
+fn reprice(trade: &mut TradeEscrow, amount: i128) -> Result<(), ContractError> {
+    trade.amount = amount;
+    if trade.broker.is_some() {
+        trade.broker_commission =
+            fees::broker_commission(trade.amount, trade.broker_commission_rate)?;
+    }
+
+    Ok(())
+}
+
+/// Bring a funded escrow in line with the trade's new amount
+///
+/// With `payment` set, the buyer covers any shortfall now, up to that much.
+fn reprice_escrow(
+    env: &Env,
+    trade: &mut TradeEscrow,
+    payment: Option<i128>,
+    marketplace_fee_rate: u32,
+) -> Result<(), ContractError> {
+    let cost = fees::escrow_cost(
+        env,
+        &trade.buyer,
+        &trade.seller,
+        trade.amount,
+        marketplace_fee_rate,
+    )?;
+
+    // Seller's share of the fee and the broker's commission both come out of the payout
+    if cost.seller_payout < trade.broker_commission {
+        return Err(ContractError::InvalidAmount);
+    }
+
+    trade.marketplace_fee = cost.marketplace_fee;
+    trade.fee_payer = cost.fee_payer;
+    trade.seller_fee = cost.seller_fee;
+
+    // Any advance already paid out no longer needs to be held
+    let required = trade::required_escrow(trade);
+    if trade.escrow_balance > required {
+        let surplus = trade.escrow_balance - required;
+        settlement::disburse(env, trade, &trade.buyer, surplus)?;
+        trade.escrow_balance -= surplus;
+    } else if let Some(max_payment) = payment.filter(|_| trade.escrow_balance < required) {
+        let shortfall = required - trade.escrow_balance;
+        if max_payment < shortfall {
+            return Err(ContractError::InsufficientEscrowFunding);
+        }
+        settlement::collect(env, trade, shortfall)?;
+        trade.escrow_balance += shortfall;
+    }
+
+    Ok(())
+}
+
+/// Get the pending change order, provided it is the one numbered `proposal`
+fn get_change_order(env: &Env, trade_id: u64, proposal: u32) -> Result<ChangeOrder, ContractError> {
+    let change_order: ChangeOrder = env
+        .storage()
+        .instance()
+        .get(&DataKey::PendingChangeOrder(trade_id))
+        .ok_or(ContractError::ChangeOrderNotFound)?;
+
+    // Consent only covers the terms the caller actually reviewed
+    if change_order.proposal != proposal {
+        return Err(ContractError::ChangeOrderMismatch);
+    }
+
+    Ok(change_order)
+}
+
+fn get_trade(env: &Env, trade_id: u64) -> Result<TradeEscrow, ContractError> {
+    env.storage()
+        .instance()
+        .get(&DataKey::Trade(trade_id))
+        .ok_or(ContractError::TradeNotFound)
+}
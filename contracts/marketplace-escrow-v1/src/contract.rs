@@ -1,14 +1,37 @@
 //! MarketplaceEscrowV1 Contract Implementation
 
-use soroban_sdk::{contract, contractimpl, Address, BytesN, Env, String, Vec};
+use soroban_sdk::{contract, contractimpl, Address, Bytes, BytesN, Env, String, Vec};
 
+use crate::advance;
+use crate::approval;
+use crate::bill_of_lading;
+use crate::bond;
+use crate::change_order;
+use crate::deferred;
+use crate::documents;
 use crate::errors::ContractError;
+use crate::fees;
+use crate::inspection;
+use crate::matching;
+use crate::migration;
+use crate::milestones;
+use crate::operators;
+use crate::receivable;
 use crate::registry;
+use crate::settlement;
+use crate::state;
 use crate::storage::DataKey;
+use crate::title_receipt;
 use crate::trade;
+use crate::treasury;
 use crate::types::{
-    BuyerInfo, CustomerInvoice, PurchaseOrder, SellerInfo, TradeEscrow, VLEIDocuments,
-    WarehouseReceipt,
+    ApprovalPolicy, BillOfLading, BillOfLadingDetails, BrokerCommission, BrokerInfo, BuyerInfo,
+    CarrierInfo, ChangeOrder, CustomerInvoice, CustomerInvoiceDetails, DeferredPaymentQuote,
+    DocumentKind, EscrowCost, FeeLedger, FeePayer, FeePeriodTotals, FeeSchedule,
+    InspectionAgencyInfo, InspectionCertificate, InspectionCertificateDetails, MatchReport,
+    Milestone, OperatorGrant, OperatorPermissions, PendingApproval, PurchaseOrder,
+    PurchaseOrderDetails, SellerDebt, SellerInfo, StateChange, TradeEscrow, VLEIDocuments,
+    WarehouseReceipt, WarehouseReceiptDetails,
 };
 
 #[contract]
@@ -44,6 +67,9 @@ impl MarketplaceEscrowV1 {
         // Initialize next trade ID
         env.storage().instance().set(&DataKey::NextTradeId, &1u64);
 
+        // Fresh deployments start at the current schema version
+        migration::set_version(env, migration::CONTRACT_VERSION);
+
         Ok(())
     }
 
@@ -75,6 +101,45 @@ impl MarketplaceEscrowV1 {
         registry::register_seller(&env, seller_address, seller_name, seller_lei_id)
     }
 
+    /// Register a new broker
+    pub fn register_broker(
+        env: Env,
+        broker_address: Address,
+        broker_name: String,
+        broker_lei_id: String,
+    ) -> Result<(), ContractError> {
+        // Require owner authorization
+        Self::require_owner(&env)?;
+
+        registry::register_broker(&env, broker_address, broker_name, broker_lei_id)
+    }
+
+    /// Register a new carrier
+    pub fn register_carrier(
+        env: Env,
+        carrier_address: Address,
+        carrier_name: String,
+        carrier_lei_id: String,
+    ) -> Result<(), ContractError> {
+        // Require owner authorization
+        Self::require_owner(&env)?;
+
+        registry::register_carrier(&env, carrier_address, carrier_name, carrier_lei_id)
+    }
+
+    /// Register a new inspection agency
+    pub fn register_inspection_agency(
+        env: Env,
+        agency_address: Address,
+        agency_name: String,
+        agency_lei_id: String,
+    ) -> Result<(), ContractError> {
+        // Require owner authorization
+        Self::require_owner(&env)?;
+
+        registry::register_inspection_agency(&env, agency_address, agency_name, agency_lei_id)
+    }
+
     /// Deactivate a buyer
     pub fn deactivate_buyer(env: Env, buyer_address: Address) -> Result<(), ContractError> {
         // Require owner authorization
@@ -91,6 +156,33 @@ impl MarketplaceEscrowV1 {
         registry::deactivate_seller(&env, &seller_address)
     }
 
+    /// Deactivate a broker
+    pub fn deactivate_broker(env: Env, broker_address: Address) -> Result<(), ContractError> {
+        // Require owner authorization
+        Self::require_owner(&env)?;
+
+        registry::deactivate_broker(&env, &broker_address)
+    }
+
+    /// Deactivate a carrier
+    pub fn deactivate_carrier(env: Env, carrier_address: Address) -> Result<(), ContractError> {
+        // Require owner authorization
+        Self::require_owner(&env)?;
+
+        registry::deactivate_carrier(&env, &carrier_address)
+    }
+
+    /// Deactivate an inspection agency
+    pub fn deactivate_inspection_agency(
+        env: Env,
+        agency_address: Address,
+    ) -> Result<(), ContractError> {
+        // Require owner authorization
+        Self::require_owner(&env)?;
+
+        registry::deactivate_inspection_agency(&env, &agency_address)
+    }
+
     /// Get buyer information
     pub fn get_buyer_info(env: Env, buyer_address: Address) -> Result<BuyerInfo, ContractError> {
         registry::get_buyer_info(&env, &buyer_address)
@@ -104,6 +196,30 @@ impl MarketplaceEscrowV1 {
         registry::get_seller_info(&env, &seller_address)
     }
 
+    /// Get broker information
+    pub fn get_broker_info(
+        env: Env,
+        broker_address: Address,
+    ) -> Result<BrokerInfo, ContractError> {
+        registry::get_broker_info(&env, &broker_address)
+    }
+
+    /// Get carrier information
+    pub fn get_carrier_info(
+        env: Env,
+        carrier_address: Address,
+    ) -> Result<CarrierInfo, ContractError> {
+        registry::get_carrier_info(&env, &carrier_address)
+    }
+
+    /// Get inspection agency information
+    pub fn get_inspection_agency_info(
+        env: Env,
+        agency_address: Address,
+    ) -> Result<InspectionAgencyInfo, ContractError> {
+        registry::get_inspection_agency_info(&env, &agency_address)
+    }
+
     /// Get all registered buyers
     pub fn get_all_buyers(env: Env) -> Vec<BuyerInfo> {
         registry::get_all_buyers(&env)
@@ -114,40 +230,343 @@ impl MarketplaceEscrowV1 {
         registry::get_all_sellers(&env)
     }
 
+    /// Get all registered brokers
+    pub fn get_all_brokers(env: Env) -> Vec<BrokerInfo> {
+        registry::get_all_brokers(&env)
+    }
+
+    /// Get all registered carriers
+    pub fn get_all_carriers(env: Env) -> Vec<CarrierInfo> {
+        registry::get_all_carriers(&env)
+    }
+
+    /// Get all registered inspection agencies
+    pub fn get_all_inspection_agencies(env: Env) -> Vec<InspectionAgencyInfo> {
+        registry::get_all_inspection_agencies(&env)
+    }
+
+    /// Get the commission a broker has earned on settled trades
+    pub fn get_broker_earnings(env: Env, broker_address: Address) -> i128 {
+        fees::get_broker_earnings(&env, &broker_address)
+    }
+
+    // ========== FEE & PAYMENT CONFIGURATION ==========
+
+    /// Set the token escrow is held in for new trades (admin only)
+    ///
+    /// Trades created while no token is set are settled off-chain.
+    pub fn set_payment_token(env: Env, payment_token: Address) -> Result<(), ContractError> {
+        Self::require_owner(&env)?;
+
+        env.storage()
+            .instance()
+            .set(&DataKey::PaymentToken, &payment_token);
+        Ok(())
+    }
+
+    /// Set who pays the marketplace fee by default (admin only)
+    pub fn set_default_fee_payer(env: Env, fee_payer: FeePayer) -> Result<(), ContractError> {
+        Self::require_owner(&env)?;
+
+        env.storage()
+            .instance()
+            .set(&DataKey::DefaultFeePayer, &fee_payer);
+        Ok(())
+    }
+
+    /// Override who pays the marketplace fee on a seller's trades (admin only)
+    pub fn set_seller_fee_payer(
+        env: Env,
+        seller_address: Address,
+        fee_payer: FeePayer,
+    ) -> Result<(), ContractError> {
+        Self::require_owner(&env)?;
+
+        registry::get_seller_info(&env, &seller_address)?;
+        env.storage()
+            .instance()
+            .set(&DataKey::SellerFeePayer(seller_address), &fee_payer);
+        Ok(())
+    }
+
+    /// Remove a seller's fee payer override (admin only)
+    pub fn clear_seller_fee_payer(env: Env, seller_address: Address) -> Result<(), ContractError> {
+        Self::require_owner(&env)?;
+
+        env.storage()
+            .instance()
+            .remove(&DataKey::SellerFeePayer(seller_address));
+        Ok(())
+    }
+
+    /// Replace the flat fee rate with a volume-tiered schedule (admin only)
+    pub fn set_fee_schedule(env: Env, schedule: FeeSchedule) -> Result<(), ContractError> {
+        Self::require_owner(&env)?;
+
+        fees::validate_fee_schedule(&schedule)?;
+        env.storage()
+            .instance()
+            .set(&DataKey::FeeSchedule, &schedule);
+        Ok(())
+    }
+
+    /// Return to the flat marketplace fee rate (admin only)
+    pub fn clear_fee_schedule(env: Env) -> Result<(), ContractError> {
+        Self::require_owner(&env)?;
+
+        env.storage().instance().remove(&DataKey::FeeSchedule);
+        Ok(())
+    }
+
+    /// Set a negotiated fee rate for a buyer or seller (admin only)
+    pub fn set_negotiated_fee_rate(
+        env: Env,
+        participant: Address,
+        fee_rate: u32,
+    ) -> Result<(), ContractError> {
+        Self::require_owner(&env)?;
+
+        if fee_rate > fees::MAX_FEE_RATE {
+            return Err(ContractError::InvalidFeeRate);
+        }
+        env.storage()
+            .instance()
+            .set(&DataKey::NegotiatedFeeRate(participant), &fee_rate);
+        Ok(())
+    }
+
+    /// Set the largest seller advance, in basis points of the trade amount (admin only)
+    pub fn set_max_advance_rate(env: Env, advance_rate: u32) -> Result<(), ContractError> {
+        Self::require_owner(&env)?;
+
+        if advance_rate > advance::MAX_ADVANCE_RATE {
+            return Err(ContractError::InvalidAdvanceRate);
+        }
+        env.storage()
+            .instance()
+            .set(&DataKey::MaxAdvanceRate, &advance_rate);
+        Ok(())
+    }
+
+    /// Get the largest seller advance, in basis points of the trade amount
+    pub fn get_max_advance_rate(env: Env) -> u32 {
+        advance::get_max_advance_rate(&env)
+    }
+
+    /// Set the NFT contract receivables are minted on (admin only)
+    ///
+    /// The contract must let the marketplace mint, e.g. an nft-enumerable
+    /// contract deployed with the marketplace as its owner.
+    pub fn set_receivable_contract(
+        env: Env,
+        receivable_contract: Address,
+    ) -> Result<(), ContractError> {
+        Self::require_owner(&env)?;
+
+        env.storage()
+            .instance()
+            .set(&DataKey::ReceivableContract, &receivable_contract);
+        Ok(())
+    }
+
+    /// Get the NFT contract receivables are minted on
+    pub fn get_receivable_contract(env: Env) -> Option<Address> {
+        receivable::get_receivable_contract(&env)
+    }
+
+    /// Set the NFT contract warehouse receipts are accepted from (admin only)
+    pub fn set_title_receipt_contract(
+        env: Env,
+        title_receipt_contract: Address,
+    ) -> Result<(), ContractError> {
+        Self::require_owner(&env)?;
+
+        env.storage()
+            .instance()
+            .set(&DataKey::TitleReceiptContract, &title_receipt_contract);
+        Ok(())
+    }
+
+    /// Get the NFT contract warehouse receipts are accepted from
+    pub fn get_title_receipt_contract(env: Env) -> Option<Address> {
+        title_receipt::get_title_receipt_contract(&env)
+    }
+
+    /// Set how much deferred-trade value a buyer may have outstanding (admin only)
+    pub fn set_credit_limit(
+        env: Env,
+        buyer_address: Address,
+        credit_limit: i128,
+    ) -> Result<(), ContractError> {
+        Self::require_owner(&env)?;
+
+        registry::get_buyer_info(&env, &buyer_address)?;
+        if credit_limit < 0 {
+            return Err(ContractError::InvalidAmount);
+        }
+        env.storage()
+            .instance()
+            .set(&DataKey::CreditLimit(buyer_address), &credit_limit);
+        Ok(())
+    }
+
+    /// Get a buyer's credit limit for deferred trades
+    pub fn get_credit_limit(env: Env, buyer_address: Address) -> i128 {
+        deferred::get_credit_limit(&env, &buyer_address)
+    }
+
+    /// Get the value of a buyer's open and unpaid deferred trades
+    pub fn get_credit_used(env: Env, buyer_address: Address) -> i128 {
+        deferred::get_credit_used(&env, &buyer_address)
+    }
+
+    /// Remove a participant's negotiated fee rate (admin only)
+    pub fn clear_negotiated_fee_rate(env: Env, participant: Address) -> Result<(), ContractError> {
+        Self::require_owner(&env)?;
+
+        env.storage()
+            .instance()
+            .remove(&DataKey::NegotiatedFeeRate(participant));
+        Ok(())
+    }
+
+    /// Get the active fee schedule
+    pub fn get_fee_schedule(env: Env) -> Option<FeeSchedule> {
+        fees::get_fee_schedule(&env)
+    }
+
+    /// Get a participant's settled volume over the last `window_days` days
+    pub fn get_trailing_volume(env: Env, participant: Address, window_days: u32) -> i128 {
+        fees::trailing_volume(&env, &participant, window_days)
+    }
+
+    /// Get the fee rate a trade between a buyer and a seller would be charged
+    pub fn get_fee_rate(env: Env, buyer: Address, seller: Address) -> u32 {
+        let marketplace_fee_rate: u32 = env
+            .storage()
+            .instance()
+            .get(&DataKey::MarketplaceFeeRate)
+            .unwrap_or(25);
+
+        fees::resolve_fee_rate(&env, &buyer, &seller, marketplace_fee_rate)
+    }
+
+    /// Get the token new trades are settled in
+    pub fn get_payment_token(env: Env) -> Option<Address> {
+        settlement::get_payment_token(&env)
+    }
+
+    /// Get who pays the marketplace fee on a seller's trades
+    pub fn get_fee_payer(env: Env, seller_address: Address) -> FeePayer {
+        fees::resolve_fee_payer(&env, &seller_address)
+    }
+
+    // ========== TREASURY FUNCTIONS ==========
+
+    /// Keep settled fees in the contract instead of sending them to the
+    /// platform treasury (admin only)
+    pub fn set_fee_retention(env: Env, retain_fees: bool) -> Result<(), ContractError> {
+        Self::require_owner(&env)?;
+
+        env.storage()
+            .instance()
+            .set(&DataKey::RetainFees, &retain_fees);
+        Ok(())
+    }
+
+    /// Appoint the account allowed to withdraw retained fees (admin only)
+    pub fn set_fee_manager(env: Env, fee_manager: Address) -> Result<(), ContractError> {
+        Self::require_owner(&env)?;
+
+        env.storage()
+            .instance()
+            .set(&DataKey::FeeManager, &fee_manager);
+        Ok(())
+    }
+
+    /// Withdraw retained fees (owner or fee manager)
+    pub fn withdraw_fees(
+        env: Env,
+        caller: Address,
+        asset: Address,
+        amount: i128,
+        recipient: Address,
+    ) -> Result<(), ContractError> {
+        caller.require_auth();
+
+        let owner: Address = env
+            .storage()
+            .instance()
+            .get(&DataKey::ContractOwner)
+            .ok_or(ContractError::NotContractOwner)?;
+        if caller != owner && treasury::get_fee_manager(&env) != Some(caller) {
+            return Err(ContractError::Unauthorized);
+        }
+
+        treasury::withdraw_fees(&env, &asset, amount, &recipient)
+    }
+
+    /// Get all-time fee totals for an asset
+    pub fn get_fee_ledger(env: Env, asset: Address) -> FeeLedger {
+        treasury::get_fee_ledger(&env, &asset)
+    }
+
+    /// Get fee totals per day for an asset over a range of periods
+    ///
+    /// Periods are ledger timestamps divided by `FEE_PERIOD_SECONDS`; days
+    /// without fee activity are omitted.
+    pub fn get_fee_totals(
+        env: Env,
+        asset: Address,
+        from_period: u64,
+        to_period: u64,
+    ) -> Result<Vec<FeePeriodTotals>, ContractError> {
+        treasury::get_fee_totals(&env, &asset, from_period, to_period)
+    }
+
+    /// Get fees held by the contract and available to withdraw
+    pub fn get_retained_fees(env: Env, asset: Address) -> i128 {
+        treasury::get_retained_fees(&env, &asset)
+    }
+
+    /// Get the account allowed to withdraw retained fees
+    pub fn get_fee_manager(env: Env) -> Option<Address> {
+        treasury::get_fee_manager(&env)
+    }
+
     // ========== TRADE LIFECYCLE FUNCTIONS ==========
 
-    /// Create a new trade with purchase order
+    /// Create a new trade with purchase order (buyer or its operator)
+    ///
+    /// `broker` optionally names the registered broker who introduced the
+    /// trade; their commission is paid out of the seller's proceeds.
     pub fn create_trade(
         env: Env,
-        buyer: Address,
+        caller: Address,
         seller: Address,
-        po_description: String,
-        quantity: u64,
-        unit_price: i128,
-        total_price: i128,
-        po_json_ipfs_hash: String,
+        details: PurchaseOrderDetails,
         buyer_lei_ipfs: String,
         seller_lei_ipfs: String,
+        broker: Option<BrokerCommission>,
     ) -> Result<u64, ContractError> {
-        buyer.require_auth();
+        caller.require_auth();
+        migration::require_current_version(&env)?;
 
         trade::create_trade(
             &env,
-            &buyer,
+            &caller,
             &seller,
-            po_description,
-            quantity,
-            unit_price,
-            total_price,
-            po_json_ipfs_hash,
+            details,
             buyer_lei_ipfs,
             seller_lei_ipfs,
+            broker,
         )
     }
 
     /// Fund escrow (buyer adds payment)
     pub fn fund_escrow(env: Env, buyer: Address, trade_id: u64, payment_amount: i128) -> Result<(), ContractError> {
         buyer.require_auth();
+        migration::require_current_version(&env)?;
 
         let marketplace_fee_rate: u32 = env
             .storage()
@@ -158,6 +577,19 @@ impl MarketplaceEscrowV1 {
         trade::fund_escrow(&env, &buyer, trade_id, payment_amount, marketplace_fee_rate)
     }
 
+    /// Top up escrow that no longer covers the trade (buyer pays at most `max_payment`)
+    pub fn top_up_escrow(
+        env: Env,
+        buyer: Address,
+        trade_id: u64,
+        max_payment: i128,
+    ) -> Result<i128, ContractError> {
+        buyer.require_auth();
+        migration::require_current_version(&env)?;
+
+        trade::top_up_escrow(&env, &buyer, trade_id, max_payment)
+    }
+
     /// Validate buyer vLEI
     pub fn validate_buyer_vlei(env: Env, trade_id: u64) -> Result<(), ContractError> {
         // In production, this would require seller authorization
@@ -170,40 +602,106 @@ impl MarketplaceEscrowV1 {
         trade::validate_seller_vlei(&env, trade_id)
     }
 
-    /// Fulfill order (seller ships goods)
+    /// Fulfill order (seller or its operator ships goods)
     pub fn fulfill_order(
+        env: Env,
+        caller: Address,
+        trade_id: u64,
+        invoice: CustomerInvoiceDetails,
+        receipt: WarehouseReceiptDetails,
+    ) -> Result<(), ContractError> {
+        caller.require_auth();
+        migration::require_current_version(&env)?;
+
+        trade::fulfill_order(&env, &caller, trade_id, invoice, receipt)
+    }
+
+    /// Designate the carrier for a shipped order (seller under C-terms, buyer under F-terms)
+    pub fn nominate_carrier(
+        env: Env,
+        caller: Address,
+        trade_id: u64,
+        carrier: Address,
+    ) -> Result<(), ContractError> {
+        caller.require_auth();
+        migration::require_current_version(&env)?;
+
+        bill_of_lading::nominate_carrier(&env, &caller, trade_id, &carrier)
+    }
+
+    /// Submit the bill of lading for a shipped order (carrier)
+    pub fn submit_bill_of_lading(
+        env: Env,
+        carrier: Address,
+        trade_id: u64,
+        details: BillOfLadingDetails,
+    ) -> Result<(), ContractError> {
+        carrier.require_auth();
+        migration::require_current_version(&env)?;
+
+        bill_of_lading::submit_bill_of_lading(&env, &carrier, trade_id, details)
+    }
+
+    /// Submit the quality inspection certificate for an order (inspection agency)
+    ///
+    /// A failing certificate refunds the buyer and ends the trade.
+    pub fn submit_inspection_certificate(
+        env: Env,
+        agency: Address,
+        trade_id: u64,
+        details: InspectionCertificateDetails,
+    ) -> Result<(), ContractError> {
+        agency.require_auth();
+        migration::require_current_version(&env)?;
+
+        inspection::submit_inspection_certificate(&env, &agency, trade_id, details)
+    }
+
+    /// Deposit the performance bond the PO asks for (seller, before fulfillment)
+    ///
+    /// Returns the amount posted.
+    pub fn post_performance_bond(
+        env: Env,
+        seller: Address,
+        trade_id: u64,
+    ) -> Result<i128, ContractError> {
+        seller.require_auth();
+        migration::require_current_version(&env)?;
+
+        bond::post_performance_bond(&env, &seller, trade_id)
+    }
+
+    /// Mint a receivable NFT for a fulfilled trade's payout (seller)
+    ///
+    /// Whoever holds the NFT at settlement is paid in the seller's place.
+    /// Returns the token ID.
+    pub fn mint_receivable(env: Env, seller: Address, trade_id: u64) -> Result<u32, ContractError> {
+        seller.require_auth();
+        migration::require_current_version(&env)?;
+
+        receivable::mint_receivable(&env, &seller, trade_id)
+    }
+
+    /// Deposit the warehouse receipt NFT for a fulfilled trade's goods (seller)
+    ///
+    /// The marketplace holds it until settlement, when title passes to the
+    /// buyer along with the payment to the seller.
+    pub fn deposit_title_receipt(
         env: Env,
         seller: Address,
         trade_id: u64,
-        ci_description: String,
-        ci_quantity: u64,
-        ci_unit_price: i128,
-        ci_total_price: i128,
-        ci_json_ipfs_hash: String,
-        wr_description: String,
-        wr_quantity: u64,
-        wr_unit_price: i128,
+        token_id: u32,
     ) -> Result<(), ContractError> {
         seller.require_auth();
+        migration::require_current_version(&env)?;
 
-        trade::fulfill_order(
-            &env,
-            &seller,
-            trade_id,
-            ci_description,
-            ci_quantity,
-            ci_unit_price,
-            ci_total_price,
-            ci_json_ipfs_hash,
-            wr_description,
-            wr_quantity,
-            wr_unit_price,
-        )
+        title_receipt::deposit_title_receipt(&env, &seller, trade_id, token_id)
     }
 
     /// Reject order (seller rejects)
     pub fn reject_order(env: Env, seller: Address, trade_id: u64) -> Result<(), ContractError> {
         seller.require_auth();
+        migration::require_current_version(&env)?;
 
         trade::reject_order(&env, &seller, trade_id)
     }
@@ -211,46 +709,354 @@ impl MarketplaceEscrowV1 {
     /// Cancel trade (buyer cancels before fulfillment)
     pub fn cancel_trade(env: Env, buyer: Address, trade_id: u64) -> Result<(), ContractError> {
         buyer.require_auth();
+        migration::require_current_version(&env)?;
 
         trade::cancel_trade(&env, &buyer, trade_id)
     }
 
-    /// Accept trade (buyer triggers DvP and settlement)
-    pub fn accept_trade(env: Env, buyer: Address, trade_id: u64) -> Result<(), ContractError> {
+    /// Propose new PO terms (buyer or seller, before fulfillment)
+    pub fn propose_change_order(
+        env: Env,
+        caller: Address,
+        trade_id: u64,
+        terms: PurchaseOrderDetails,
+    ) -> Result<u32, ContractError> {
+        caller.require_auth();
+        migration::require_current_version(&env)?;
+
+        change_order::propose_change_order(&env, &caller, trade_id, terms)
+    }
+
+    /// Accept the pending change order (counterparty) and reprice escrow
+    ///
+    /// `proposal` names the change order reviewed; a buyer accepting pays any
+    /// shortfall up to `max_payment`.
+    pub fn accept_change_order(
+        env: Env,
+        caller: Address,
+        trade_id: u64,
+        proposal: u32,
+        max_payment: i128,
+    ) -> Result<u32, ContractError> {
+        caller.require_auth();
+        migration::require_current_version(&env)?;
+
+        let marketplace_fee_rate: u32 = env
+            .storage()
+            .instance()
+            .get(&DataKey::MarketplaceFeeRate)
+            .unwrap_or(25);
+
+        change_order::accept_change_order(
+            &env,
+            &caller,
+            trade_id,
+            proposal,
+            max_payment,
+            marketplace_fee_rate,
+        )
+    }
+
+    /// Accept trade (buyer or its operator triggers DvP and settlement)
+    pub fn accept_trade(env: Env, caller: Address, trade_id: u64) -> Result<(), ContractError> {
+        caller.require_auth();
+        migration::require_current_version(&env)?;
+
+        let platform_treasury: Address = env
+            .storage()
+            .instance()
+            .get(&DataKey::PlatformTreasury)
+            .ok_or(ContractError::Unauthorized)?;
+
+        trade::accept_trade(&env, &caller, trade_id, &platform_treasury)
+    }
+
+    /// Register or replace the buyer's approval policy (buyer)
+    ///
+    /// Replacing a policy needs enough of its current approvers as
+    /// `cosigners`, each authorizing the change.
+    pub fn set_approval_policy(
+        env: Env,
+        participant: Address,
+        policy: ApprovalPolicy,
+        cosigners: Vec<Address>,
+    ) -> Result<(), ContractError> {
+        participant.require_auth();
+        migration::require_current_version(&env)?;
+
+        approval::set_approval_policy(&env, &participant, policy, cosigners)
+    }
+
+    /// Approve the action a trade is held on (one of the buyer's approvers)
+    ///
+    /// The approval that meets the policy's requirement carries the action
+    /// out. Returns whether it did.
+    pub fn approve_action(
+        env: Env,
+        approver: Address,
+        trade_id: u64,
+    ) -> Result<bool, ContractError> {
+        approver.require_auth();
+        migration::require_current_version(&env)?;
+
+        let platform_treasury: Address = env
+            .storage()
+            .instance()
+            .get(&DataKey::PlatformTreasury)
+            .ok_or(ContractError::Unauthorized)?;
+        let marketplace_fee_rate: u32 = env
+            .storage()
+            .instance()
+            .get(&DataKey::MarketplaceFeeRate)
+            .unwrap_or(25);
+
+        approval::approve(
+            &env,
+            &approver,
+            trade_id,
+            &platform_treasury,
+            marketplace_fee_rate,
+        )
+    }
+
+    /// Delegate trade actions to a staff operator until it expires (buyer or seller)
+    ///
+    /// Replaces any earlier delegation to the operator; an operator acts for
+    /// one participant only.
+    pub fn grant_operator(
+        env: Env,
+        participant: Address,
+        operator: Address,
+        permissions: OperatorPermissions,
+    ) -> Result<(), ContractError> {
+        participant.require_auth();
+        migration::require_current_version(&env)?;
+
+        operators::grant_operator(&env, &participant, &operator, permissions)
+    }
+
+    /// Withdraw a delegation to an operator (the participant that granted it)
+    pub fn revoke_operator(
+        env: Env,
+        participant: Address,
+        operator: Address,
+    ) -> Result<(), ContractError> {
+        participant.require_auth();
+        migration::require_current_version(&env)?;
+
+        operators::revoke_operator(&env, &participant, &operator)
+    }
+
+    /// Pay the seller an advance out of the funded escrow (buyer)
+    ///
+    /// The advance is deducted from the seller's payout at settlement.
+    pub fn authorize_advance(
+        env: Env,
+        buyer: Address,
+        trade_id: u64,
+        amount: i128,
+    ) -> Result<(), ContractError> {
         buyer.require_auth();
+        migration::require_current_version(&env)?;
+
+        advance::authorize_advance(&env, &buyer, trade_id, amount)
+    }
 
+    /// Pay a settled deferred trade (buyer)
+    ///
+    /// Returns the amount paid, including the buyer's share of the fee.
+    pub fn pay_deferred(env: Env, buyer: Address, trade_id: u64) -> Result<i128, ContractError> {
+        buyer.require_auth();
+        migration::require_current_version(&env)?;
+
+        let marketplace_fee_rate: u32 = env
+            .storage()
+            .instance()
+            .get(&DataKey::MarketplaceFeeRate)
+            .unwrap_or(25);
         let platform_treasury: Address = env
             .storage()
             .instance()
             .get(&DataKey::PlatformTreasury)
             .ok_or(ContractError::Unauthorized)?;
 
-        trade::accept_trade(&env, &buyer, trade_id, &platform_treasury)
+        deferred::pay_deferred(
+            &env,
+            &buyer,
+            trade_id,
+            marketplace_fee_rate,
+            &platform_treasury,
+        )
+    }
+
+    /// Take the seller's bond after a missed delivery date and cancel (buyer)
+    ///
+    /// Returns the amount forfeited.
+    pub fn claim_performance_bond(
+        env: Env,
+        buyer: Address,
+        trade_id: u64,
+    ) -> Result<i128, ContractError> {
+        buyer.require_auth();
+        migration::require_current_version(&env)?;
+
+        bond::claim_performance_bond(&env, &buyer, trade_id)
+    }
+
+    /// Release the next payment milestone (buyer, once its evidence matches)
+    ///
+    /// Releasing the final milestone settles the trade. Returns the index of
+    /// the milestone released.
+    pub fn release_milestone(
+        env: Env,
+        buyer: Address,
+        trade_id: u64,
+    ) -> Result<u32, ContractError> {
+        buyer.require_auth();
+        migration::require_current_version(&env)?;
+
+        let platform_treasury: Address = env
+            .storage()
+            .instance()
+            .get(&DataKey::PlatformTreasury)
+            .ok_or(ContractError::Unauthorized)?;
+
+        milestones::release_milestone(&env, &buyer, trade_id, &platform_treasury)
     }
 
     // ========== QUERY FUNCTIONS ==========
 
     /// Get trade details
     pub fn get_trade(env: Env, trade_id: u64) -> Result<TradeEscrow, ContractError> {
+        migration::require_current_version(&env)?;
+
         env.storage()
             .instance()
             .get(&DataKey::Trade(trade_id))
             .ok_or(ContractError::TradeNotFound)
     }
 
+    /// Get a trade's state changes, oldest first
+    pub fn get_trade_history(env: Env, trade_id: u64) -> Result<Vec<StateChange>, ContractError> {
+        migration::require_current_version(&env)?;
+
+        if !env.storage().instance().has(&DataKey::Trade(trade_id)) {
+            return Err(ContractError::TradeNotFound);
+        }
+
+        Ok(state::get_history(&env, trade_id))
+    }
+
+    /// Preview three-way matching without settling (every pairwise comparison)
+    pub fn preview_match(env: Env, trade_id: u64) -> Result<MatchReport, ContractError> {
+        migration::require_current_version(&env)?;
+
+        if !env.storage().instance().has(&DataKey::Trade(trade_id)) {
+            return Err(ContractError::TradeNotFound);
+        }
+
+        matching::match_report(&env, trade_id)
+    }
+
+    /// Quote what paying a deferred trade now would cost, discount or penalty included
+    pub fn quote_deferred_payment(
+        env: Env,
+        trade_id: u64,
+    ) -> Result<DeferredPaymentQuote, ContractError> {
+        migration::require_current_version(&env)?;
+
+        let marketplace_fee_rate: u32 = env
+            .storage()
+            .instance()
+            .get(&DataKey::MarketplaceFeeRate)
+            .unwrap_or(25);
+
+        deferred::quote_deferred_payment(&env, trade_id, marketplace_fee_rate)
+    }
+
+    /// Get a participant's approval policy
+    pub fn get_approval_policy(env: Env, participant: Address) -> Option<ApprovalPolicy> {
+        approval::get_approval_policy(&env, &participant)
+    }
+
+    /// Get the action a trade is held on until approved, if any
+    pub fn get_pending_approval(env: Env, trade_id: u64) -> Option<PendingApproval> {
+        approval::get_pending_approval(&env, trade_id)
+    }
+
+    /// Get the delegation an operator acts under, if any
+    pub fn get_operator(env: Env, operator: Address) -> Option<OperatorGrant> {
+        operators::get_operator(&env, &operator)
+    }
+
+    /// Get a buyer's deferred trades past their due date and still unpaid
+    pub fn get_overdue_trades(env: Env, buyer: Address) -> Vec<u64> {
+        deferred::get_overdue_trades(&env, &buyer)
+    }
+
+    /// Get the advances a seller still owes from unwound trades, oldest first
+    pub fn get_seller_debts(env: Env, seller: Address) -> Vec<SellerDebt> {
+        advance::get_seller_debts(&env, &seller)
+    }
+
+    /// Get the payment milestones not yet released on a trade
+    pub fn get_pending_milestones(
+        env: Env,
+        trade_id: u64,
+    ) -> Result<Vec<Milestone>, ContractError> {
+        migration::require_current_version(&env)?;
+
+        milestones::pending_milestones(&env, trade_id)
+    }
+
+    /// Check whether `bytes` are the document content recorded on a trade
+    ///
+    /// Recomputes the SHA-256 of the supplied canonical JSON and compares it
+    /// with the hash submitted alongside the document.
+    pub fn verify_document(
+        env: Env,
+        trade_id: u64,
+        doc_kind: DocumentKind,
+        bytes: Bytes,
+    ) -> Result<bool, ContractError> {
+        migration::require_current_version(&env)?;
+
+        documents::verify_document(&env, trade_id, doc_kind, &bytes)
+    }
+
     /// Get purchase order
     pub fn get_purchase_order(env: Env, trade_id: u64) -> Result<PurchaseOrder, ContractError> {
+        migration::require_current_version(&env)?;
+
         env.storage()
             .instance()
             .get(&DataKey::PurchaseOrder(trade_id))
             .ok_or(ContractError::PurchaseOrderNotFound)
     }
 
+    /// Get a purchase order as it stood at a given version
+    pub fn get_purchase_order_version(
+        env: Env,
+        trade_id: u64,
+        version: u32,
+    ) -> Result<PurchaseOrder, ContractError> {
+        migration::require_current_version(&env)?;
+
+        change_order::get_purchase_order_version(&env, trade_id, version)
+    }
+
+    /// Get the change order awaiting consent on a trade
+    pub fn get_pending_change_order(env: Env, trade_id: u64) -> Option<ChangeOrder> {
+        change_order::get_pending_change_order(&env, trade_id)
+    }
+
     /// Get customer invoice
     pub fn get_customer_invoice(
         env: Env,
         trade_id: u64,
     ) -> Result<CustomerInvoice, ContractError> {
+        migration::require_current_version(&env)?;
+
         env.storage()
             .instance()
             .get(&DataKey::CustomerInvoice(trade_id))
@@ -262,12 +1068,38 @@ impl MarketplaceEscrowV1 {
         env: Env,
         trade_id: u64,
     ) -> Result<WarehouseReceipt, ContractError> {
+        migration::require_current_version(&env)?;
+
         env.storage()
             .instance()
             .get(&DataKey::WarehouseReceipt(trade_id))
             .ok_or(ContractError::WarehouseReceiptNotFound)
     }
 
+    /// Get bill of lading
+    pub fn get_bill_of_lading(env: Env, trade_id: u64) -> Result<BillOfLading, ContractError> {
+        env.storage()
+            .instance()
+            .get(&DataKey::BillOfLading(trade_id))
+            .ok_or(ContractError::BillOfLadingNotFound)
+    }
+
+    /// Get the carrier designated to issue a trade's bill of lading
+    pub fn get_designated_carrier(env: Env, trade_id: u64) -> Option<Address> {
+        bill_of_lading::get_designated_carrier(&env, trade_id)
+    }
+
+    /// Get inspection certificate
+    pub fn get_inspection_certificate(
+        env: Env,
+        trade_id: u64,
+    ) -> Result<InspectionCertificate, ContractError> {
+        env.storage()
+            .instance()
+            .get(&DataKey::InspectionCertificate(trade_id))
+            .ok_or(ContractError::InspectionCertificateNotFound)
+    }
+
     /// Get vLEI documents
     pub fn get_vlei_documents(env: Env, trade_id: u64) -> Result<VLEIDocuments, ContractError> {
         env.storage()
@@ -292,22 +1124,23 @@ impl MarketplaceEscrowV1 {
             .unwrap_or(Vec::new(&env))
     }
 
-    /// Calculate escrow cost (amount + marketplace fee)
-    pub fn calculate_escrow_cost(env: Env, amount: i128) -> Result<(i128, i128), ContractError> {
+    /// Calculate escrow cost for a trade between a buyer and a seller
+    ///
+    /// Returns what the buyer must fund and what the seller receives under
+    /// the fee rate and fee payer policy that currently apply to them.
+    pub fn calculate_escrow_cost(
+        env: Env,
+        buyer: Address,
+        seller: Address,
+        amount: i128,
+    ) -> Result<EscrowCost, ContractError> {
         let marketplace_fee_rate: u32 = env
             .storage()
             .instance()
             .get(&DataKey::MarketplaceFeeRate)
             .unwrap_or(25);
 
-        let fee = amount
-            .checked_mul(marketplace_fee_rate as i128)
-            .ok_or(ContractError::OverflowError)?
-            / 10000;
-
-        let total = amount.checked_add(fee).ok_or(ContractError::OverflowError)?;
-
-        Ok((total, fee))
+        fees::escrow_cost(&env, &buyer, &seller, amount, marketplace_fee_rate)
     }
 
     /// Upgrade contract (admin only)
@@ -317,6 +1150,20 @@ impl MarketplaceEscrowV1 {
         Ok(())
     }
 
+    /// Migrate stored data to this build's schema (admin only)
+    ///
+    /// Call after `upgrade` until it returns `true`; trade functions are
+    /// unavailable while a migration is pending.
+    pub fn migrate(env: Env, batch_size: u32) -> Result<bool, ContractError> {
+        Self::require_owner(&env)?;
+        migration::migrate(&env, batch_size)
+    }
+
+    /// Get the schema version of the data in storage
+    pub fn get_contract_version(env: Env) -> u32 {
+        migration::get_version(&env)
+    }
+
     // ========== INTERNAL HELPER FUNCTIONS ==========
 
     /// Require contract owner authorization
@@ -0,0 +1,297 @@
+//! Open account trades paid after settlement against a buyer credit limit
+
+use soroban_sdk::{Address, Env, Vec};
+
+use crate::errors::ContractError;
+use crate::fees::{self, SECONDS_PER_DAY};
+use crate::settlement;
+use crate::storage::DataKey;
+use crate::trade;
+use crate::types::{
+    DeferredPaymentQuote, EscrowCost, PurchaseOrder, PurchaseOrderDetails, TradeEscrow, TradeState,
+};
+
+/// Longest payment term a PO may grant, in days
+pub const MAX_PAYMENT_TERMS_DAYS: u32 = 180;
+
+/// Largest early-payment discount a PO may offer (10%)
+pub const MAX_DISCOUNT_BPS: u32 = 1000;
+
+/// Largest daily late-payment penalty a PO may charge (1% a day)
+pub const MAX_PENALTY_BPS_PER_DAY: u32 = 100;
+
+/// Check a PO's payment terms
+///
+/// Deferred trades have no escrow to release milestones from, so they take
+/// no payment schedule. Discounts and penalties only apply to deferred
+/// trades, and the discount window must close by the due date.
+pub fn check_payment_terms(details: &PurchaseOrderDetails) -> Result<(), ContractError> {
+    if details.payment_terms_days > MAX_PAYMENT_TERMS_DAYS {
+        return Err(ContractError::InvalidPaymentTerms);
+    }
+
+    if details.payment_terms_days == 0 {
+        if details.early_payment_discount_bps > 0
+            || details.discount_window_days > 0
+            || details.late_penalty_bps_per_day > 0
+        {
+            return Err(ContractError::InvalidPaymentTerms);
+        }
+        return Ok(());
+    }
+
+    if !details.payment_schedule.is_empty()
+        || details.early_payment_discount_bps > MAX_DISCOUNT_BPS
+        || details.discount_window_days > details.payment_terms_days
+        || (details.early_payment_discount_bps > 0) != (details.discount_window_days > 0)
+        || details.late_penalty_bps_per_day > MAX_PENALTY_BPS_PER_DAY
+    {
+        return Err(ContractError::InvalidPaymentTerms);
+    }
+
+    Ok(())
+}
+
+/// Whether a trade is paid on open account rather than through escrow
+pub fn is_deferred(trade: &TradeEscrow) -> bool {
+    trade.credit_reserved > 0 || trade.payment_due > 0
+}
+
+/// Get the most deferred-trade value a buyer may have outstanding (0 by default)
+pub fn get_credit_limit(env: &Env, buyer: &Address) -> i128 {
+    env.storage()
+        .instance()
+        .get(&DataKey::CreditLimit(buyer.clone()))
+        .unwrap_or(0)
+}
+
+/// Get the value of a buyer's open and unpaid deferred trades
+pub fn get_credit_used(env: &Env, buyer: &Address) -> i128 {
+    env.storage()
+        .instance()
+        .get(&DataKey::CreditUsed(buyer.clone()))
+        .unwrap_or(0)
+}
+
+/// Hold the trade's amount against its buyer's credit limit
+///
+/// Called again after a change order, when only an increase is checked
+/// against the limit.
+pub fn reserve_credit(env: &Env, trade: &mut TradeEscrow) -> Result<(), ContractError> {
+    let increase = trade.amount - trade.credit_reserved;
+    let used = get_credit_used(env, &trade.buyer) + increase;
+    if increase > 0 && used > get_credit_limit(env, &trade.buyer) {
+        return Err(ContractError::CreditLimitExceeded);
+    }
+
+    env.storage()
+        .instance()
+        .set(&DataKey::CreditUsed(trade.buyer.clone()), &used);
+    trade.credit_reserved = trade.amount;
+
+    Ok(())
+}
+
+/// Give back the credit a trade holds
+pub fn release_credit(env: &Env, trade: &mut TradeEscrow) {
+    if trade.credit_reserved <= 0 {
+        return;
+    }
+
+    let used = get_credit_used(env, &trade.buyer) - trade.credit_reserved;
+    env.storage()
+        .instance()
+        .set(&DataKey::CreditUsed(trade.buyer.clone()), &used);
+    trade.credit_reserved = 0;
+}
+
+/// Start the payment term of a trade being settled
+pub fn defer_payment(env: &Env, trade: &mut TradeEscrow, payment_terms_days: u32) {
+    trade.payment_due = env.ledger().timestamp() + payment_terms_days as u64 * SECONDS_PER_DAY;
+
+    let mut open = get_deferred_trades(env, &trade.buyer);
+    open.push_back(trade.trade_id);
+    env.storage()
+        .instance()
+        .set(&DataKey::BuyerDeferredTrades(trade.buyer.clone()), &open);
+}
+
+/// Pay a settled deferred trade
+///
+/// The marketplace fee is resolved at payment, as it would be when funding
+/// escrow, and the payment is released straight on to the seller, treasury
+/// and broker. The PO's early-payment discount or late penalty is applied
+/// and recorded on the trade; the fee is charged on the undiscounted amount.
+/// Returns the amount paid.
+pub fn pay_deferred(
+    env: &Env,
+    buyer: &Address,
+    trade_id: u64,
+    marketplace_fee_rate: u32,
+    platform_treasury: &Address,
+) -> Result<i128, ContractError> {
+    let mut trade = get_unpaid_trade(env, trade_id)?;
+
+    // Verify caller is buyer
+    if &trade.buyer != buyer {
+        return Err(ContractError::NotBuyer);
+    }
+
+    let (cost, discount, penalty) = price_payment(env, &trade, marketplace_fee_rate)?;
+    if cost.seller_payout - discount < trade.broker_commission {
+        return Err(ContractError::InvalidAmount);
+    }
+    let total_due = cost.total_required - discount + penalty;
+
+    settlement::collect(env, &trade, total_due)?;
+    trade.escrow_balance = total_due;
+    trade.early_payment_discount = discount;
+    trade.late_payment_penalty = penalty;
+    trade.marketplace_fee = cost.marketplace_fee;
+    trade.fee_payer = cost.fee_payer;
+    trade.seller_fee = cost.seller_fee;
+
+    trade::release_payment(env, &mut trade, platform_treasury)?;
+    release_credit(env, &mut trade);
+    trade.updated_at = env.ledger().timestamp();
+
+    let mut open = get_deferred_trades(env, buyer);
+    if let Some(index) = open.first_index_of(trade_id) {
+        open.remove(index);
+    }
+    env.storage()
+        .instance()
+        .set(&DataKey::BuyerDeferredTrades(buyer.clone()), &open);
+
+    env.storage()
+        .instance()
+        .set(&DataKey::Trade(trade_id), &trade);
+
+    Ok(total_due)
+}
+
+/// What paying a settled deferred trade now would cost its buyer
+pub fn quote_deferred_payment(
+    env: &Env,
+    trade_id: u64,
+    marketplace_fee_rate: u32,
+) -> Result<DeferredPaymentQuote, ContractError> {
+    let trade = get_unpaid_trade(env, trade_id)?;
+    let (cost, discount, penalty) = price_payment(env, &trade, marketplace_fee_rate)?;
+
+    Ok(DeferredPaymentQuote {
+        discount,
+        penalty,
+        total_due: cost.total_required - discount + penalty,
+    })
+}
+
+/// Load a settled deferred trade that has not been paid yet
+fn get_unpaid_trade(env: &Env, trade_id: u64) -> Result<TradeEscrow, ContractError> {
+    let trade: TradeEscrow = env
+        .storage()
+        .instance()
+        .get(&DataKey::Trade(trade_id))
+        .ok_or(ContractError::TradeNotFound)?;
+
+    if trade.state != TradeState::Settled || trade.payment_due == 0 {
+        return Err(ContractError::NoDeferredPayment);
+    }
+    if trade.credit_reserved == 0 {
+        return Err(ContractError::DeferredPaymentAlreadyMade);
+    }
+
+    Ok(trade)
+}
+
+/// Fee split, discount and penalty of paying a deferred trade now
+fn price_payment(
+    env: &Env,
+    trade: &TradeEscrow,
+    marketplace_fee_rate: u32,
+) -> Result<(EscrowCost, i128, i128), ContractError> {
+    let po: PurchaseOrder = env
+        .storage()
+        .instance()
+        .get(&DataKey::PurchaseOrder(trade.trade_id))
+        .ok_or(ContractError::PurchaseOrderNotFound)?;
+    let cost = fees::escrow_cost(
+        env,
+        &trade.buyer,
+        &trade.seller,
+        trade.amount,
+        marketplace_fee_rate,
+    )?;
+    let (discount, penalty) = adjustments(env, trade, &po)?;
+
+    Ok((cost, discount, penalty))
+}
+
+/// Early-payment discount and late penalty due if a trade is paid now
+///
+/// The discount applies up to the end of its window after settlement. The
+/// penalty accrues for each started day past the due date and never exceeds
+/// the trade amount.
+fn adjustments(
+    env: &Env,
+    trade: &TradeEscrow,
+    po: &PurchaseOrder,
+) -> Result<(i128, i128), ContractError> {
+    let now = env.ledger().timestamp();
+
+    let discount_until = trade.settled_at + po.discount_window_days as u64 * SECONDS_PER_DAY;
+    let discount = if po.early_payment_discount_bps > 0 && now <= discount_until {
+        trade
+            .amount
+            .checked_mul(po.early_payment_discount_bps as i128)
+            .ok_or(ContractError::OverflowError)?
+            / 10000
+    } else {
+        0
+    };
+
+    let penalty = if now > trade.payment_due {
+        let days_late = (now - trade.payment_due).div_ceil(SECONDS_PER_DAY) as i128;
+        trade
+            .amount
+            .checked_mul(po.late_penalty_bps_per_day as i128)
+            .and_then(|daily| daily.checked_mul(days_late))
+            .ok_or(ContractError::OverflowError)?
+            / 10000
+    } else {
+        0
+    };
+
+    Ok((discount, penalty.min(trade.amount)))
+}
+
+/// Get a buyer's settled deferred trades past their due date and still unpaid
+pub fn get_overdue_trades(env: &Env, buyer: &Address) -> Vec<u64> {
+    let now = env.ledger().timestamp();
+    let mut overdue = Vec::new(env);
+    for trade_id in get_deferred_trades(env, buyer).iter() {
+        let trade: Option<TradeEscrow> = env.storage().instance().get(&DataKey::Trade(trade_id));
+        if trade.is_some_and(|trade| now > trade.payment_due) {
+            overdue.push_back(trade_id);
+        }
+    }
+
+    overdue
+}
+
+/// Fail if a buyer has an overdue deferred payment
+pub fn check_buyer_standing(env: &Env, buyer: &Address) -> Result<(), ContractError> {
+    if !get_overdue_trades(env, buyer).is_empty() {
+        return Err(ContractError::BuyerPaymentsOverdue);
+    }
+
+    Ok(())
+}
+
+/// Settled deferred trades a buyer has yet to pay
+fn get_deferred_trades(env: &Env, buyer: &Address) -> Vec<u64> {
+    env.storage()
+        .instance()
+        .get(&DataKey::BuyerDeferredTrades(buyer.clone()))
+        .unwrap_or(Vec::new(env))
+}
@@ -0,0 +1,78 @@
+//! Incoterms delivery terms and the settlement evidence they call for
+
+use soroban_sdk::{Env, String};
+
+use crate::errors::ContractError;
+use crate::types::{DeliveryTerms, Incoterm, PurchaseOrder};
+
+/// Check a PO's delivery terms name a place and an open delivery window
+pub fn check_delivery_terms(env: &Env, terms: &DeliveryTerms) -> Result<(), ContractError> {
+    if terms.named_place.is_empty()
+        || terms.window_start > terms.window_end
+        || terms.window_end < env.ledger().timestamp()
+    {
+        return Err(ContractError::InvalidDeliveryTerms);
+    }
+
+    Ok(())
+}
+
+/// Check an invoice restates the PO's Incoterm and named place
+///
+/// Orders migrated from version 1 have no named place and accept any terms.
+pub fn check_invoice_terms(
+    po: &PurchaseOrder,
+    incoterm: Incoterm,
+    named_place: &String,
+) -> Result<(), ContractError> {
+    if po.delivery.named_place.is_empty() {
+        return Ok(());
+    }
+
+    if incoterm != po.delivery.incoterm || named_place != &po.delivery.named_place {
+        return Err(ContractError::DeliveryTermsMismatch);
+    }
+
+    Ok(())
+}
+
+/// Check the seller delivers within the PO's delivery window
+///
+/// Orders migrated from version 1 have an unbounded window.
+pub fn check_delivery_window(env: &Env, po: &PurchaseOrder) -> Result<(), ContractError> {
+    let now = env.ledger().timestamp();
+    if now < po.delivery.window_start || now > po.delivery.window_end {
+        return Err(ContractError::OutsideDeliveryWindow);
+    }
+
+    Ok(())
+}
+
+/// Whether an Incoterm needs a carrier's bill of lading before payment
+///
+/// Under these rules the seller's delivery is complete when the goods are
+/// handed to the main carrier, so the transport document is the proof of
+/// delivery. Under EXW and FCA the buyer arranges carriage, and under the
+/// D-rules the warehouse receipt at destination already proves delivery.
+pub fn requires_shipment_evidence(incoterm: Incoterm) -> bool {
+    matches!(
+        incoterm,
+        Incoterm::Cpt
+            | Incoterm::Cip
+            | Incoterm::Fas
+            | Incoterm::Fob
+            | Incoterm::Cfr
+            | Incoterm::Cif
+    )
+}
+
+/// Whether the seller contracts the main carriage under an Incoterm
+///
+/// Under the C-rules the seller books and pays for the main carriage, so it
+/// picks the carrier; under the F-rules the buyer does.
+pub fn seller_books_carriage(incoterm: Incoterm) -> bool {
+    matches!(
+        incoterm,
+        Incoterm::Cpt | Incoterm::Cip | Incoterm::Cfr | Incoterm::Cif
+    )
+}
@@ -0,0 +1,108 @@
+//! Document content hashes and IPFS reference checks
+
+use soroban_sdk::{Bytes, BytesN, Env, String};
+
+use crate::errors::ContractError;
+use crate::storage::DataKey;
+use crate::types::{
+    BillOfLading, CustomerInvoice, DocumentKind, InspectionCertificate, PurchaseOrder,
+    WarehouseReceipt,
+};
+
+/// Length of a CIDv0 (base58btc multihash of a SHA-256 digest)
+pub const CID_V0_LEN: u32 = 46;
+
+/// Shortest CIDv1 accepted, including the multibase prefix
+pub const CID_V1_MIN_LEN: u32 = 10;
+
+/// Longest CIDv1 accepted, including the multibase prefix
+pub const CID_V1_MAX_LEN: u32 = 128;
+
+const BASE58_ALPHABET: &[u8] = b"123456789ABCDEFGHJKLMNPQRSTUVWXYZabcdefghijkmnopqrstuvwxyz";
+
+/// Check a document's IPFS reference and content hash are well formed
+pub fn check_document_reference(cid: &String, hash: &BytesN<32>) -> Result<(), ContractError> {
+    check_cid(cid)?;
+
+    // An all-zero digest marks documents recorded before hashes existed
+    if hash.to_array() == [0u8; 32] {
+        return Err(ContractError::InvalidDocumentHash);
+    }
+
+    Ok(())
+}
+
+/// Check an IPFS CID is a CIDv0 (base58btc) or a base32 CIDv1
+pub fn check_cid(cid: &String) -> Result<(), ContractError> {
+    let len = cid.len();
+    if len > CID_V1_MAX_LEN {
+        return Err(ContractError::InvalidDocumentCid);
+    }
+
+    let mut buf = [0u8; CID_V1_MAX_LEN as usize];
+    let bytes = &mut buf[..len as usize];
+    cid.copy_into_slice(bytes);
+
+    let valid = match bytes {
+        // CIDv0: "Qm" followed by base58btc, always 46 characters
+        [b'Q', b'm', rest @ ..] if len == CID_V0_LEN => {
+            rest.iter().all(|byte| BASE58_ALPHABET.contains(byte))
+        }
+        // CIDv1: multibase prefix 'b' (lowercase base32); the version byte
+        // 0x01 always encodes to a leading 'a'
+        [b'b', b'a', rest @ ..] if len >= CID_V1_MIN_LEN => rest
+            .iter()
+            .all(|byte| byte.is_ascii_lowercase() || (b'2'..=b'7').contains(byte)),
+        _ => false,
+    };
+
+    if !valid {
+        return Err(ContractError::InvalidDocumentCid);
+    }
+
+    Ok(())
+}
+
+/// Whether `bytes` hash to the digest recorded for one of a trade's documents
+pub fn verify_document(
+    env: &Env,
+    trade_id: u64,
+    kind: DocumentKind,
+    bytes: &Bytes,
+) -> Result<bool, ContractError> {
+    let recorded = recorded_hash(env, trade_id, kind)?;
+    let computed: BytesN<32> = env.crypto().sha256(bytes).into();
+
+    Ok(computed == recorded)
+}
+
+fn recorded_hash(
+    env: &Env,
+    trade_id: u64,
+    kind: DocumentKind,
+) -> Result<BytesN<32>, ContractError> {
+    let storage = env.storage().instance();
+
+    match kind {
+        DocumentKind::PurchaseOrder => storage
+            .get::<_, PurchaseOrder>(&DataKey::PurchaseOrder(trade_id))
+            .map(|po| po.po_json_hash)
+            .ok_or(ContractError::PurchaseOrderNotFound),
+        DocumentKind::CustomerInvoice => storage
+            .get::<_, CustomerInvoice>(&DataKey::CustomerInvoice(trade_id))
+            .map(|ci| ci.ci_json_hash)
+            .ok_or(ContractError::CustomerInvoiceNotFound),
+        DocumentKind::WarehouseReceipt => storage
+            .get::<_, WarehouseReceipt>(&DataKey::WarehouseReceipt(trade_id))
+            .map(|wr| wr.wr_json_hash)
+            .ok_or(ContractError::WarehouseReceiptNotFound),
+        DocumentKind::BillOfLading => storage
+            .get::<_, BillOfLading>(&DataKey::BillOfLading(trade_id))
+            .map(|bl| bl.bl_json_hash)
+            .ok_or(ContractError::BillOfLadingNotFound),
+        DocumentKind::InspectionCertificate => storage
+            .get::<_, InspectionCertificate>(&DataKey::InspectionCertificate(trade_id))
+            .map(|certificate| certificate.report_hash)
+            .ok_or(ContractError::InspectionCertificateNotFound),
+    }
+}
@@ -15,12 +15,33 @@ pub enum ContractError {
     SellerInactive = 6,
     BuyerNameTaken = 7,
     SellerNameTaken = 8,
+    BrokerNotRegistered = 9,
+    BrokerAlreadyRegistered = 10,
+    BrokerInactive = 11,
+    BrokerNameTaken = 12,
+    CarrierNotRegistered = 13,
+    CarrierAlreadyRegistered = 14,
+    CarrierInactive = 15,
+    CarrierNameTaken = 16,
+    InspectionAgencyNotRegistered = 17,
+    InspectionAgencyAlreadyRegistered = 18,
+    InspectionAgencyInactive = 19,
     
     // Authorization errors (20-39)
     Unauthorized = 20,
     NotContractOwner = 21,
     NotBuyer = 22,
     NotSeller = 23,
+    NotApprover = 24,
+    ApprovalAlreadyGiven = 25,
+    InsufficientApprovals = 26,
+    NoPendingApproval = 27,
+    NotOperator = 28,
+    OperatorExpired = 29,
+    OperatorNotPermitted = 30,
+    OperatorAlreadyAssigned = 31,
+    CarrierNotDesignated = 32,
+    InspectionAgencyNotDesignated = 33,
     
     // Trade state errors (40-59)
     InvalidTradeState = 40,
@@ -29,11 +50,36 @@ pub enum ContractError {
     TradeNotOrdered = 43,
     TradeNotFulfilled = 44,
     BuyerCannotBeSeller = 45,
+    BrokerCannotBeParty = 46,
+    NotTradeParty = 47,
+    CannotAcceptOwnChangeOrder = 48,
+    ReceivableAlreadyMinted = 49,
+    TitleReceiptAlreadyDeposited = 50,
+    TitleReceiptRequired = 51,
+    ApprovalPending = 52,
+    ChangeOrderMismatch = 53,
     
     // Escrow errors (60-79)
     InsufficientEscrowFunding = 60,
     EscrowNotFunded = 61,
     EscrowAlreadyFunded = 62,
+    PaymentTransferFailed = 63,
+    InsufficientRetainedFees = 64,
+    InvalidPaymentSchedule = 65,
+    NoPaymentSchedule = 66,
+    MilestonesAlreadyReleased = 67,
+    AdvanceLimitExceeded = 68,
+    AdvanceNotAllowed = 69,
+    PerformanceBondRequired = 70,
+    PerformanceBondAlreadyPosted = 71,
+    PerformanceBondNotRequired = 72,
+    PerformanceBondNotPosted = 73,
+    DeliveryDeadlineNotPassed = 74,
+    CreditLimitExceeded = 75,
+    BuyerPaymentsOverdue = 76,
+    NoDeferredPayment = 77,
+    DeferredPaymentAlreadyMade = 78,
+    ReceivableNotAllowed = 79,
     
     // Document errors (80-99)
     PurchaseOrderNotFound = 80,
@@ -42,16 +88,51 @@ pub enum ContractError {
     VLEIDocumentsNotFound = 83,
     BuyerVLEINotValidated = 84,
     SellerVLEINotValidated = 85,
+    ChangeOrderNotFound = 86,
+    PurchaseOrderVersionNotFound = 87,
+    InconsistentDocumentTotals = 88,
+    InvalidProductId = 89,
+    DescriptionTooLong = 90,
+    InvalidDocumentCid = 91,
+    InvalidDocumentHash = 92,
+    BillOfLadingNotFound = 93,
+    BillOfLadingAlreadySubmitted = 94,
+    BillOfLadingNotRequired = 95,
+    InspectionCertificateNotFound = 96,
+    InspectionCertificateAlreadySubmitted = 97,
+    InspectionNotRequired = 98,
+    InvalidDeliveryTerms = 99,
     
     // Matching errors (100-119)
     DescriptionMismatch = 100,
     QuantityVarianceTooHigh = 101,
     PriceVarianceTooHigh = 102,
     ThreeWayMatchFailed = 103,
+    UnitPriceVarianceTooHigh = 104,
+    ProductIdMismatch = 105,
+    InspectionFailed = 106,
+    DeliveryTermsMismatch = 107,
+    TitleReceiptMismatch = 108,
+    OutsideDeliveryWindow = 109,
     
     // General errors (120-139)
     InvalidAmount = 120,
     InvalidFeeRate = 121,
     OverflowError = 122,
     DivisionByZero = 123,
+    InvalidFeeSchedule = 124,
+    InvalidCommissionRate = 125,
+    InvalidPeriodRange = 126,
+    InvalidAdvanceRate = 127,
+    InvalidBondRate = 128,
+    InvalidPaymentTerms = 129,
+    ReceivableContractNotSet = 130,
+    TitleReceiptContractNotSet = 131,
+    InvalidApprovalPolicy = 132,
+    InvalidOperatorGrant = 133,
+
+    // Migration errors (140-159)
+    MigrationRequired = 140,
+    UnsupportedContractVersion = 141,
+    InvalidMigrationBatch = 142,
 }
@@ -0,0 +1,249 @@
+//! Marketplace fee calculation, fee schedules and fee-payer policy
+
+use soroban_sdk::{Address, Env, Vec};
+
+use crate::errors::ContractError;
+use crate::storage::{self, DataKey};
+use crate::types::{EscrowCost, FeePayer, FeeSchedule, VolumeBucket};
+
+/// Highest fee rate the marketplace may charge (10%)
+pub const MAX_FEE_RATE: u32 = 1000;
+
+/// Highest commission a broker may take on a trade (20%)
+pub const MAX_COMMISSION_RATE: u32 = 2000;
+
+/// Longest trailing window a fee schedule may look back over
+pub const MAX_VOLUME_WINDOW_DAYS: u32 = 366;
+
+pub const SECONDS_PER_DAY: u64 = 86400;
+
+/// Validate a fee schedule before it is stored
+pub fn validate_fee_schedule(schedule: &FeeSchedule) -> Result<(), ContractError> {
+    if schedule.tiers.is_empty()
+        || schedule.volume_window_days == 0
+        || schedule.volume_window_days > MAX_VOLUME_WINDOW_DAYS
+        || schedule.min_fee < 0
+        || schedule.max_fee < 0
+        || (schedule.max_fee > 0 && schedule.min_fee > schedule.max_fee)
+    {
+        return Err(ContractError::InvalidFeeSchedule);
+    }
+
+    let mut previous_min_volume: i128 = -1;
+    for tier in schedule.tiers.iter() {
+        // First tier must start at zero volume, later tiers strictly ascend
+        if (previous_min_volume < 0 && tier.min_volume != 0)
+            || tier.min_volume <= previous_min_volume
+        {
+            return Err(ContractError::InvalidFeeSchedule);
+        }
+        if tier.fee_rate > MAX_FEE_RATE {
+            return Err(ContractError::InvalidFeeRate);
+        }
+        previous_min_volume = tier.min_volume;
+    }
+
+    Ok(())
+}
+
+/// Get the active fee schedule, if one replaces the flat fee rate
+pub fn get_fee_schedule(env: &Env) -> Option<FeeSchedule> {
+    env.storage().instance().get(&DataKey::FeeSchedule)
+}
+
+/// Get a participant's negotiated fee rate, if any
+pub fn get_negotiated_fee_rate(env: &Env, participant: &Address) -> Option<u32> {
+    env.storage()
+        .instance()
+        .get(&DataKey::NegotiatedFeeRate(participant.clone()))
+}
+
+/// Get a participant's settled volume over the last `window_days` days
+pub fn trailing_volume(env: &Env, participant: &Address, window_days: u32) -> i128 {
+    let buckets: Vec<VolumeBucket> =
+        storage::get_persistent(env, &DataKey::SettledVolume(participant.clone()))
+            .unwrap_or(Vec::new(env));
+
+    let first_day = window_start(env, window_days);
+    let mut volume: i128 = 0;
+    for bucket in buckets.iter() {
+        if bucket.day >= first_day {
+            volume = volume.saturating_add(bucket.volume);
+        }
+    }
+    volume
+}
+
+/// Add a settled trade to a participant's daily volume
+///
+/// Buckets that have fallen out of the longest possible window are dropped
+/// so the record stays bounded.
+pub fn record_settled_volume(env: &Env, participant: &Address, amount: i128) {
+    let key = DataKey::SettledVolume(participant.clone());
+    let buckets: Vec<VolumeBucket> = storage::get_persistent(env, &key).unwrap_or(Vec::new(env));
+
+    let today = env.ledger().timestamp() / SECONDS_PER_DAY;
+    let first_day = window_start(env, MAX_VOLUME_WINDOW_DAYS);
+
+    let mut updated = Vec::new(env);
+    let mut recorded = false;
+    for mut bucket in buckets.iter() {
+        if bucket.day < first_day {
+            continue;
+        }
+        if bucket.day == today {
+            bucket.volume = bucket.volume.saturating_add(amount);
+            recorded = true;
+        }
+        updated.push_back(bucket);
+    }
+    if !recorded {
+        updated.push_back(VolumeBucket {
+            day: today,
+            volume: amount,
+        });
+    }
+
+    storage::set_persistent(env, &key, &updated);
+}
+
+/// Resolve the fee rate for a trade between a buyer and a seller
+///
+/// A negotiated rate held by either party takes precedence (the lower one
+/// if both have one). Otherwise the fee schedule tier reached by the party
+/// with the larger trailing volume applies, falling back to the flat
+/// marketplace rate when no schedule is configured.
+pub fn resolve_fee_rate(env: &Env, buyer: &Address, seller: &Address, base_fee_rate: u32) -> u32 {
+    let negotiated = match (
+        get_negotiated_fee_rate(env, buyer),
+        get_negotiated_fee_rate(env, seller),
+    ) {
+        (Some(buyer_rate), Some(seller_rate)) => Some(buyer_rate.min(seller_rate)),
+        (buyer_rate, seller_rate) => buyer_rate.or(seller_rate),
+    };
+    if let Some(fee_rate) = negotiated {
+        return fee_rate;
+    }
+
+    let Some(schedule) = get_fee_schedule(env) else {
+        return base_fee_rate;
+    };
+
+    let volume = trailing_volume(env, buyer, schedule.volume_window_days).max(trailing_volume(
+        env,
+        seller,
+        schedule.volume_window_days,
+    ));
+
+    let mut fee_rate = base_fee_rate;
+    for tier in schedule.tiers.iter() {
+        if volume >= tier.min_volume {
+            fee_rate = tier.fee_rate;
+        }
+    }
+    fee_rate
+}
+
+/// Calculate a broker's commission on a trade amount
+pub fn broker_commission(amount: i128, commission_rate: u32) -> Result<i128, ContractError> {
+    if commission_rate == 0 || commission_rate > MAX_COMMISSION_RATE {
+        return Err(ContractError::InvalidCommissionRate);
+    }
+
+    Ok(amount
+        .checked_mul(commission_rate as i128)
+        .ok_or(ContractError::OverflowError)?
+        / 10000)
+}
+
+/// Get the total commission a broker has earned on settled trades
+pub fn get_broker_earnings(env: &Env, broker: &Address) -> i128 {
+    env.storage()
+        .instance()
+        .get(&DataKey::BrokerEarnings(broker.clone()))
+        .unwrap_or(0)
+}
+
+/// Add a settled commission to a broker's accrued earnings
+pub fn record_broker_earnings(env: &Env, broker: &Address, commission: i128) {
+    let earnings = get_broker_earnings(env, broker).saturating_add(commission);
+    env.storage()
+        .instance()
+        .set(&DataKey::BrokerEarnings(broker.clone()), &earnings);
+}
+
+/// First day counted in a trailing window ending today
+fn window_start(env: &Env, window_days: u32) -> u64 {
+    let today = env.ledger().timestamp() / SECONDS_PER_DAY;
+    (today + 1).saturating_sub(window_days as u64)
+}
+
+/// Get the marketplace-wide fee payer (defaults to the buyer)
+pub fn get_default_fee_payer(env: &Env) -> FeePayer {
+    env.storage()
+        .instance()
+        .get(&DataKey::DefaultFeePayer)
+        .unwrap_or(FeePayer::Buyer)
+}
+
+/// Resolve who pays the fee on a seller's trades
+///
+/// A per-seller override takes precedence over the marketplace default.
+pub fn resolve_fee_payer(env: &Env, seller: &Address) -> FeePayer {
+    env.storage()
+        .instance()
+        .get(&DataKey::SellerFeePayer(seller.clone()))
+        .unwrap_or_else(|| get_default_fee_payer(env))
+}
+
+/// Calculate what the buyer funds and the seller receives for a trade amount
+pub fn escrow_cost(
+    env: &Env,
+    buyer: &Address,
+    seller: &Address,
+    amount: i128,
+    base_fee_rate: u32,
+) -> Result<EscrowCost, ContractError> {
+    if amount <= 0 {
+        return Err(ContractError::InvalidAmount);
+    }
+
+    let fee_rate = resolve_fee_rate(env, buyer, seller, base_fee_rate);
+    let mut marketplace_fee = amount
+        .checked_mul(fee_rate as i128)
+        .ok_or(ContractError::OverflowError)?
+        / 10000;
+
+    // Apply per-trade caps, never charging more than the trade itself
+    if let Some(schedule) = get_fee_schedule(env) {
+        marketplace_fee = marketplace_fee.max(schedule.min_fee);
+        if schedule.max_fee > 0 {
+            marketplace_fee = marketplace_fee.min(schedule.max_fee);
+        }
+        marketplace_fee = marketplace_fee.min(amount);
+    }
+
+    let fee_payer = resolve_fee_payer(env, seller);
+
+    // On a split the seller absorbs the odd unit
+    let buyer_fee = match fee_payer {
+        FeePayer::Buyer => marketplace_fee,
+        FeePayer::Seller => 0,
+        FeePayer::Split => marketplace_fee / 2,
+    };
+    let seller_fee = marketplace_fee - buyer_fee;
+
+    let total_required = amount
+        .checked_add(buyer_fee)
+        .ok_or(ContractError::OverflowError)?;
+
+    Ok(EscrowCost {
+        fee_payer,
+        fee_rate,
+        marketplace_fee,
+        buyer_fee,
+        seller_fee,
+        total_required,
+        seller_payout: amount - seller_fee,
+    })
+}
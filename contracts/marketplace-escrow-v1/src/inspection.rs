@@ -0,0 +1,131 @@
+//! Quality inspection certificates issued by registered agencies
+
+use soroban_sdk::{Address, Env};
+
+use crate::bond;
+use crate::documents;
+use crate::errors::ContractError;
+use crate::registry::is_inspection_agency_active;
+use crate::state;
+use crate::storage::DataKey;
+use crate::trade;
+use crate::types::{
+    InspectionCertificate, InspectionCertificateDetails, PurchaseOrder, PurchaseOrderDetails,
+    TradeEscrow, TradeState,
+};
+
+/// Record the inspection certificate for a trade whose PO requires one
+///
+/// Only the registered, active agency named on the PO may submit it, once,
+/// before the trade settles. A failing certificate ends the trade: the
+/// escrow is refunded to the buyer and the trade moves to `Refunded`.
+pub fn submit_inspection_certificate(
+    env: &Env,
+    agency: &Address,
+    trade_id: u64,
+    details: InspectionCertificateDetails,
+) -> Result<(), ContractError> {
+    is_inspection_agency_active(env, agency)?;
+
+    let mut trade: TradeEscrow = env
+        .storage()
+        .instance()
+        .get(&DataKey::Trade(trade_id))
+        .ok_or(ContractError::TradeNotFound)?;
+
+    // Goods are inspected between ordering and settlement
+    if trade.state != TradeState::Ordered && trade.state != TradeState::Fulfilled {
+        return Err(ContractError::InvalidTradeState);
+    }
+
+    let po: PurchaseOrder = env
+        .storage()
+        .instance()
+        .get(&DataKey::PurchaseOrder(trade_id))
+        .ok_or(ContractError::PurchaseOrderNotFound)?;
+    if !po.requires_inspection {
+        return Err(ContractError::InspectionNotRequired);
+    }
+    if po.inspection_agency.as_ref() != Some(agency) {
+        return Err(ContractError::InspectionAgencyNotDesignated);
+    }
+
+    if env
+        .storage()
+        .instance()
+        .has(&DataKey::InspectionCertificate(trade_id))
+    {
+        return Err(ContractError::InspectionCertificateAlreadySubmitted);
+    }
+
+    // The sample is drawn from the ordered goods
+    if details.sampled_quantity == 0 || details.sampled_quantity > po.quantity {
+        return Err(ContractError::InvalidAmount);
+    }
+    documents::check_document_reference(&details.report_ipfs_hash, &details.report_hash)?;
+
+    let certificate = InspectionCertificate {
+        agency: agency.clone(),
+        passed: details.passed,
+        grade: details.grade,
+        sampled_quantity: details.sampled_quantity,
+        report_ipfs_hash: details.report_ipfs_hash,
+        report_hash: details.report_hash,
+        issued_at: env.ledger().timestamp(),
+    };
+
+    env.storage()
+        .instance()
+        .set(&DataKey::InspectionCertificate(trade_id), &certificate);
+
+    if !certificate.passed {
+        state::transition(env, &mut trade, TradeState::Refunded, agency)?;
+        bond::release(env, &mut trade)?;
+        trade::refund_buyer(env, &mut trade)?;
+
+        env.storage()
+            .instance()
+            .set(&DataKey::Trade(trade_id), &trade);
+    }
+
+    Ok(())
+}
+
+/// Check a PO that requires inspection names an active agency to carry it out
+pub fn check_inspection_terms(
+    env: &Env,
+    details: &PurchaseOrderDetails,
+) -> Result<(), ContractError> {
+    if !details.requires_inspection {
+        return Ok(());
+    }
+
+    let agency = details
+        .inspection_agency
+        .as_ref()
+        .ok_or(ContractError::InspectionAgencyNotDesignated)?;
+    is_inspection_agency_active(env, agency)
+}
+
+/// Fail unless a required inspection certificate exists and passed
+pub fn require_passed_inspection(env: &Env, trade_id: u64) -> Result<(), ContractError> {
+    let po: PurchaseOrder = env
+        .storage()
+        .instance()
+        .get(&DataKey::PurchaseOrder(trade_id))
+        .ok_or(ContractError::PurchaseOrderNotFound)?;
+    if !po.requires_inspection {
+        return Ok(());
+    }
+
+    let certificate: InspectionCertificate = env
+        .storage()
+        .instance()
+        .get(&DataKey::InspectionCertificate(trade_id))
+        .ok_or(ContractError::InspectionCertificateNotFound)?;
+    if !certificate.passed {
+        return Err(ContractError::InspectionFailed);
+    }
+
+    Ok(())
+}
@@ -8,28 +8,64 @@
 //! ## Features
 //! - Buyer and seller registration with LEI IDs
 //! - Trade lifecycle management (Ordered → Fulfilled → Settled)
+//! - M-of-N officer approval of buyer actions on trades above a threshold
+//! - Expiring operator delegations letting staff act on trades for a participant
 //! - Purchase Order, Customer Invoice, and Warehouse Receipt with IPFS storage
+//! - SHA-256 content hashes on every document, verifiable on-chain
+//! - Incoterms 2020 delivery terms deciding which evidence settlement needs
+//! - Carrier-issued bills of lading for four-way matching of shipped goods
+//! - Warehouse receipt NFTs passed to the buyer as title at settlement
+//! - Quality inspection certificates that gate settlement or refund the buyer
+//! - Seller working-capital advances, netted against later payouts if unwound
+//! - Seller performance bonds, forfeited on a missed delivery date or rejection
+//! - Deferred (Net-30/60) payment terms against per-buyer credit limits
+//! - Receivable NFTs that let sellers factor a fulfilled trade's payout
+//! - Milestone payment schedules released against matching evidence
+//! - Versioned purchase orders renegotiated through bilateral change orders
 //! - 3-way matching with variance tolerance (5% quantity, 2% price)
 //! - Delivery vs Payment (DvP) automated settlement
 //! - GLEIF/vLEI validation support
 //!
 //! ## Trade States
-//! - ORDERED (0): Buyer created PO and funded escrow
-//! - FULFILLED (1): Seller shipped goods and submitted CI + WR
-//! - SETTLED (2): 3-way match passed, payment released
-//! - REJECTED (3): Seller rejected the order
-//! - CANCELLED (4): Buyer cancelled before fulfillment
+//! - Ordered (0): Buyer created PO and funded escrow
+//! - Fulfilled (1): Seller shipped goods and submitted CI + WR
+//! - Settled (2): 3-way match passed, payment released
+//! - Rejected (3): Seller rejected the order
+//! - Cancelled (4): Buyer cancelled before fulfillment
+//! - Refunded (5): Goods failed inspection, escrow returned to the buyer
+//!
+//! Every state change goes through `state::transition` and is logged in the
+//! trade's history.
 
+mod advance;
+mod approval;
+mod bill_of_lading;
+mod bond;
+mod change_order;
 mod contract;
+mod deferred;
+mod delivery;
+mod documents;
 mod errors;
+mod fees;
+mod inspection;
 mod matching;
+mod migration;
+mod milestones;
+mod operators;
+mod receivable;
 mod registry;
+mod settlement;
+mod state;
 mod storage;
+mod title_receipt;
 mod trade;
+mod treasury;
 mod types;
 
-pub use contract::MarketplaceEscrowV1;
+pub use contract::{MarketplaceEscrowV1, MarketplaceEscrowV1Client};
 pub use errors::ContractError;
+pub use migration::CONTRACT_VERSION;
 pub use types::*;
 
 #[cfg(test)]
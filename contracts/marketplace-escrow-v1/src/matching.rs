@@ -1,10 +1,76 @@
-//! DvP check and 3-way matching logic
+//! DvP check and 3-way (or 4-way, with a bill of lading) matching logic
 
-use soroban_sdk::Env;
+use soroban_sdk::{Env, String, TryFromVal, Val, Vec};
 
+use crate::delivery;
 use crate::errors::ContractError;
+use crate::inspection;
 use crate::storage::DataKey;
-use crate::types::{CustomerInvoice, PurchaseOrder, WarehouseReceipt, FULFILLED};
+use crate::types::{
+    BillOfLading, CustomerInvoice, DocumentKind, DocumentPair, MatchCheck, MatchField, MatchReport,
+    ProductId, PurchaseOrder, TradeState, WarehouseReceipt,
+};
+
+/// Largest gap allowed between quantity × unit price and a document's total (0.1%)
+pub const LINE_TOTAL_TOLERANCE_BPS: u32 = 10;
+
+/// Largest quantity variance allowed between any two documents (5%)
+pub const QUANTITY_TOLERANCE_BPS: u32 = 500;
+
+/// Largest total-price variance allowed between any two documents (2%)
+pub const TOTAL_PRICE_TOLERANCE_BPS: u32 = 200;
+
+/// Largest unit-price variance allowed between any two documents (1%)
+pub const UNIT_PRICE_TOLERANCE_BPS: u32 = 100;
+
+/// Longest description a document may carry, in bytes
+pub const MAX_DESCRIPTION_LEN: u32 = 256;
+
+/// Check a document identifies its product
+///
+/// At least one identifier is required and none may be empty.
+pub fn check_document_identity(
+    description: &String,
+    product: &ProductId,
+) -> Result<(), ContractError> {
+    if description.len() > MAX_DESCRIPTION_LEN {
+        return Err(ContractError::DescriptionTooLong);
+    }
+
+    let identifiers = [&product.gtin, &product.hs_code, &product.sku];
+    if !has_identifier(product)
+        || identifiers
+            .iter()
+            .any(|id| id.as_ref().is_some_and(|id| id.is_empty()))
+    {
+        return Err(ContractError::InvalidProductId);
+    }
+
+    Ok(())
+}
+
+/// Check a document's quantity, unit price and total agree with each other
+///
+/// The tolerance absorbs rounding in per-unit prices.
+pub fn check_document_totals(
+    quantity: u64,
+    unit_price: i128,
+    total_price: i128,
+) -> Result<(), ContractError> {
+    if quantity == 0 || unit_price <= 0 || total_price <= 0 {
+        return Err(ContractError::InvalidAmount);
+    }
+
+    let line_total = (quantity as i128)
+        .checked_mul(unit_price)
+        .ok_or(ContractError::OverflowError)?;
+
+    if variance_bps(total_price, line_total)? > LINE_TOTAL_TOLERANCE_BPS as u128 {
+        return Err(ContractError::InconsistentDocumentTotals);
+    }
+
+    Ok(())
+}
 
 /// DvP check function - wrapper that calls three_way_match
 pub fn dvp_check(env: &Env, trade_id: u64) -> Result<(), ContractError> {
@@ -15,9 +81,9 @@ pub fn dvp_check(env: &Env, trade_id: u64) -> Result<(), ContractError> {
         .get(&DataKey::Trade(trade_id))
         .ok_or(ContractError::TradeNotFound)?;
 
-    // Verify trade is in FULFILLED state
+    // Verify trade is in Fulfilled state
     let trade: crate::types::TradeEscrow = trade;
-    if trade.state != FULFILLED {
+    if trade.state != TradeState::Fulfilled {
         return Err(ContractError::TradeNotFulfilled);
     }
 
@@ -46,109 +112,424 @@ pub fn dvp_check(env: &Env, trade_id: u64) -> Result<(), ContractError> {
         return Err(ContractError::WarehouseReceiptNotFound);
     }
 
+    // Shipment evidence, when the PO's Incoterm calls for it
+    if requires_bill_of_lading(env, trade_id)?
+        && !env
+            .storage()
+            .instance()
+            .has(&DataKey::BillOfLading(trade_id))
+    {
+        return Err(ContractError::BillOfLadingNotFound);
+    }
+
+    // Quality, when the PO calls for an inspection
+    inspection::require_passed_inspection(env, trade_id)?;
+
     // Call three-way match
     three_way_match(env, trade_id)?;
 
     Ok(())
 }
 
-/// Three-way matching with variance logic
-pub fn three_way_match(env: &Env, trade_id: u64) -> Result<(), ContractError> {
-    // Get all three documents
+/// Whether a trade's Incoterm calls for a four-way match including a bill of lading
+pub fn requires_bill_of_lading(env: &Env, trade_id: u64) -> Result<bool, ContractError> {
     let po: PurchaseOrder = env
         .storage()
         .instance()
         .get(&DataKey::PurchaseOrder(trade_id))
         .ok_or(ContractError::PurchaseOrderNotFound)?;
 
-    let ci: CustomerInvoice = env
+    Ok(delivery::requires_shipment_evidence(po.delivery.incoterm))
+}
+
+/// Three-way matching with variance logic
+///
+/// Fails with the error for the first comparison that is out of tolerance,
+/// checking product identifiers and descriptions, then quantities, total
+/// prices and unit prices. Shipped quantities on a bill of lading are
+/// checked last when the PO's Incoterm requires one.
+pub fn three_way_match(env: &Env, trade_id: u64) -> Result<(), ContractError> {
+    first_failure(&match_report(env, trade_id)?)
+}
+
+/// Check the documents a payment milestone relies on are on file and agree
+///
+/// Only the comparisons between the PO and the listed evidence are run, so
+/// a "shipped" milestone backed by a bill of lading checks shipped against
+/// ordered quantity without waiting for the invoice. Listed inspection
+/// certificates must have passed.
+pub fn milestone_check(
+    env: &Env,
+    trade_id: u64,
+    evidence: &Vec<DocumentKind>,
+) -> Result<(), ContractError> {
+    if evidence.contains(DocumentKind::InspectionCertificate) {
+        inspection::require_passed_inspection(env, trade_id)?;
+    }
+
+    first_failure(&milestone_report(env, trade_id, evidence)?)
+}
+
+/// Fail with the error for the first document missing from a report, or
+/// else for its first comparison that did not pass
+fn first_failure(report: &MatchReport) -> Result<(), ContractError> {
+    if let Some(kind) = report.missing.first() {
+        return Err(match kind {
+            DocumentKind::PurchaseOrder => ContractError::PurchaseOrderNotFound,
+            DocumentKind::CustomerInvoice => ContractError::CustomerInvoiceNotFound,
+            DocumentKind::WarehouseReceipt => ContractError::WarehouseReceiptNotFound,
+            DocumentKind::BillOfLading => ContractError::BillOfLadingNotFound,
+            DocumentKind::InspectionCertificate => ContractError::InspectionCertificateNotFound,
+        });
+    }
+
+    match report.checks.iter().find(|check| !check.passed) {
+        Some(check) => Err(match check.field {
+            MatchField::ProductId => ContractError::ProductIdMismatch,
+            MatchField::Description => ContractError::DescriptionMismatch,
+            MatchField::Quantity => ContractError::QuantityVarianceTooHigh,
+            MatchField::TotalPrice => ContractError::PriceVarianceTooHigh,
+            MatchField::UnitPrice => ContractError::UnitPriceVarianceTooHigh,
+        }),
+        None => Ok(()),
+    }
+}
+
+/// Compare every pair of documents on a trade without settling it
+///
+/// A required bill of lading only carries a quantity, so it is compared
+/// against each other document on that field alone. Documents not on file
+/// yet are listed as missing and the report does not pass.
+pub fn match_report(env: &Env, trade_id: u64) -> Result<MatchReport, ContractError> {
+    let po: PurchaseOrder = env
         .storage()
         .instance()
-        .get(&DataKey::CustomerInvoice(trade_id))
-        .ok_or(ContractError::CustomerInvoiceNotFound)?;
+        .get(&DataKey::PurchaseOrder(trade_id))
+        .ok_or(ContractError::PurchaseOrderNotFound)?;
+    let shipped = delivery::requires_shipment_evidence(po.delivery.incoterm);
 
-    let wr: WarehouseReceipt = env
+    build_report(env, trade_id, po, true, true, shipped)
+}
+
+/// Compare the PO against the evidence documents of a payment milestone
+pub fn milestone_report(
+    env: &Env,
+    trade_id: u64,
+    evidence: &Vec<DocumentKind>,
+) -> Result<MatchReport, ContractError> {
+    let po: PurchaseOrder = env
         .storage()
         .instance()
-        .get(&DataKey::WarehouseReceipt(trade_id))
-        .ok_or(ContractError::WarehouseReceiptNotFound)?;
+        .get(&DataKey::PurchaseOrder(trade_id))
+        .ok_or(ContractError::PurchaseOrderNotFound)?;
 
-    // ===== MATCH 1: DESCRIPTION (EXACT MATCH) =====
-    if po.po_description != ci.ci_description {
-        return Err(ContractError::DescriptionMismatch);
-    }
-    if po.po_description != wr.wr_description {
-        return Err(ContractError::DescriptionMismatch);
-    }
-    if ci.ci_description != wr.wr_description {
-        return Err(ContractError::DescriptionMismatch);
-    }
+    build_report(
+        env,
+        trade_id,
+        po,
+        evidence.contains(DocumentKind::CustomerInvoice),
+        evidence.contains(DocumentKind::WarehouseReceipt),
+        evidence.contains(DocumentKind::BillOfLading),
+    )
+}
+
+/// Compare the PO with whichever of the CI, WR and BL are asked for
+///
+/// Requested documents that are not on file are listed as missing, and the
+/// comparisons against them are left out.
+fn build_report(
+    env: &Env,
+    trade_id: u64,
+    po: PurchaseOrder,
+    with_invoice: bool,
+    with_receipt: bool,
+    with_bill: bool,
+) -> Result<MatchReport, ContractError> {
+    let mut missing = Vec::new(env);
+    let ci: Option<CustomerInvoice> = load_document(
+        env,
+        DataKey::CustomerInvoice(trade_id),
+        with_invoice.then_some(DocumentKind::CustomerInvoice),
+        &mut missing,
+    );
+    let wr: Option<WarehouseReceipt> = load_document(
+        env,
+        DataKey::WarehouseReceipt(trade_id),
+        with_receipt.then_some(DocumentKind::WarehouseReceipt),
+        &mut missing,
+    );
+    let bill: Option<BillOfLading> = load_document(
+        env,
+        DataKey::BillOfLading(trade_id),
+        with_bill.then_some(DocumentKind::BillOfLading),
+        &mut missing,
+    );
+
+    let mut checks = Vec::new(env);
+
+    // ===== MATCH 1: PRODUCT IDENTITY (IDENTIFIERS, THEN DESCRIPTION) =====
+    let products = [
+        Some(&po.product),
+        ci.as_ref().map(|ci| &ci.product),
+        wr.as_ref().map(|wr| &wr.product),
+    ];
+    let descriptions = [
+        Some(&po.po_description),
+        ci.as_ref().map(|ci| &ci.ci_description),
+        wr.as_ref().map(|wr| &wr.wr_description),
+    ];
+    push_identity_checks(&mut checks, MatchField::ProductId, products, products_match);
+    push_identity_checks(
+        &mut checks,
+        MatchField::Description,
+        descriptions,
+        descriptions_match,
+    );
 
     // ===== MATCH 2: QUANTITY (≤5% VARIANCE) =====
-    check_quantity_variance(po.quantity, ci.quantity)?;
-    check_quantity_variance(po.quantity, wr.quantity)?;
-    check_quantity_variance(ci.quantity, wr.quantity)?;
+    let quantities = [
+        Some(po.quantity as i128),
+        ci.as_ref().map(|ci| ci.quantity as i128),
+        wr.as_ref().map(|wr| wr.quantity as i128),
+    ];
+    push_variance_checks(
+        &mut checks,
+        MatchField::Quantity,
+        quantities,
+        QUANTITY_TOLERANCE_BPS,
+    )?;
 
     // ===== MATCH 3: TOTAL PRICE (≤2% VARIANCE) =====
-    check_price_variance(po.total_price, ci.total_price)?;
-    check_price_variance(po.total_price, wr.total_price)?;
-    check_price_variance(ci.total_price, wr.total_price)?;
+    let totals = [
+        Some(po.total_price),
+        ci.as_ref().map(|ci| ci.total_price),
+        wr.as_ref().map(|wr| wr.total_price),
+    ];
+    push_variance_checks(
+        &mut checks,
+        MatchField::TotalPrice,
+        totals,
+        TOTAL_PRICE_TOLERANCE_BPS,
+    )?;
 
-    Ok(())
+    // ===== MATCH 4: UNIT PRICE (≤1% VARIANCE) =====
+    let unit_prices = [
+        Some(po.unit_price),
+        ci.as_ref().map(|ci| ci.unit_price),
+        wr.as_ref().map(|wr| wr.unit_price),
+    ];
+    push_variance_checks(
+        &mut checks,
+        MatchField::UnitPrice,
+        unit_prices,
+        UNIT_PRICE_TOLERANCE_BPS,
+    )?;
+
+    // ===== MATCH 5: SHIPPED QUANTITY (≤5% VARIANCE, FOUR-WAY ONLY) =====
+    if let Some(bill) = bill {
+        let shipped = bill.quantity as i128;
+        for (pair, expected) in [
+            (DocumentPair::PoBl, quantities[0]),
+            (DocumentPair::CiBl, quantities[1]),
+            (DocumentPair::WrBl, quantities[2]),
+        ] {
+            if let Some(expected) = expected {
+                push_variance_check(
+                    &mut checks,
+                    MatchField::Quantity,
+                    pair,
+                    expected,
+                    shipped,
+                    QUANTITY_TOLERANCE_BPS,
+                )?;
+            }
+        }
+    }
+
+    let passed = missing.is_empty() && checks.iter().all(|check| check.passed);
+
+    Ok(MatchReport {
+        trade_id,
+        checks,
+        missing,
+        passed,
+    })
 }
 
-/// Check if quantity variance is within 5% tolerance
-fn check_quantity_variance(val1: u64, val2: u64) -> Result<(), ContractError> {
-    if val1 == 0 {
-        return Err(ContractError::DivisionByZero);
+/// Load a document if it is needed, noting its `kind` as missing when it is not on file
+fn load_document<T: TryFromVal<Env, Val>>(
+    env: &Env,
+    key: DataKey,
+    kind: Option<DocumentKind>,
+    missing: &mut Vec<DocumentKind>,
+) -> Option<T> {
+    let kind = kind?;
+    let document = env.storage().instance().get(&key);
+    if document.is_none() {
+        missing.push_back(kind);
     }
 
-    let diff = if val1 > val2 {
-        val1 - val2
-    } else {
-        val2 - val1
-    };
+    document
+}
 
-    // Calculate variance percentage: (diff / val1) * 100
-    // To avoid floating point, we use: (diff * 100) / val1
-    let variance_percent = (diff as u128)
-        .checked_mul(100)
-        .ok_or(ContractError::OverflowError)?
-        / (val1 as u128);
+/// PO/CI, PO/WR and CI/WR, as indices into `[po, ci, wr]`
+const DOCUMENT_PAIRS: [(DocumentPair, usize, usize); 3] = [
+    (DocumentPair::PoCi, 0, 1),
+    (DocumentPair::PoWr, 0, 2),
+    (DocumentPair::CiWr, 1, 2),
+];
+
+/// Compare PO/CI, PO/WR and CI/WR values of a field that must match exactly
+///
+/// Pairs with a document left out of the comparison are skipped.
+fn push_identity_checks<T>(
+    checks: &mut Vec<MatchCheck>,
+    field: MatchField,
+    values: [Option<&T>; 3],
+    matches: fn(&T, &T) -> bool,
+) {
+    for (pair, first, second) in DOCUMENT_PAIRS {
+        let (Some(first), Some(second)) = (values[first], values[second]) else {
+            continue;
+        };
+        let passed = matches(first, second);
 
-    if variance_percent > 5 {
-        return Err(ContractError::QuantityVarianceTooHigh);
+        checks.push_back(MatchCheck {
+            field,
+            pair,
+            variance_bps: if passed { 0 } else { 10000 },
+            tolerance_bps: 0,
+            passed,
+        });
     }
+}
 
-    Ok(())
+/// Whether two documents identify the same product
+///
+/// Identifiers carried by both must agree and at least one must be shared.
+/// Documents recorded before identifiers existed carry none; against those
+/// the description check decides.
+fn products_match(first: &ProductId, second: &ProductId) -> bool {
+    let mut shared = 0;
+    for (a, b) in [
+        (&first.gtin, &second.gtin),
+        (&first.hs_code, &second.hs_code),
+        (&first.sku, &second.sku),
+    ] {
+        if let (Some(a), Some(b)) = (a, b) {
+            if a != b {
+                return false;
+            }
+            shared += 1;
+        }
+    }
+
+    shared > 0 || !has_identifier(first) || !has_identifier(second)
 }
 
-/// Check if price variance is within 2% tolerance
-fn check_price_variance(val1: i128, val2: i128) -> Result<(), ContractError> {
-    if val1 == 0 {
-        return Err(ContractError::DivisionByZero);
+fn has_identifier(product: &ProductId) -> bool {
+    product.gtin.is_some() || product.hs_code.is_some() || product.sku.is_some()
+}
+
+/// Whether two descriptions are equal ignoring ASCII case and whitespace runs
+fn descriptions_match(first: &String, second: &String) -> bool {
+    let mut first_buf = [0u8; MAX_DESCRIPTION_LEN as usize];
+    let mut second_buf = [0u8; MAX_DESCRIPTION_LEN as usize];
+
+    match (
+        normalize_description(first, &mut first_buf),
+        normalize_description(second, &mut second_buf),
+    ) {
+        (Some(first_len), Some(second_len)) => first_buf[..first_len] == second_buf[..second_len],
+        // Oversized legacy descriptions must match exactly
+        _ => first == second,
     }
+}
 
-    // Get absolute difference
-    let diff = if val1 > val2 {
-        val1 - val2
-    } else {
-        val2 - val1
-    };
+/// Lowercase a description and collapse whitespace runs into single spaces
+///
+/// Returns the normalized length, or `None` if the description does not fit.
+fn normalize_description(
+    description: &String,
+    buf: &mut [u8; MAX_DESCRIPTION_LEN as usize],
+) -> Option<usize> {
+    let len = description.len() as usize;
+    if len > buf.len() {
+        return None;
+    }
+    description.copy_into_slice(&mut buf[..len]);
 
-    // Calculate variance percentage: (diff / val1) * 100
-    // Convert to u128 for calculation
-    let abs_val1 = val1.abs() as u128;
-    let abs_diff = diff.abs() as u128;
+    // Compact in place; the write position never passes the read position
+    let mut out = 0;
+    let mut pending_space = false;
+    for i in 0..len {
+        let byte = buf[i];
+        if byte.is_ascii_whitespace() {
+            pending_space = out > 0;
+            continue;
+        }
+        if pending_space {
+            buf[out] = b' ';
+            out += 1;
+            pending_space = false;
+        }
+        buf[out] = byte.to_ascii_lowercase();
+        out += 1;
+    }
 
-    let variance_percent = abs_diff
-        .checked_mul(100)
-        .ok_or(ContractError::OverflowError)?
-        / abs_val1;
+    Some(out)
+}
 
-    if variance_percent > 2 {
-        return Err(ContractError::PriceVarianceTooHigh);
+/// Compare PO/CI, PO/WR and CI/WR values of one field
+///
+/// Pairs with a document left out of the comparison are skipped.
+fn push_variance_checks(
+    checks: &mut Vec<MatchCheck>,
+    field: MatchField,
+    values: [Option<i128>; 3],
+    tolerance_bps: u32,
+) -> Result<(), ContractError> {
+    for (pair, first, second) in DOCUMENT_PAIRS {
+        if let (Some(first), Some(second)) = (values[first], values[second]) {
+            push_variance_check(checks, field, pair, first, second, tolerance_bps)?;
+        }
     }
 
     Ok(())
 }
+
+/// Compare one field between a pair of documents
+fn push_variance_check(
+    checks: &mut Vec<MatchCheck>,
+    field: MatchField,
+    pair: DocumentPair,
+    first: i128,
+    second: i128,
+    tolerance_bps: u32,
+) -> Result<(), ContractError> {
+    let variance_bps = variance_bps(first, second)?.min(u32::MAX as u128) as u32;
+
+    checks.push_back(MatchCheck {
+        field,
+        pair,
+        variance_bps,
+        tolerance_bps,
+        passed: variance_bps <= tolerance_bps,
+    });
+
+    Ok(())
+}
+
+/// Variance of `actual` from `expected` in basis points
+fn variance_bps(expected: i128, actual: i128) -> Result<u128, ContractError> {
+    if expected == 0 {
+        return Err(ContractError::DivisionByZero);
+    }
+
+    let diff = expected.abs_diff(actual);
+
+    Ok(diff
+        .checked_mul(10000)
+        .ok_or(ContractError::OverflowError)?
+        / expected.unsigned_abs())
+}
@@ -0,0 +1,366 @@
+//! Versioned storage migrations run after a wasm upgrade
+
+use soroban_sdk::{contracttype, Address, BytesN, Env, String, Vec};
+
+use crate::errors::ContractError;
+use crate::storage::{self, DataKey};
+use crate::types::{
+    CustomerInvoice, DeliveryTerms, FeePayer, Incoterm, ProductId, PurchaseOrder, StateChange,
+    TradeEscrow, TradeState, WarehouseReceipt,
+};
+
+/// Storage schema version written by this build of the contract
+pub const CONTRACT_VERSION: u32 = 2;
+
+/// Trade record layout stored by contract version 1
+#[contracttype]
+#[derive(Clone, Debug, Eq, PartialEq)]
+pub struct TradeEscrowV1 {
+    pub trade_id: u64,
+    pub buyer: Address,
+    pub seller: Address,
+    pub amount: i128,
+    /// Stored as a bare `u32`, which `TradeState` decodes unchanged
+    pub state: TradeState,
+    pub created_at: u64,
+    pub fulfilled_at: u64,
+    pub settled_at: u64,
+    pub marketplace_fee: i128,
+    pub escrow_balance: i128,
+}
+
+impl TradeEscrowV1 {
+    /// Reconstruct the state history of a version 1 trade
+    ///
+    /// Version 1 kept no log, so entries are rebuilt from the lifecycle
+    /// timestamps. Rejections and cancellations were not timestamped and
+    /// are dated at the last known change.
+    fn history(&self, env: &Env) -> Vec<StateChange> {
+        let mut history = Vec::new(env);
+        history.push_back(StateChange {
+            from: TradeState::Ordered,
+            to: TradeState::Ordered,
+            timestamp: self.created_at,
+            actor: self.buyer.clone(),
+        });
+
+        match self.state {
+            // Version 1 had no inspections, so no trade was refunded
+            TradeState::Ordered | TradeState::Refunded => {}
+            TradeState::Fulfilled | TradeState::Settled => {
+                history.push_back(StateChange {
+                    from: TradeState::Ordered,
+                    to: TradeState::Fulfilled,
+                    timestamp: self.fulfilled_at,
+                    actor: self.seller.clone(),
+                });
+                if self.state == TradeState::Settled {
+                    history.push_back(StateChange {
+                        from: TradeState::Fulfilled,
+                        to: TradeState::Settled,
+                        timestamp: self.settled_at,
+                        actor: self.buyer.clone(),
+                    });
+                }
+            }
+            TradeState::Rejected | TradeState::Cancelled => {
+                let actor = if self.state == TradeState::Rejected {
+                    self.seller.clone()
+                } else {
+                    self.buyer.clone()
+                };
+                history.push_back(StateChange {
+                    from: TradeState::Ordered,
+                    to: self.state,
+                    timestamp: self.created_at,
+                    actor,
+                });
+            }
+        }
+        history
+    }
+
+    /// Re-encode a version 1 trade into the current layout
+    ///
+    /// Version 1 never held funds, so migrated trades stay settled off-chain.
+    fn into_current(self) -> TradeEscrow {
+        let updated_at = self.created_at.max(self.fulfilled_at).max(self.settled_at);
+
+        TradeEscrow {
+            trade_id: self.trade_id,
+            buyer: self.buyer,
+            seller: self.seller,
+            amount: self.amount,
+            state: self.state,
+            created_at: self.created_at,
+            fulfilled_at: self.fulfilled_at,
+            settled_at: self.settled_at,
+            updated_at,
+            marketplace_fee: self.marketplace_fee,
+            escrow_balance: self.escrow_balance,
+            payment_token: None,
+            fee_payer: FeePayer::Buyer,
+            seller_fee: 0,
+            broker: None,
+            broker_commission_rate: 0,
+            broker_commission: 0,
+            po_version: 1,
+            milestones_released: 0,
+            released_to_seller: 0,
+            advance_paid: 0,
+            bond_balance: 0,
+            bond_deadline: 0,
+            credit_reserved: 0,
+            payment_due: 0,
+            early_payment_discount: 0,
+            late_payment_penalty: 0,
+            receivable_contract: None,
+            receivable_token_id: 0,
+            title_receipt_contract: None,
+            title_receipt_token_id: 0,
+        }
+    }
+}
+
+/// Purchase order layout stored by contract version 1
+#[contracttype]
+#[derive(Clone, Debug, Eq, PartialEq)]
+pub struct PurchaseOrderV1 {
+    pub po_description: String,
+    pub quantity: u64,
+    pub unit_price: i128,
+    pub total_price: i128,
+    pub po_json_ipfs_hash: String,
+    pub created_by: Address,
+    pub created_at: u64,
+}
+
+/// Customer invoice layout stored by contract version 1
+#[contracttype]
+#[derive(Clone, Debug, Eq, PartialEq)]
+pub struct CustomerInvoiceV1 {
+    pub ci_description: String,
+    pub quantity: u64,
+    pub unit_price: i128,
+    pub total_price: i128,
+    pub ci_json_ipfs_hash: String,
+    pub created_by: Address,
+    pub created_at: u64,
+}
+
+/// Warehouse receipt layout stored by contract version 1
+#[contracttype]
+#[derive(Clone, Debug, Eq, PartialEq)]
+pub struct WarehouseReceiptV1 {
+    pub wr_description: String,
+    pub quantity: u64,
+    pub unit_price: i128,
+    pub total_price: i128,
+    pub wr_json_ipfs_hash: String,
+    pub warehouse_location: String,
+    pub created_by: Address,
+    pub created_at: u64,
+}
+
+/// Version 1 documents carry no product identifiers
+fn no_product_id() -> ProductId {
+    ProductId {
+        gtin: None,
+        hs_code: None,
+        sku: None,
+    }
+}
+
+/// Version 1 documents carry no content hash; the all-zero digest marks that
+fn no_content_hash(env: &Env) -> BytesN<32> {
+    BytesN::from_array(env, &[0u8; 32])
+}
+
+/// Version 1 orders carry no delivery terms; EXW with an unbounded window
+/// needs no evidence beyond the original three documents
+fn no_delivery_terms(env: &Env) -> DeliveryTerms {
+    DeliveryTerms {
+        incoterm: Incoterm::Exw,
+        named_place: String::from_str(env, ""),
+        window_start: 0,
+        window_end: u64::MAX,
+    }
+}
+
+impl PurchaseOrderV1 {
+    fn into_current(self, env: &Env) -> PurchaseOrder {
+        PurchaseOrder {
+            po_description: self.po_description,
+            product: no_product_id(),
+            quantity: self.quantity,
+            unit_price: self.unit_price,
+            total_price: self.total_price,
+            po_json_ipfs_hash: self.po_json_ipfs_hash,
+            po_json_hash: no_content_hash(env),
+            delivery: no_delivery_terms(env),
+            requires_inspection: false,
+            inspection_agency: None,
+            payment_schedule: Vec::new(env),
+            performance_bond_rate: 0,
+            payment_terms_days: 0,
+            early_payment_discount_bps: 0,
+            discount_window_days: 0,
+            late_penalty_bps_per_day: 0,
+            requires_title_receipt: false,
+            created_by: self.created_by,
+            created_at: self.created_at,
+        }
+    }
+}
+
+impl CustomerInvoiceV1 {
+    fn into_current(self, env: &Env) -> CustomerInvoice {
+        CustomerInvoice {
+            ci_description: self.ci_description,
+            product: no_product_id(),
+            quantity: self.quantity,
+            unit_price: self.unit_price,
+            total_price: self.total_price,
+            ci_json_ipfs_hash: self.ci_json_ipfs_hash,
+            ci_json_hash: no_content_hash(env),
+            incoterm: Incoterm::Exw,
+            named_place: String::from_str(env, ""),
+            created_by: self.created_by,
+            created_at: self.created_at,
+        }
+    }
+}
+
+impl WarehouseReceiptV1 {
+    fn into_current(self, env: &Env) -> WarehouseReceipt {
+        WarehouseReceipt {
+            wr_description: self.wr_description,
+            product: no_product_id(),
+            quantity: self.quantity,
+            unit_price: self.unit_price,
+            total_price: self.total_price,
+            wr_json_ipfs_hash: self.wr_json_ipfs_hash,
+            wr_json_hash: no_content_hash(env),
+            warehouse_location: self.warehouse_location,
+            lot: String::from_str(env, ""),
+            created_by: self.created_by,
+            created_at: self.created_at,
+        }
+    }
+}
+
+/// Get the schema version of the data currently in storage
+///
+/// Deployments made before versioning was introduced have no version key
+/// and are treated as version 1.
+pub fn get_version(env: &Env) -> u32 {
+    env.storage()
+        .instance()
+        .get(&DataKey::ContractVersion)
+        .unwrap_or(1)
+}
+
+/// Record the schema version of the data currently in storage
+pub fn set_version(env: &Env, version: u32) {
+    env.storage()
+        .instance()
+        .set(&DataKey::ContractVersion, &version);
+}
+
+/// Fail unless stored data matches the layout this build reads and writes
+pub fn require_current_version(env: &Env) -> Result<(), ContractError> {
+    let version = get_version(env);
+
+    if version > CONTRACT_VERSION {
+        return Err(ContractError::UnsupportedContractVersion);
+    }
+
+    if version < CONTRACT_VERSION {
+        return Err(ContractError::MigrationRequired);
+    }
+
+    Ok(())
+}
+
+/// Run pending migration steps over at most `batch_size` trades
+///
+/// Each step upgrades every stored trade from one version to the next.
+/// Progress is kept in `MigrationCursor`, so large deployments can be
+/// migrated over several transactions. Returns `true` once storage is at
+/// `CONTRACT_VERSION`.
+pub fn migrate(env: &Env, batch_size: u32) -> Result<bool, ContractError> {
+    if batch_size == 0 {
+        return Err(ContractError::InvalidMigrationBatch);
+    }
+
+    let mut version = get_version(env);
+
+    if version > CONTRACT_VERSION {
+        return Err(ContractError::UnsupportedContractVersion);
+    }
+
+    if version == CONTRACT_VERSION {
+        return Ok(true);
+    }
+
+    let next_trade_id: u64 = env
+        .storage()
+        .instance()
+        .get(&DataKey::NextTradeId)
+        .unwrap_or(1);
+
+    let mut cursor: u64 = env
+        .storage()
+        .instance()
+        .get(&DataKey::MigrationCursor)
+        .unwrap_or(1);
+
+    let end = cursor.saturating_add(batch_size as u64).min(next_trade_id);
+
+    while cursor < end {
+        match version {
+            1 => migrate_trade_v1(env, cursor),
+            _ => return Err(ContractError::UnsupportedContractVersion),
+        }
+        cursor += 1;
+    }
+
+    if cursor >= next_trade_id {
+        // Step complete, move on to the next version
+        version += 1;
+        set_version(env, version);
+        env.storage().instance().remove(&DataKey::MigrationCursor);
+    } else {
+        env.storage()
+            .instance()
+            .set(&DataKey::MigrationCursor, &cursor);
+    }
+
+    Ok(version == CONTRACT_VERSION)
+}
+
+/// Step 1 -> 2: re-encode the trade record and its documents in the version 2 layout
+fn migrate_trade_v1(env: &Env, trade_id: u64) {
+    let storage = env.storage().instance();
+
+    let key = DataKey::Trade(trade_id);
+    if let Some(old) = storage.get::<_, TradeEscrowV1>(&key) {
+        storage::set_persistent(env, &DataKey::TradeHistory(trade_id), &old.history(env));
+        storage.set(&key, &old.into_current());
+    }
+
+    let key = DataKey::PurchaseOrder(trade_id);
+    if let Some(old) = storage.get::<_, PurchaseOrderV1>(&key) {
+        storage.set(&key, &old.into_current(env));
+    }
+
+    let key = DataKey::CustomerInvoice(trade_id);
+    if let Some(old) = storage.get::<_, CustomerInvoiceV1>(&key) {
+        storage.set(&key, &old.into_current(env));
+    }
+
+    let key = DataKey::WarehouseReceipt(trade_id);
+    if let Some(old) = storage.get::<_, WarehouseReceiptV1>(&key) {
+        storage.set(&key, &old.into_current(env));
+    }
+}
@@ -0,0 +1,189 @@
+//! Staged payment schedules released milestone by milestone
+
+use soroban_sdk::{Address, Env, Vec};
+
+use crate::advance;
+use crate::approval;
+use crate::delivery;
+use crate::errors::ContractError;
+use crate::matching;
+use crate::storage::DataKey;
+use crate::trade;
+use crate::types::{
+    ApprovalAction, DocumentKind, Milestone, MilestoneAmount, PurchaseOrder, PurchaseOrderDetails,
+    TradeEscrow, TradeState,
+};
+
+/// Most milestones a payment schedule may have
+pub const MAX_MILESTONES: u32 = 10;
+
+/// Check a PO's payment schedule adds up to its total
+///
+/// Percentage milestones may leave up to one unit of rounding dust each,
+/// which the final release picks up. Evidence must be a document the PO
+/// will actually produce: a bill of lading only under Incoterms that call
+/// for one, an inspection certificate only when inspection is required.
+/// An empty schedule pays the whole amount at settlement.
+pub fn check_payment_schedule(details: &PurchaseOrderDetails) -> Result<(), ContractError> {
+    let schedule = &details.payment_schedule;
+    if schedule.len() > MAX_MILESTONES {
+        return Err(ContractError::InvalidPaymentSchedule);
+    }
+
+    let mut scheduled: i128 = 0;
+    let mut percentages: i128 = 0;
+    for milestone in schedule.iter() {
+        let amount = resolve(&milestone, details.total_price)?;
+        if amount <= 0 {
+            return Err(ContractError::InvalidPaymentSchedule);
+        }
+        if let MilestoneAmount::Percentage(_) = milestone.amount {
+            percentages += 1;
+        }
+        scheduled = scheduled
+            .checked_add(amount)
+            .ok_or(ContractError::InvalidPaymentSchedule)?;
+
+        for kind in milestone.evidence.iter() {
+            let produced = match kind {
+                DocumentKind::BillOfLading => {
+                    delivery::requires_shipment_evidence(details.delivery.incoterm)
+                }
+                DocumentKind::InspectionCertificate => details.requires_inspection,
+                _ => true,
+            };
+            if !produced {
+                return Err(ContractError::InvalidPaymentSchedule);
+            }
+        }
+    }
+
+    let dust = details.total_price - scheduled;
+    if !schedule.is_empty() && (dust < 0 || dust >= percentages.max(1)) {
+        return Err(ContractError::InvalidPaymentSchedule);
+    }
+
+    Ok(())
+}
+
+/// Release the next milestone of a trade's payment schedule to the seller
+///
+/// The milestone's evidence must be on file and agree with the PO. The
+/// seller receives the milestone's share of their net payout, so the fee
+/// and any broker commission are only paid out at settlement. Releasing the
+/// final milestone settles the trade through the usual DvP check. Over the
+/// buyer's approval threshold the release is held until its approvers sign
+/// off. Returns the index of the milestone released.
+pub fn release_milestone(
+    env: &Env,
+    buyer: &Address,
+    trade_id: u64,
+    platform_treasury: &Address,
+) -> Result<u32, ContractError> {
+    let mut trade: TradeEscrow = env
+        .storage()
+        .instance()
+        .get(&DataKey::Trade(trade_id))
+        .ok_or(ContractError::TradeNotFound)?;
+
+    // Verify caller is buyer
+    if &trade.buyer != buyer {
+        return Err(ContractError::NotBuyer);
+    }
+
+    // Milestones fall between ordering and settlement
+    if trade.state != TradeState::Ordered && trade.state != TradeState::Fulfilled {
+        return Err(ContractError::InvalidTradeState);
+    }
+    if trade.escrow_balance == 0 {
+        return Err(ContractError::EscrowNotFunded);
+    }
+    if trade.escrow_balance < trade::required_escrow(&trade) {
+        return Err(ContractError::InsufficientEscrowFunding);
+    }
+    approval::require_none_pending(env, trade_id)?;
+
+    let po: PurchaseOrder = env
+        .storage()
+        .instance()
+        .get(&DataKey::PurchaseOrder(trade_id))
+        .ok_or(ContractError::PurchaseOrderNotFound)?;
+    if po.payment_schedule.is_empty() {
+        return Err(ContractError::NoPaymentSchedule);
+    }
+
+    let index = trade.milestones_released;
+    let milestone = po
+        .payment_schedule
+        .get(index)
+        .ok_or(ContractError::InvalidTradeState)?;
+
+    // The last stage settles the trade and pays whatever is left
+    if index + 1 == po.payment_schedule.len() {
+        trade::accept_trade(env, buyer, trade_id, platform_treasury)?;
+        return Ok(index);
+    }
+
+    matching::milestone_check(env, trade_id, &milestone.evidence)?;
+
+    if approval::requires_approval(env, &trade) {
+        approval::hold(env, trade_id, ApprovalAction::ReleaseMilestone(index))?;
+        return Ok(index);
+    }
+
+    pay_milestone(env, &mut trade, &po, &milestone)?;
+
+    env.storage()
+        .instance()
+        .set(&DataKey::Trade(trade_id), &trade);
+
+    Ok(index)
+}
+
+/// Pay the seller a milestone's share of their net payout from escrow
+pub fn pay_milestone(
+    env: &Env,
+    trade: &mut TradeEscrow,
+    po: &PurchaseOrder,
+    milestone: &Milestone,
+) -> Result<(), ContractError> {
+    let net = trade.amount - trade.seller_fee - trade.broker_commission;
+    let payout = resolve(milestone, po.total_price)? * net / trade.amount;
+    advance::pay_seller(env, trade, payout)?;
+
+    trade.escrow_balance -= payout;
+    trade.released_to_seller += payout;
+    trade.milestones_released += 1;
+    trade.updated_at = env.ledger().timestamp();
+
+    Ok(())
+}
+
+/// Remaining milestones of a trade, in release order
+pub fn pending_milestones(env: &Env, trade_id: u64) -> Result<Vec<Milestone>, ContractError> {
+    let trade: TradeEscrow = env
+        .storage()
+        .instance()
+        .get(&DataKey::Trade(trade_id))
+        .ok_or(ContractError::TradeNotFound)?;
+    let po: PurchaseOrder = env
+        .storage()
+        .instance()
+        .get(&DataKey::PurchaseOrder(trade_id))
+        .ok_or(ContractError::PurchaseOrderNotFound)?;
+
+    Ok(po
+        .payment_schedule
+        .slice(trade.milestones_released.min(po.payment_schedule.len())..))
+}
+
+/// Amount of the order total a milestone stands for
+fn resolve(milestone: &Milestone, total: i128) -> Result<i128, ContractError> {
+    match milestone.amount {
+        MilestoneAmount::Percentage(bps) => total
+            .checked_mul(bps as i128)
+            .map(|share| share / 10000)
+            .ok_or(ContractError::InvalidPaymentSchedule),
+        MilestoneAmount::Fixed(amount) => Ok(amount),
+    }
+}
@@ -0,0 +1,144 @@
+//! Operator accounts acting on trades for a participant
+//!
+//! A registered buyer or seller can delegate trade actions to staff
+//! addresses instead of sharing its own key: creating trades up to an
+//! amount, fulfilling orders and accepting trades. Each delegation lapses at
+//! its expiry. An operator acts for one participant, so the trade
+//! entrypoints can take either the participant or its operator, and the
+//! documents and state history record whichever of them acted.
+
+use soroban_sdk::{Address, Env};
+
+use crate::errors::ContractError;
+use crate::registry;
+use crate::storage::DataKey;
+use crate::types::{OperatorGrant, OperatorPermissions};
+
+/// Trade action a caller wants to take for a participant
+pub enum OperatorAction {
+    /// Open a trade for the given total price
+    CreateTrade(i128),
+    FulfillOrder,
+    AcceptTrade,
+}
+
+/// Get the delegation an operator acts under, if any
+pub fn get_operator(env: &Env, operator: &Address) -> Option<OperatorGrant> {
+    env.storage()
+        .instance()
+        .get(&DataKey::Operator(operator.clone()))
+}
+
+/// Delegate trade actions to an operator, replacing any earlier delegation
+///
+/// The participant must be a registered buyer or seller, and the operator
+/// must not already act for another participant.
+pub fn grant_operator(
+    env: &Env,
+    participant: &Address,
+    operator: &Address,
+    permissions: OperatorPermissions,
+) -> Result<(), ContractError> {
+    if registry::get_buyer_info(env, participant).is_err() {
+        registry::get_seller_info(env, participant)?;
+    }
+    if operator == participant
+        || permissions.max_trade_amount < 0
+        || permissions.expires_at <= env.ledger().timestamp()
+    {
+        return Err(ContractError::InvalidOperatorGrant);
+    }
+    if let Some(grant) = get_operator(env, operator) {
+        if &grant.participant != participant {
+            return Err(ContractError::OperatorAlreadyAssigned);
+        }
+    }
+
+    let grant = OperatorGrant {
+        participant: participant.clone(),
+        permissions,
+        granted_at: env.ledger().timestamp(),
+    };
+    env.storage()
+        .instance()
+        .set(&DataKey::Operator(operator.clone()), &grant);
+
+    Ok(())
+}
+
+/// Withdraw a participant's delegation to an operator
+pub fn revoke_operator(
+    env: &Env,
+    participant: &Address,
+    operator: &Address,
+) -> Result<(), ContractError> {
+    let grant = get_operator(env, operator).ok_or(ContractError::NotOperator)?;
+    if &grant.participant != participant {
+        return Err(ContractError::NotOperator);
+    }
+
+    env.storage()
+        .instance()
+        .remove(&DataKey::Operator(operator.clone()));
+
+    Ok(())
+}
+
+/// Whether a caller may take an action for a participant
+///
+/// True for the participant itself and for its operators whose delegation
+/// covers the action. Fails when the caller is the participant's operator
+/// but its delegation has lapsed or doesn't cover the action.
+pub fn acts_for(
+    env: &Env,
+    caller: &Address,
+    participant: &Address,
+    action: OperatorAction,
+) -> Result<bool, ContractError> {
+    if caller == participant {
+        return Ok(true);
+    }
+    let Some(grant) = get_operator(env, caller) else {
+        return Ok(false);
+    };
+    if &grant.participant != participant {
+        return Ok(false);
+    }
+
+    let permissions = grant.permissions;
+    if permissions.expires_at <= env.ledger().timestamp() {
+        return Err(ContractError::OperatorExpired);
+    }
+    let permitted = match action {
+        OperatorAction::CreateTrade(amount) => amount <= permissions.max_trade_amount,
+        OperatorAction::FulfillOrder => permissions.fulfill_orders,
+        OperatorAction::AcceptTrade => permissions.accept_trades,
+    };
+    if !permitted {
+        return Err(ContractError::OperatorNotPermitted);
+    }
+
+    Ok(true)
+}
+
+/// Work out which buyer a caller creates a trade for
+///
+/// A registered buyer creates trades for itself; an operator creates them
+/// for the participant that delegated to it, within its trade limit. Any
+/// other caller is returned as is and fails the usual buyer checks.
+pub fn resolve_buyer(env: &Env, caller: &Address, amount: i128) -> Result<Address, ContractError> {
+    if registry::get_buyer_info(env, caller).is_ok() {
+        return Ok(caller.clone());
+    }
+    let Some(grant) = get_operator(env, caller) else {
+        return Ok(caller.clone());
+    };
+
+    acts_for(
+        env,
+        caller,
+        &grant.participant,
+        OperatorAction::CreateTrade(amount),
+    )?;
+    Ok(grant.participant)
+}
@@ -0,0 +1,92 @@
+//! Receivable NFTs that let a seller sell a fulfilled trade's payout
+
+use soroban_sdk::{contractclient, Address, Env};
+
+use crate::advance;
+use crate::errors::ContractError;
+use crate::state;
+use crate::storage::DataKey;
+use crate::types::{PurchaseOrder, TradeEscrow, TradeState};
+
+/// Receivable NFT contract interface
+///
+/// Matches the nft-enumerable contract deployed with the marketplace as its
+/// owner, so only the marketplace can mint. Only the generated client is used.
+#[allow(dead_code)]
+#[contractclient(name = "ReceivableClient")]
+pub trait ReceivableNft {
+    /// Mint the next token ID to an account
+    fn mint(env: Env, to: Address) -> u32;
+
+    /// Current holder of a token
+    fn owner_of(env: Env, token_id: u32) -> Address;
+}
+
+/// Get the NFT contract receivables are minted on, if one is configured
+pub fn get_receivable_contract(env: &Env) -> Option<Address> {
+    env.storage().instance().get(&DataKey::ReceivableContract)
+}
+
+/// Mint the seller an NFT for the payout of a fulfilled trade
+///
+/// Whoever holds the NFT at settlement is paid the seller's share, so the
+/// seller can transfer it to a factor for early liquidity. Trades with a
+/// payment schedule pay the seller along the way and take no receivable,
+/// and neither do sellers with outstanding advance debts in the trade's
+/// payment token, since those are only netted from payouts to the seller.
+/// Returns the token ID.
+pub fn mint_receivable(env: &Env, seller: &Address, trade_id: u64) -> Result<u32, ContractError> {
+    let mut trade: TradeEscrow = env
+        .storage()
+        .instance()
+        .get(&DataKey::Trade(trade_id))
+        .ok_or(ContractError::TradeNotFound)?;
+
+    // Verify caller is seller
+    if &trade.seller != seller {
+        return Err(ContractError::NotSeller);
+    }
+
+    // The payout is only a receivable once the goods are delivered
+    state::require_state(&trade, TradeState::Fulfilled)?;
+
+    if trade.receivable_contract.is_some() {
+        return Err(ContractError::ReceivableAlreadyMinted);
+    }
+
+    let po: PurchaseOrder = env
+        .storage()
+        .instance()
+        .get(&DataKey::PurchaseOrder(trade_id))
+        .ok_or(ContractError::PurchaseOrderNotFound)?;
+    let owes_debts = advance::get_seller_debts(env, seller)
+        .iter()
+        .any(|debt| debt.payment_token == trade.payment_token);
+    if !po.payment_schedule.is_empty() || owes_debts {
+        return Err(ContractError::ReceivableNotAllowed);
+    }
+
+    let contract = get_receivable_contract(env).ok_or(ContractError::ReceivableContractNotSet)?;
+    let token_id = ReceivableClient::new(env, &contract).mint(seller);
+
+    trade.receivable_contract = Some(contract);
+    trade.receivable_token_id = token_id;
+    trade.updated_at = env.ledger().timestamp();
+
+    env.storage()
+        .instance()
+        .set(&DataKey::Trade(trade_id), &trade);
+
+    Ok(token_id)
+}
+
+/// Current holder of a trade's receivable, if one was minted
+///
+/// Looked up on the contract the NFT was minted on, even if the owner has
+/// since configured another.
+pub fn holder(env: &Env, trade: &TradeEscrow) -> Option<Address> {
+    trade
+        .receivable_contract
+        .as_ref()
+        .map(|contract| ReceivableClient::new(env, contract).owner_of(&trade.receivable_token_id))
+}
@@ -1,10 +1,10 @@
-//! Buyer and Seller registry functions
+//! Buyer, Seller, Broker, Carrier and Inspection Agency registry functions
 
 use soroban_sdk::{Address, Env, String, Vec};
 
 use crate::errors::ContractError;
 use crate::storage::DataKey;
-use crate::types::{BuyerInfo, SellerInfo};
+use crate::types::{BrokerInfo, BuyerInfo, CarrierInfo, InspectionAgencyInfo, SellerInfo};
 
 /// Register a new buyer
 pub fn register_buyer(
@@ -120,6 +120,164 @@ pub fn register_seller(
     Ok(())
 }
 
+/// Register a new broker
+pub fn register_broker(
+    env: &Env,
+    broker_address: Address,
+    broker_name: String,
+    broker_lei_id: String,
+) -> Result<(), ContractError> {
+    // Check if broker already registered
+    if env
+        .storage()
+        .instance()
+        .has(&DataKey::RegisteredBroker(broker_address.clone()))
+    {
+        return Err(ContractError::BrokerAlreadyRegistered);
+    }
+
+    // Check if name already taken
+    if env
+        .storage()
+        .instance()
+        .has(&DataKey::BrokerByName(broker_name.clone()))
+    {
+        return Err(ContractError::BrokerNameTaken);
+    }
+
+    let broker_info = BrokerInfo {
+        name: broker_name.clone(),
+        lei_id: broker_lei_id,
+        wallet_address: broker_address.clone(),
+        registered_at: env.ledger().timestamp(),
+        is_active: true,
+    };
+
+    // Store broker info
+    env.storage()
+        .instance()
+        .set(&DataKey::RegisteredBroker(broker_address.clone()), &broker_info);
+
+    // Store name mapping
+    env.storage()
+        .instance()
+        .set(&DataKey::BrokerByName(broker_name), &broker_address);
+
+    // Add to all brokers list
+    let mut all_brokers: Vec<Address> = env
+        .storage()
+        .instance()
+        .get(&DataKey::AllBrokers)
+        .unwrap_or(Vec::new(env));
+    all_brokers.push_back(broker_address);
+    env.storage()
+        .instance()
+        .set(&DataKey::AllBrokers, &all_brokers);
+
+    Ok(())
+}
+
+/// Register a new carrier
+pub fn register_carrier(
+    env: &Env,
+    carrier_address: Address,
+    carrier_name: String,
+    carrier_lei_id: String,
+) -> Result<(), ContractError> {
+    // Check if carrier already registered
+    if env
+        .storage()
+        .instance()
+        .has(&DataKey::RegisteredCarrier(carrier_address.clone()))
+    {
+        return Err(ContractError::CarrierAlreadyRegistered);
+    }
+
+    // Check if name already taken
+    if env
+        .storage()
+        .instance()
+        .has(&DataKey::CarrierByName(carrier_name.clone()))
+    {
+        return Err(ContractError::CarrierNameTaken);
+    }
+
+    let carrier_info = CarrierInfo {
+        name: carrier_name.clone(),
+        lei_id: carrier_lei_id,
+        wallet_address: carrier_address.clone(),
+        registered_at: env.ledger().timestamp(),
+        is_active: true,
+    };
+
+    // Store carrier info
+    env.storage()
+        .instance()
+        .set(&DataKey::RegisteredCarrier(carrier_address.clone()), &carrier_info);
+
+    // Store name mapping
+    env.storage()
+        .instance()
+        .set(&DataKey::CarrierByName(carrier_name), &carrier_address);
+
+    // Add to all carriers list
+    let mut all_carriers: Vec<Address> = env
+        .storage()
+        .instance()
+        .get(&DataKey::AllCarriers)
+        .unwrap_or(Vec::new(env));
+    all_carriers.push_back(carrier_address);
+    env.storage()
+        .instance()
+        .set(&DataKey::AllCarriers, &all_carriers);
+
+    Ok(())
+}
+
+/// Register a new inspection agency
+pub fn register_inspection_agency(
+    env: &Env,
+    agency_address: Address,
+    agency_name: String,
+    agency_lei_id: String,
+) -> Result<(), ContractError> {
+    // Check if agency already registered
+    if env
+        .storage()
+        .instance()
+        .has(&DataKey::RegisteredInspectionAgency(agency_address.clone()))
+    {
+        return Err(ContractError::InspectionAgencyAlreadyRegistered);
+    }
+
+    let agency_info = InspectionAgencyInfo {
+        name: agency_name,
+        lei_id: agency_lei_id,
+        wallet_address: agency_address.clone(),
+        registered_at: env.ledger().timestamp(),
+        is_active: true,
+    };
+
+    // Store agency info
+    env.storage().instance().set(
+        &DataKey::RegisteredInspectionAgency(agency_address.clone()),
+        &agency_info,
+    );
+
+    // Add to all agencies list
+    let mut all_agencies: Vec<Address> = env
+        .storage()
+        .instance()
+        .get(&DataKey::AllInspectionAgencies)
+        .unwrap_or(Vec::new(env));
+    all_agencies.push_back(agency_address);
+    env.storage()
+        .instance()
+        .set(&DataKey::AllInspectionAgencies, &all_agencies);
+
+    Ok(())
+}
+
 /// Get buyer info
 pub fn get_buyer_info(env: &Env, buyer_address: &Address) -> Result<BuyerInfo, ContractError> {
     env.storage()
@@ -136,6 +294,36 @@ pub fn get_seller_info(env: &Env, seller_address: &Address) -> Result<SellerInfo
         .ok_or(ContractError::SellerNotRegistered)
 }
 
+/// Get broker info
+pub fn get_broker_info(env: &Env, broker_address: &Address) -> Result<BrokerInfo, ContractError> {
+    env.storage()
+        .instance()
+        .get(&DataKey::RegisteredBroker(broker_address.clone()))
+        .ok_or(ContractError::BrokerNotRegistered)
+}
+
+/// Get carrier info
+pub fn get_carrier_info(
+    env: &Env,
+    carrier_address: &Address,
+) -> Result<CarrierInfo, ContractError> {
+    env.storage()
+        .instance()
+        .get(&DataKey::RegisteredCarrier(carrier_address.clone()))
+        .ok_or(ContractError::CarrierNotRegistered)
+}
+
+/// Get inspection agency info
+pub fn get_inspection_agency_info(
+    env: &Env,
+    agency_address: &Address,
+) -> Result<InspectionAgencyInfo, ContractError> {
+    env.storage()
+        .instance()
+        .get(&DataKey::RegisteredInspectionAgency(agency_address.clone()))
+        .ok_or(ContractError::InspectionAgencyNotRegistered)
+}
+
 /// Check if buyer is registered and active
 pub fn is_buyer_active(env: &Env, buyer_address: &Address) -> Result<(), ContractError> {
     let buyer_info = get_buyer_info(env, buyer_address)?;
@@ -154,6 +342,36 @@ pub fn is_seller_active(env: &Env, seller_address: &Address) -> Result<(), Contr
     Ok(())
 }
 
+/// Check if broker is registered and active
+pub fn is_broker_active(env: &Env, broker_address: &Address) -> Result<(), ContractError> {
+    let broker_info = get_broker_info(env, broker_address)?;
+    if !broker_info.is_active {
+        return Err(ContractError::BrokerInactive);
+    }
+    Ok(())
+}
+
+/// Check if carrier is registered and active
+pub fn is_carrier_active(env: &Env, carrier_address: &Address) -> Result<(), ContractError> {
+    let carrier_info = get_carrier_info(env, carrier_address)?;
+    if !carrier_info.is_active {
+        return Err(ContractError::CarrierInactive);
+    }
+    Ok(())
+}
+
+/// Check if inspection agency is registered and active
+pub fn is_inspection_agency_active(
+    env: &Env,
+    agency_address: &Address,
+) -> Result<(), ContractError> {
+    let agency_info = get_inspection_agency_info(env, agency_address)?;
+    if !agency_info.is_active {
+        return Err(ContractError::InspectionAgencyInactive);
+    }
+    Ok(())
+}
+
 /// Deactivate buyer
 pub fn deactivate_buyer(env: &Env, buyer_address: &Address) -> Result<(), ContractError> {
     let mut buyer_info = get_buyer_info(env, buyer_address)?;
@@ -174,6 +392,40 @@ pub fn deactivate_seller(env: &Env, seller_address: &Address) -> Result<(), Cont
     Ok(())
 }
 
+/// Deactivate broker
+pub fn deactivate_broker(env: &Env, broker_address: &Address) -> Result<(), ContractError> {
+    let mut broker_info = get_broker_info(env, broker_address)?;
+    broker_info.is_active = false;
+    env.storage()
+        .instance()
+        .set(&DataKey::RegisteredBroker(broker_address.clone()), &broker_info);
+    Ok(())
+}
+
+/// Deactivate carrier
+pub fn deactivate_carrier(env: &Env, carrier_address: &Address) -> Result<(), ContractError> {
+    let mut carrier_info = get_carrier_info(env, carrier_address)?;
+    carrier_info.is_active = false;
+    env.storage()
+        .instance()
+        .set(&DataKey::RegisteredCarrier(carrier_address.clone()), &carrier_info);
+    Ok(())
+}
+
+/// Deactivate inspection agency
+pub fn deactivate_inspection_agency(
+    env: &Env,
+    agency_address: &Address,
+) -> Result<(), ContractError> {
+    let mut agency_info = get_inspection_agency_info(env, agency_address)?;
+    agency_info.is_active = false;
+    env.storage().instance().set(
+        &DataKey::RegisteredInspectionAgency(agency_address.clone()),
+        &agency_info,
+    );
+    Ok(())
+}
+
 /// Get all buyers
 pub fn get_all_buyers(env: &Env) -> Vec<BuyerInfo> {
     let all_buyers: Vec<Address> = env
@@ -207,3 +459,54 @@ pub fn get_all_sellers(env: &Env) -> Vec<SellerInfo> {
     }
     seller_infos
 }
+
+/// Get all brokers
+pub fn get_all_brokers(env: &Env) -> Vec<BrokerInfo> {
+    let all_brokers: Vec<Address> = env
+        .storage()
+        .instance()
+        .get(&DataKey::AllBrokers)
+        .unwrap_or(Vec::new(env));
+
+    let mut broker_infos = Vec::new(env);
+    for broker_addr in all_brokers.iter() {
+        if let Ok(broker_info) = get_broker_info(env, &broker_addr) {
+            broker_infos.push_back(broker_info);
+        }
+    }
+    broker_infos
+}
+
+/// Get all carriers
+pub fn get_all_carriers(env: &Env) -> Vec<CarrierInfo> {
+    let all_carriers: Vec<Address> = env
+        .storage()
+        .instance()
+        .get(&DataKey::AllCarriers)
+        .unwrap_or(Vec::new(env));
+
+    let mut carrier_infos = Vec::new(env);
+    for carrier_addr in all_carriers.iter() {
+        if let Ok(carrier_info) = get_carrier_info(env, &carrier_addr) {
+            carrier_infos.push_back(carrier_info);
+        }
+    }
+    carrier_infos
+}
+
+/// Get all inspection agencies
+pub fn get_all_inspection_agencies(env: &Env) -> Vec<InspectionAgencyInfo> {
+    let all_agencies: Vec<Address> = env
+        .storage()
+        .instance()
+        .get(&DataKey::AllInspectionAgencies)
+        .unwrap_or(Vec::new(env));
+
+    let mut agency_infos = Vec::new(env);
+    for agency_addr in all_agencies.iter() {
+        if let Ok(agency_info) = get_inspection_agency_info(env, &agency_addr) {
+            agency_infos.push_back(agency_info);
+        }
+    }
+    agency_infos
+}
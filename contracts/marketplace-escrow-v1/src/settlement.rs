@@ -0,0 +1,69 @@
+//! Token movements in and out of escrow
+
+use soroban_sdk::{token, Address, Env};
+
+use crate::errors::ContractError;
+use crate::storage::DataKey;
+use crate::types::TradeEscrow;
+
+/// Get the token new trades are settled in, if on-chain custody is enabled
+pub fn get_payment_token(env: &Env) -> Option<Address> {
+    env.storage().instance().get(&DataKey::PaymentToken)
+}
+
+/// Pull funds from the buyer into the contract
+///
+/// Trades without a payment token are settled off-chain, so nothing moves.
+pub fn collect(env: &Env, trade: &TradeEscrow, amount: i128) -> Result<(), ContractError> {
+    collect_from(env, trade, &trade.buyer, amount)
+}
+
+/// Pull funds for a trade from any account into the contract
+pub fn collect_from(
+    env: &Env,
+    trade: &TradeEscrow,
+    from: &Address,
+    amount: i128,
+) -> Result<(), ContractError> {
+    if amount <= 0 {
+        return Ok(());
+    }
+
+    if let Some(payment_token) = &trade.payment_token {
+        transfer(env, payment_token, from, &env.current_contract_address(), amount)?;
+    }
+
+    Ok(())
+}
+
+/// Pay funds held for a trade out of the contract
+pub fn disburse(
+    env: &Env,
+    trade: &TradeEscrow,
+    to: &Address,
+    amount: i128,
+) -> Result<(), ContractError> {
+    if amount <= 0 {
+        return Ok(());
+    }
+
+    if let Some(payment_token) = &trade.payment_token {
+        transfer(env, payment_token, &env.current_contract_address(), to, amount)?;
+    }
+
+    Ok(())
+}
+
+/// Move tokens, failing unless the token reports a clean transfer
+pub fn transfer(
+    env: &Env,
+    asset: &Address,
+    from: &Address,
+    to: &Address,
+    amount: i128,
+) -> Result<(), ContractError> {
+    match token::Client::new(env, asset).try_transfer(from, to, &amount) {
+        Ok(Ok(())) => Ok(()),
+        _ => Err(ContractError::PaymentTransferFailed),
+    }
+}
@@ -0,0 +1,87 @@
+//! Trade state machine and state-history log
+
+use soroban_sdk::{Address, Env, Vec};
+
+use crate::errors::ContractError;
+use crate::storage::{self, DataKey};
+use crate::types::{StateChange, TradeEscrow, TradeState};
+
+/// Whether a trade may move directly from one state to another
+///
+/// ```text
+/// Ordered ──> Fulfilled ──> Settled
+///    │            │
+///    │            └──> Refunded
+///    ├──> Refunded
+///    ├──> Rejected
+///    └──> Cancelled
+/// ```
+pub fn can_transition(from: TradeState, to: TradeState) -> bool {
+    matches!(
+        (from, to),
+        (TradeState::Ordered, TradeState::Fulfilled)
+            | (TradeState::Ordered, TradeState::Rejected)
+            | (TradeState::Ordered, TradeState::Cancelled)
+            | (TradeState::Ordered, TradeState::Refunded)
+            | (TradeState::Fulfilled, TradeState::Settled)
+            | (TradeState::Fulfilled, TradeState::Refunded)
+    )
+}
+
+/// Fail unless the trade is currently in `state`
+pub fn require_state(trade: &TradeEscrow, state: TradeState) -> Result<(), ContractError> {
+    if trade.state != state {
+        return Err(ContractError::InvalidTradeState);
+    }
+    Ok(())
+}
+
+/// Move a trade to a new state and log who did it
+///
+/// This is the only place trade state changes; the caller still persists
+/// the updated trade.
+pub fn transition(
+    env: &Env,
+    trade: &mut TradeEscrow,
+    to: TradeState,
+    actor: &Address,
+) -> Result<(), ContractError> {
+    if !can_transition(trade.state, to) {
+        return Err(ContractError::InvalidTradeState);
+    }
+
+    append_history(env, trade.trade_id, trade.state, to, actor);
+
+    trade.state = to;
+    trade.updated_at = env.ledger().timestamp();
+    Ok(())
+}
+
+/// Log the initial state of a newly created trade
+pub fn record_created(env: &Env, trade: &TradeEscrow, actor: &Address) {
+    append_history(env, trade.trade_id, trade.state, trade.state, actor);
+}
+
+/// Get every state change a trade has gone through, oldest first
+pub fn get_history(env: &Env, trade_id: u64) -> Vec<StateChange> {
+    storage::get_persistent(env, &DataKey::TradeHistory(trade_id)).unwrap_or(Vec::new(env))
+}
+
+/// Append an entry to a trade's state history
+pub fn append_history(
+    env: &Env,
+    trade_id: u64,
+    from: TradeState,
+    to: TradeState,
+    actor: &Address,
+) {
+    let mut history = get_history(env, trade_id);
+    history.push_back(StateChange {
+        from,
+        to,
+        timestamp: env.ledger().timestamp(),
+        actor: actor.clone(),
+    });
+
+    storage::set_persistent(env, &DataKey::TradeHistory(trade_id), &history);
+}
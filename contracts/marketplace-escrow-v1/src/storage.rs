@@ -1,6 +1,8 @@
 //! Storage keys for the MarketplaceEscrowV1 contract
 
-use soroban_sdk::{contracttype, symbol_short, Address, String, Symbol};
+use soroban_sdk::{
+    contracttype, symbol_short, Address, Env, IntoVal, String, Symbol, TryFromVal, Val,
+};
 
 /// Storage keys for global state and data maps
 #[contracttype]
@@ -11,6 +13,18 @@ pub enum DataKey {
     PlatformTreasury,
     MarketplaceFeeRate,
     ContractOwner,
+    PaymentToken,
+    DefaultFeePayer,
+    FeeSchedule,
+    FeeManager,
+    RetainFees,
+    MaxAdvanceRate,
+    ReceivableContract,
+    TitleReceiptContract,
+
+    // Schema versioning
+    ContractVersion,
+    MigrationCursor,
     
     // Buyer/Seller Registry
     RegisteredBuyer(Address),
@@ -19,13 +33,44 @@ pub enum DataKey {
     SellerByName(String),
     AllBuyers,
     AllSellers,
+    RegisteredBroker(Address),
+    BrokerByName(String),
+    AllBrokers,
+    RegisteredCarrier(Address),
+    CarrierByName(String),
+    AllCarriers,
+    RegisteredInspectionAgency(Address),
+    AllInspectionAgencies,
+    BrokerEarnings(Address),
+    SellerFeePayer(Address),
+    NegotiatedFeeRate(Address),
+    SettledVolume(Address), // persistent
+    SellerDebts(Address),
+    CreditLimit(Address),
+    CreditUsed(Address),
+    BuyerDeferredTrades(Address),
+    ApprovalPolicy(Address),
+    Operator(Address),
     
+    // Fee ledger, keyed by asset (persistent)
+    FeeLedger(Address),
+    FeePeriod(Address, u64),
+    RetainedFees(Address),
+
     // Trade data
     Trade(u64),
     PurchaseOrder(u64),
+    PurchaseOrderVersion(u64, u32),
+    PendingChangeOrder(u64),
+    ChangeOrderCount(u64),
+    PendingApproval(u64),
     CustomerInvoice(u64),
     WarehouseReceipt(u64),
+    BillOfLading(u64),
+    DesignatedCarrier(u64),
+    InspectionCertificate(u64),
     VLEIDocuments(u64),
+    TradeHistory(u64), // persistent
     
     // Trade indices
     BuyerTrades(Address),
@@ -35,3 +80,32 @@ pub enum DataKey {
 /// Symbols for quick access
 pub const NEXT_TRADE_ID: Symbol = symbol_short!("NEXT_ID");
 pub const OWNER: Symbol = symbol_short!("OWNER");
+
+/// Ledgers closed in a day, at about five seconds a ledger
+const DAY_IN_LEDGERS: u32 = 17280;
+
+/// TTL a persistent record is extended to whenever it is read or written
+pub const PERSISTENT_TTL: u32 = 30 * DAY_IN_LEDGERS;
+
+/// Remaining TTL below which a persistent record is extended
+pub const PERSISTENT_TTL_THRESHOLD: u32 = PERSISTENT_TTL - DAY_IN_LEDGERS;
+
+/// Read a persistent record, extending its TTL if it exists
+///
+/// Records that grow with trades or periods live in persistent storage so
+/// instance storage, loaded on every call, stays small.
+pub fn get_persistent<V: TryFromVal<Env, Val>>(env: &Env, key: &DataKey) -> Option<V> {
+    let storage = env.storage().persistent();
+    let value = storage.get(key);
+    if value.is_some() {
+        storage.extend_ttl(key, PERSISTENT_TTL_THRESHOLD, PERSISTENT_TTL);
+    }
+    value
+}
+
+/// Write a persistent record and extend its TTL
+pub fn set_persistent<V: IntoVal<Env, Val>>(env: &Env, key: &DataKey, value: &V) {
+    let storage = env.storage().persistent();
+    storage.set(key, value);
+    storage.extend_ttl(key, PERSISTENT_TTL_THRESHOLD, PERSISTENT_TTL);
+}
@@ -1,46 +1,57 @@
 #![cfg(test)]
 
 use crate::{
-    contract::MarketplaceEscrowV1, types::*, BuyerInfo, ContractError, CustomerInvoice,
-    PurchaseOrder, SellerInfo, TradeEscrow, WarehouseReceipt,
+    contract::{MarketplaceEscrowV1, MarketplaceEscrowV1Client},
+    errors::ContractError,
+    migration::{PurchaseOrderV1, TradeEscrowV1, CONTRACT_VERSION},
+    state,
+    storage::DataKey,
+    types::*,
 };
+use nft_enumerable_example::{ExampleContract, ExampleContractClient};
 use soroban_sdk::{
     testutils::{Address as _, Ledger},
-    Address, Env, String,
+    token::{StellarAssetClient, TokenClient},
+    Address, Bytes, BytesN, Env, String, Vec,
 };
+use warehouse_receipt::{WarehouseReceiptContract, WarehouseReceiptContractClient};
 
 fn create_contract() -> (Env, Address, Address, Address, Address) {
     let env = Env::default();
     env.mock_all_auths();
 
-    let contract_id = env.register(MarketplaceEscrowV1, ());
-    let client = MarketplaceEscrowV1Client::new(&env, &contract_id);
-
     let admin = Address::generate(&env);
     let treasury = Address::generate(&env);
     let buyer = Address::generate(&env);
     let seller = Address::generate(&env);
 
-    // Initialize contract
-    client.constructor(&treasury, &25); // 0.25% fee
+    // Initialize contract with a 0.25% fee
+    let contract_id = env.register(MarketplaceEscrowV1, (&treasury, 25u32));
+    let client = MarketplaceEscrowV1Client::new(&env, &contract_id);
+
+    // Hold escrow in a test token and give the buyer enough to trade with
+    let token = env.register_stellar_asset_contract_v2(admin.clone());
+    StellarAssetClient::new(&env, &token.address()).mint(&buyer, &100000_0000000);
+    client.set_payment_token(&token.address());
 
     (env, contract_id, admin, buyer, seller)
 }
 
 #[test]
 fn test_initialize() {
-    let (env, contract_id, _admin, _buyer, _seller) = create_contract();
+    let (env, contract_id, _admin, buyer, seller) = create_contract();
     let client = MarketplaceEscrowV1Client::new(&env, &contract_id);
 
     // Test calculate_escrow_cost
-    let (total, fee) = client.calculate_escrow_cost(&10000);
-    assert_eq!(fee, 25); // 0.25% of 10000
-    assert_eq!(total, 10025);
+    let cost = client.calculate_escrow_cost(&buyer, &seller, &10000);
+    assert_eq!(cost.marketplace_fee, 25); // 0.25% of 10000
+    assert_eq!(cost.total_required, 10025);
+    assert_eq!(cost.seller_payout, 10000);
 }
 
 #[test]
 fn test_register_buyer() {
-    let (env, contract_id, admin, buyer, _seller) = create_contract();
+    let (env, contract_id, _admin, buyer, _seller) = create_contract();
     let client = MarketplaceEscrowV1Client::new(&env, &contract_id);
 
     // Register buyer
@@ -58,7 +69,7 @@ fn test_register_buyer() {
 
 #[test]
 fn test_register_seller() {
-    let (env, contract_id, admin, _buyer, seller) = create_contract();
+    let (env, contract_id, _admin, _buyer, seller) = create_contract();
     let client = MarketplaceEscrowV1Client::new(&env, &contract_id);
 
     // Register seller
@@ -75,9 +86,9 @@ fn test_register_seller() {
 }
 
 #[test]
-#[should_panic(expected = "BuyerAlreadyRegistered")]
+#[should_panic(expected = "Error(Contract, #3)")]
 fn test_register_buyer_duplicate() {
-    let (env, contract_id, admin, buyer, _seller) = create_contract();
+    let (env, contract_id, _admin, buyer, _seller) = create_contract();
     let client = MarketplaceEscrowV1Client::new(&env, &contract_id);
 
     let buyer_name = String::from_str(&env, "Tommy Hilfiger");
@@ -92,7 +103,7 @@ fn test_register_buyer_duplicate() {
 
 #[test]
 fn test_create_trade() {
-    let (env, contract_id, admin, buyer, seller) = create_contract();
+    let (env, contract_id, _admin, buyer, seller) = create_contract();
     let client = MarketplaceEscrowV1Client::new(&env, &contract_id);
 
     // Register buyer and seller
@@ -110,30 +121,48 @@ fn test_create_trade() {
 
     // Create trade
     let trade_id = client.create_trade(
+        &buyer,
         &seller,
-        &String::from_str(&env, "Cotton T-shirts"),
-        &1000,
-        &15_0000000, // 15 XLM in stroops
-        &15000_0000000,
-        &String::from_str(&env, "QmPO123"),
+        &PurchaseOrderDetails {
+            po_description: String::from_str(&env, "Cotton T-shirts"),
+            product: product_id(&env),
+            quantity: 1000,
+            unit_price: 15_0000000, // 15 XLM in stroops
+            total_price: 15000_0000000,
+            po_json_ipfs_hash: String::from_str(&env, PO_CID),
+            po_json_hash: doc_hash(&env, PO_JSON),
+            delivery: delivery_terms(&env, Incoterm::Dap),
+            requires_inspection: false,
+            inspection_agency: None,
+            payment_schedule: Vec::new(&env),
+            performance_bond_rate: 0,
+            payment_terms_days: 0,
+            early_payment_discount_bps: 0,
+            discount_window_days: 0,
+            late_penalty_bps_per_day: 0,
+            requires_title_receipt: false,
+        },
         &String::from_str(&env, "QmBuyerLEI"),
         &String::from_str(&env, "QmSellerLEI"),
+        &None,
     );
 
     assert_eq!(trade_id, 1);
+    // Without a broker only the buyer signs
+    assert!(!env.auths().iter().any(|(address, _)| address == &seller));
 
     // Verify trade created
     let trade = client.get_trade(&trade_id);
     assert_eq!(trade.trade_id, 1);
     assert_eq!(trade.buyer, buyer);
     assert_eq!(trade.seller, seller);
-    assert_eq!(trade.state, ORDERED);
+    assert_eq!(trade.state, TradeState::Ordered);
     assert_eq!(trade.amount, 15000_0000000);
 }
 
 #[test]
 fn test_fund_escrow() {
-    let (env, contract_id, admin, buyer, seller) = create_contract();
+    let (env, contract_id, _admin, buyer, seller) = create_contract();
     let client = MarketplaceEscrowV1Client::new(&env, &contract_id);
 
     // Setup
@@ -150,22 +179,42 @@ fn test_fund_escrow() {
     );
 
     let trade_id = client.create_trade(
+        &buyer,
         &seller,
-        &String::from_str(&env, "Cotton T-shirts"),
-        &1000,
-        &15_0000000,
-        &10000_0000000,
-        &String::from_str(&env, "QmPO123"),
+        &PurchaseOrderDetails {
+            po_description: String::from_str(&env, "Cotton T-shirts"),
+            product: product_id(&env),
+            quantity: 1000,
+            unit_price: 10_0000000,
+            total_price: 10000_0000000,
+            po_json_ipfs_hash: String::from_str(&env, PO_CID),
+            po_json_hash: doc_hash(&env, PO_JSON),
+            delivery: delivery_terms(&env, Incoterm::Dap),
+            requires_inspection: false,
+            inspection_agency: None,
+            payment_schedule: Vec::new(&env),
+            performance_bond_rate: 0,
+            payment_terms_days: 0,
+            early_payment_discount_bps: 0,
+            discount_window_days: 0,
+            late_penalty_bps_per_day: 0,
+            requires_title_receipt: false,
+        },
         &String::from_str(&env, "QmBuyerLEI"),
         &String::from_str(&env, "QmSellerLEI"),
+        &None,
     );
 
     // Calculate required escrow
-    let (total_required, fee) = client.calculate_escrow_cost(&10000_0000000);
+    let EscrowCost {
+        total_required,
+        marketplace_fee: fee,
+        ..
+    } = client.calculate_escrow_cost(&buyer, &seller, &10000_0000000);
     assert_eq!(fee, 25_0000000); // 0.25% fee
 
     // Fund escrow
-    client.fund_escrow(&trade_id, &total_required);
+    client.fund_escrow(&buyer, &trade_id, &total_required);
 
     // Verify escrow funded
     let trade = client.get_trade(&trade_id);
@@ -175,7 +224,7 @@ fn test_fund_escrow() {
 
 #[test]
 fn test_fulfill_order() {
-    let (env, contract_id, admin, buyer, seller) = create_contract();
+    let (env, contract_id, _admin, buyer, seller) = create_contract();
     let client = MarketplaceEscrowV1Client::new(&env, &contract_id);
 
     // Setup
@@ -192,46 +241,76 @@ fn test_fulfill_order() {
     );
 
     let trade_id = client.create_trade(
+        &buyer,
         &seller,
-        &String::from_str(&env, "Cotton T-shirts"),
-        &1000,
-        &15_0000000,
-        &15000_0000000,
-        &String::from_str(&env, "QmPO123"),
+        &PurchaseOrderDetails {
+            po_description: String::from_str(&env, "Cotton T-shirts"),
+            product: product_id(&env),
+            quantity: 1000,
+            unit_price: 15_0000000,
+            total_price: 15000_0000000,
+            po_json_ipfs_hash: String::from_str(&env, PO_CID),
+            po_json_hash: doc_hash(&env, PO_JSON),
+            delivery: delivery_terms(&env, Incoterm::Dap),
+            requires_inspection: false,
+            inspection_agency: None,
+            payment_schedule: Vec::new(&env),
+            performance_bond_rate: 0,
+            payment_terms_days: 0,
+            early_payment_discount_bps: 0,
+            discount_window_days: 0,
+            late_penalty_bps_per_day: 0,
+            requires_title_receipt: false,
+        },
         &String::from_str(&env, "QmBuyerLEI"),
         &String::from_str(&env, "QmSellerLEI"),
+        &None,
     );
 
-    let (total_required, _) = client.calculate_escrow_cost(&15000_0000000);
-    client.fund_escrow(&trade_id, &total_required);
+    let total_required = client
+        .calculate_escrow_cost(&buyer, &seller, &15000_0000000)
+        .total_required;
+    client.fund_escrow(&buyer, &trade_id, &total_required);
 
     // Validate buyer vLEI
     client.validate_buyer_vlei(&trade_id);
 
     // Fulfill order
     client.fulfill_order(
+        &seller,
         &trade_id,
-        &String::from_str(&env, "Cotton T-shirts"), // CI description
-        &1000,                                       // CI quantity
-        &15_0000000,                                 // CI unit price
-        &15000_0000000,                              // CI total
-        &String::from_str(&env, "QmCI123"),
-        &String::from_str(&env, "Cotton T-shirts"), // WR description
-        &1000,                                       // WR quantity
-        &15_0000000,                                 // WR unit price
-        &15000_0000000,                              // WR total
-        &String::from_str(&env, "QmWR123"),
-        &String::from_str(&env, "Warehouse A"),
+        &CustomerInvoiceDetails {
+            ci_description: String::from_str(&env, "Cotton T-shirts"),
+            product: product_id(&env),
+            quantity: 1000,
+            unit_price: 15_0000000,
+            total_price: 15000_0000000,
+            ci_json_ipfs_hash: String::from_str(&env, CI_CID),
+            ci_json_hash: doc_hash(&env, CI_JSON),
+            incoterm: Incoterm::Dap,
+            named_place: String::from_str(&env, DELIVERY_PLACE),
+        },
+        &WarehouseReceiptDetails {
+            wr_description: String::from_str(&env, "Cotton T-shirts"),
+            product: product_id(&env),
+            quantity: 1000,
+            unit_price: 15_0000000,
+            total_price: 15000_0000000,
+            wr_json_ipfs_hash: String::from_str(&env, WR_CID),
+            wr_json_hash: doc_hash(&env, WR_JSON),
+            warehouse_location: String::from_str(&env, WAREHOUSE_LOCATION),
+            lot: String::from_str(&env, LOT),
+        },
     );
 
     // Verify fulfilled
     let trade = client.get_trade(&trade_id);
-    assert_eq!(trade.state, FULFILLED);
+    assert_eq!(trade.state, TradeState::Fulfilled);
 }
 
 #[test]
 fn test_accept_trade_exact_match() {
-    let (env, contract_id, admin, buyer, seller) = create_contract();
+    let (env, contract_id, _admin, buyer, seller) = create_contract();
     let client = MarketplaceEscrowV1Client::new(&env, &contract_id);
 
     // Setup
@@ -248,46 +327,76 @@ fn test_accept_trade_exact_match() {
     );
 
     let trade_id = client.create_trade(
+        &buyer,
         &seller,
-        &String::from_str(&env, "Cotton T-shirts"),
-        &1000,
-        &15_0000000,
-        &15000_0000000,
-        &String::from_str(&env, "QmPO123"),
+        &PurchaseOrderDetails {
+            po_description: String::from_str(&env, "Cotton T-shirts"),
+            product: product_id(&env),
+            quantity: 1000,
+            unit_price: 15_0000000,
+            total_price: 15000_0000000,
+            po_json_ipfs_hash: String::from_str(&env, PO_CID),
+            po_json_hash: doc_hash(&env, PO_JSON),
+            delivery: delivery_terms(&env, Incoterm::Dap),
+            requires_inspection: false,
+            inspection_agency: None,
+            payment_schedule: Vec::new(&env),
+            performance_bond_rate: 0,
+            payment_terms_days: 0,
+            early_payment_discount_bps: 0,
+            discount_window_days: 0,
+            late_penalty_bps_per_day: 0,
+            requires_title_receipt: false,
+        },
         &String::from_str(&env, "QmBuyerLEI"),
         &String::from_str(&env, "QmSellerLEI"),
+        &None,
     );
 
-    let (total_required, _) = client.calculate_escrow_cost(&15000_0000000);
-    client.fund_escrow(&trade_id, &total_required);
+    let total_required = client
+        .calculate_escrow_cost(&buyer, &seller, &15000_0000000)
+        .total_required;
+    client.fund_escrow(&buyer, &trade_id, &total_required);
     client.validate_buyer_vlei(&trade_id);
 
     client.fulfill_order(
+        &seller,
         &trade_id,
-        &String::from_str(&env, "Cotton T-shirts"),
-        &1000,
-        &15_0000000,
-        &15000_0000000,
-        &String::from_str(&env, "QmCI123"),
-        &String::from_str(&env, "Cotton T-shirts"),
-        &1000,
-        &15_0000000,
-        &15000_0000000,
-        &String::from_str(&env, "QmWR123"),
-        &String::from_str(&env, "Warehouse A"),
+        &CustomerInvoiceDetails {
+            ci_description: String::from_str(&env, "Cotton T-shirts"),
+            product: product_id(&env),
+            quantity: 1000,
+            unit_price: 15_0000000,
+            total_price: 15000_0000000,
+            ci_json_ipfs_hash: String::from_str(&env, CI_CID),
+            ci_json_hash: doc_hash(&env, CI_JSON),
+            incoterm: Incoterm::Dap,
+            named_place: String::from_str(&env, DELIVERY_PLACE),
+        },
+        &WarehouseReceiptDetails {
+            wr_description: String::from_str(&env, "Cotton T-shirts"),
+            product: product_id(&env),
+            quantity: 1000,
+            unit_price: 15_0000000,
+            total_price: 15000_0000000,
+            wr_json_ipfs_hash: String::from_str(&env, WR_CID),
+            wr_json_hash: doc_hash(&env, WR_JSON),
+            warehouse_location: String::from_str(&env, WAREHOUSE_LOCATION),
+            lot: String::from_str(&env, LOT),
+        },
     );
 
     // Accept trade (triggers DvP)
-    client.accept_trade(&trade_id);
+    client.accept_trade(&buyer, &trade_id);
 
     // Verify settled
     let trade = client.get_trade(&trade_id);
-    assert_eq!(trade.state, SETTLED);
+    assert_eq!(trade.state, TradeState::Settled);
 }
 
 #[test]
 fn test_accept_trade_with_quantity_variance() {
-    let (env, contract_id, admin, buyer, seller) = create_contract();
+    let (env, contract_id, _admin, buyer, seller) = create_contract();
     let client = MarketplaceEscrowV1Client::new(&env, &contract_id);
 
     // Setup
@@ -304,47 +413,77 @@ fn test_accept_trade_with_quantity_variance() {
     );
 
     let trade_id = client.create_trade(
+        &buyer,
         &seller,
-        &String::from_str(&env, "Cotton T-shirts"),
-        &1000,
-        &15_0000000,
-        &15000_0000000,
-        &String::from_str(&env, "QmPO123"),
+        &PurchaseOrderDetails {
+            po_description: String::from_str(&env, "Cotton T-shirts"),
+            product: product_id(&env),
+            quantity: 1000,
+            unit_price: 15_0000000,
+            total_price: 15000_0000000,
+            po_json_ipfs_hash: String::from_str(&env, PO_CID),
+            po_json_hash: doc_hash(&env, PO_JSON),
+            delivery: delivery_terms(&env, Incoterm::Dap),
+            requires_inspection: false,
+            inspection_agency: None,
+            payment_schedule: Vec::new(&env),
+            performance_bond_rate: 0,
+            payment_terms_days: 0,
+            early_payment_discount_bps: 0,
+            discount_window_days: 0,
+            late_penalty_bps_per_day: 0,
+            requires_title_receipt: false,
+        },
         &String::from_str(&env, "QmBuyerLEI"),
         &String::from_str(&env, "QmSellerLEI"),
+        &None,
     );
 
-    let (total_required, _) = client.calculate_escrow_cost(&15000_0000000);
-    client.fund_escrow(&trade_id, &total_required);
+    let total_required = client
+        .calculate_escrow_cost(&buyer, &seller, &15000_0000000)
+        .total_required;
+    client.fund_escrow(&buyer, &trade_id, &total_required);
     client.validate_buyer_vlei(&trade_id);
 
-    // Fulfill with 4% quantity variance (within 5% tolerance)
+    // Fulfill with 1.5% quantity variance (within 5% quantity and 2% price tolerance)
     client.fulfill_order(
+        &seller,
         &trade_id,
-        &String::from_str(&env, "Cotton T-shirts"),
-        &1040, // 4% more than PO
-        &15_0000000,
-        &15600_0000000, // Adjusted total
-        &String::from_str(&env, "QmCI123"),
-        &String::from_str(&env, "Cotton T-shirts"),
-        &1040,
-        &15_0000000,
-        &15600_0000000,
-        &String::from_str(&env, "QmWR123"),
-        &String::from_str(&env, "Warehouse A"),
+        &CustomerInvoiceDetails {
+            ci_description: String::from_str(&env, "Cotton T-shirts"),
+            product: product_id(&env),
+            quantity: 1015, // 1.5% more than PO
+            unit_price: 15_0000000,
+            total_price: 15225_0000000, // Adjusted total
+            ci_json_ipfs_hash: String::from_str(&env, CI_CID),
+            ci_json_hash: doc_hash(&env, CI_JSON),
+            incoterm: Incoterm::Dap,
+            named_place: String::from_str(&env, DELIVERY_PLACE),
+        },
+        &WarehouseReceiptDetails {
+            wr_description: String::from_str(&env, "Cotton T-shirts"),
+            product: product_id(&env),
+            quantity: 1015,
+            unit_price: 15_0000000,
+            total_price: 15225_0000000,
+            wr_json_ipfs_hash: String::from_str(&env, WR_CID),
+            wr_json_hash: doc_hash(&env, WR_JSON),
+            warehouse_location: String::from_str(&env, WAREHOUSE_LOCATION),
+            lot: String::from_str(&env, LOT),
+        },
     );
 
     // Accept trade (should pass with variance)
-    client.accept_trade(&trade_id);
+    client.accept_trade(&buyer, &trade_id);
 
     let trade = client.get_trade(&trade_id);
-    assert_eq!(trade.state, SETTLED);
+    assert_eq!(trade.state, TradeState::Settled);
 }
 
 #[test]
-#[should_panic(expected = "DescriptionMismatch")]
+#[should_panic(expected = "Error(Contract, #100)")]
 fn test_accept_trade_description_mismatch() {
-    let (env, contract_id, admin, buyer, seller) = create_contract();
+    let (env, contract_id, _admin, buyer, seller) = create_contract();
     let client = MarketplaceEscrowV1Client::new(&env, &contract_id);
 
     // Setup
@@ -361,43 +500,73 @@ fn test_accept_trade_description_mismatch() {
     );
 
     let trade_id = client.create_trade(
+        &buyer,
         &seller,
-        &String::from_str(&env, "Cotton T-shirts"),
-        &1000,
-        &15_0000000,
-        &15000_0000000,
-        &String::from_str(&env, "QmPO123"),
+        &PurchaseOrderDetails {
+            po_description: String::from_str(&env, "Cotton T-shirts"),
+            product: product_id(&env),
+            quantity: 1000,
+            unit_price: 15_0000000,
+            total_price: 15000_0000000,
+            po_json_ipfs_hash: String::from_str(&env, PO_CID),
+            po_json_hash: doc_hash(&env, PO_JSON),
+            delivery: delivery_terms(&env, Incoterm::Dap),
+            requires_inspection: false,
+            inspection_agency: None,
+            payment_schedule: Vec::new(&env),
+            performance_bond_rate: 0,
+            payment_terms_days: 0,
+            early_payment_discount_bps: 0,
+            discount_window_days: 0,
+            late_penalty_bps_per_day: 0,
+            requires_title_receipt: false,
+        },
         &String::from_str(&env, "QmBuyerLEI"),
         &String::from_str(&env, "QmSellerLEI"),
+        &None,
     );
 
-    let (total_required, _) = client.calculate_escrow_cost(&15000_0000000);
-    client.fund_escrow(&trade_id, &total_required);
+    let total_required = client
+        .calculate_escrow_cost(&buyer, &seller, &15000_0000000)
+        .total_required;
+    client.fund_escrow(&buyer, &trade_id, &total_required);
     client.validate_buyer_vlei(&trade_id);
 
     // Fulfill with different description
     client.fulfill_order(
+        &seller,
         &trade_id,
-        &String::from_str(&env, "Polyester T-shirts"), // DIFFERENT!
-        &1000,
-        &15_0000000,
-        &15000_0000000,
-        &String::from_str(&env, "QmCI123"),
-        &String::from_str(&env, "Cotton T-shirts"),
-        &1000,
-        &15_0000000,
-        &15000_0000000,
-        &String::from_str(&env, "QmWR123"),
-        &String::from_str(&env, "Warehouse A"),
+        &CustomerInvoiceDetails {
+            ci_description: String::from_str(&env, "Polyester T-shirts"), // DIFFERENT!
+            product: product_id(&env),
+            quantity: 1000,
+            unit_price: 15_0000000,
+            total_price: 15000_0000000,
+            ci_json_ipfs_hash: String::from_str(&env, CI_CID),
+            ci_json_hash: doc_hash(&env, CI_JSON),
+            incoterm: Incoterm::Dap,
+            named_place: String::from_str(&env, DELIVERY_PLACE),
+        },
+        &WarehouseReceiptDetails {
+            wr_description: String::from_str(&env, "Cotton T-shirts"),
+            product: product_id(&env),
+            quantity: 1000,
+            unit_price: 15_0000000,
+            total_price: 15000_0000000,
+            wr_json_ipfs_hash: String::from_str(&env, WR_CID),
+            wr_json_hash: doc_hash(&env, WR_JSON),
+            warehouse_location: String::from_str(&env, WAREHOUSE_LOCATION),
+            lot: String::from_str(&env, LOT),
+        },
     );
 
     // This should fail
-    client.accept_trade(&trade_id);
+    client.accept_trade(&buyer, &trade_id);
 }
 
 #[test]
 fn test_reject_order() {
-    let (env, contract_id, admin, buyer, seller) = create_contract();
+    let (env, contract_id, _admin, buyer, seller) = create_contract();
     let client = MarketplaceEscrowV1Client::new(&env, &contract_id);
 
     // Setup
@@ -414,26 +583,42 @@ fn test_reject_order() {
     );
 
     let trade_id = client.create_trade(
+        &buyer,
         &seller,
-        &String::from_str(&env, "Cotton T-shirts"),
-        &1000,
-        &15_0000000,
-        &15000_0000000,
-        &String::from_str(&env, "QmPO123"),
+        &PurchaseOrderDetails {
+            po_description: String::from_str(&env, "Cotton T-shirts"),
+            product: product_id(&env),
+            quantity: 1000,
+            unit_price: 15_0000000,
+            total_price: 15000_0000000,
+            po_json_ipfs_hash: String::from_str(&env, PO_CID),
+            po_json_hash: doc_hash(&env, PO_JSON),
+            delivery: delivery_terms(&env, Incoterm::Dap),
+            requires_inspection: false,
+            inspection_agency: None,
+            payment_schedule: Vec::new(&env),
+            performance_bond_rate: 0,
+            payment_terms_days: 0,
+            early_payment_discount_bps: 0,
+            discount_window_days: 0,
+            late_penalty_bps_per_day: 0,
+            requires_title_receipt: false,
+        },
         &String::from_str(&env, "QmBuyerLEI"),
         &String::from_str(&env, "QmSellerLEI"),
+        &None,
     );
 
     // Reject order
-    client.reject_order(&trade_id);
+    client.reject_order(&seller, &trade_id);
 
     let trade = client.get_trade(&trade_id);
-    assert_eq!(trade.state, REJECTED);
+    assert_eq!(trade.state, TradeState::Rejected);
 }
 
 #[test]
 fn test_cancel_trade() {
-    let (env, contract_id, admin, buyer, seller) = create_contract();
+    let (env, contract_id, _admin, buyer, seller) = create_contract();
     let client = MarketplaceEscrowV1Client::new(&env, &contract_id);
 
     // Setup
@@ -450,19 +635,3674 @@ fn test_cancel_trade() {
     );
 
     let trade_id = client.create_trade(
+        &buyer,
         &seller,
-        &String::from_str(&env, "Cotton T-shirts"),
-        &1000,
-        &15_0000000,
-        &15000_0000000,
-        &String::from_str(&env, "QmPO123"),
+        &PurchaseOrderDetails {
+            po_description: String::from_str(&env, "Cotton T-shirts"),
+            product: product_id(&env),
+            quantity: 1000,
+            unit_price: 15_0000000,
+            total_price: 15000_0000000,
+            po_json_ipfs_hash: String::from_str(&env, PO_CID),
+            po_json_hash: doc_hash(&env, PO_JSON),
+            delivery: delivery_terms(&env, Incoterm::Dap),
+            requires_inspection: false,
+            inspection_agency: None,
+            payment_schedule: Vec::new(&env),
+            performance_bond_rate: 0,
+            payment_terms_days: 0,
+            early_payment_discount_bps: 0,
+            discount_window_days: 0,
+            late_penalty_bps_per_day: 0,
+            requires_title_receipt: false,
+        },
         &String::from_str(&env, "QmBuyerLEI"),
         &String::from_str(&env, "QmSellerLEI"),
+        &None,
     );
 
     // Cancel trade
-    client.cancel_trade(&trade_id);
+    client.cancel_trade(&buyer, &trade_id);
+
+    let trade = client.get_trade(&trade_id);
+    assert_eq!(trade.state, TradeState::Cancelled);
+}
+
+/// Register the default buyer and seller and open a 15,000 XLM trade
+fn setup_trade(
+    env: &Env,
+    client: &MarketplaceEscrowV1Client,
+    buyer: &Address,
+    seller: &Address,
+) -> u64 {
+    if client.try_get_buyer_info(buyer).is_err() {
+        client.register_buyer(
+            buyer,
+            &String::from_str(env, "Tommy Hilfiger"),
+            &String::from_str(env, "549300VGEJK8QMIYGZ34"),
+        );
+        client.register_seller(
+            seller,
+            &String::from_str(env, "Jupiter Knitting"),
+            &String::from_str(env, "213800ABCDEF1234XYZ"),
+        );
+    }
+
+    client.create_trade(
+        buyer,
+        seller,
+        &PurchaseOrderDetails {
+            po_description: String::from_str(env, "Cotton T-shirts"),
+            product: product_id(env),
+            quantity: 1000,
+            unit_price: 15_0000000,
+            total_price: 15000_0000000,
+            po_json_ipfs_hash: String::from_str(env, PO_CID),
+            po_json_hash: doc_hash(env, PO_JSON),
+            delivery: delivery_terms(env, Incoterm::Dap),
+            requires_inspection: false,
+            inspection_agency: None,
+            payment_schedule: Vec::new(env),
+            performance_bond_rate: 0,
+            payment_terms_days: 0,
+            early_payment_discount_bps: 0,
+            discount_window_days: 0,
+            late_penalty_bps_per_day: 0,
+            requires_title_receipt: false,
+        },
+        &String::from_str(env, "QmBuyerLEI"),
+        &String::from_str(env, "QmSellerLEI"),
+        &None,
+    )
+}
+
+/// Rewrite storage the way a version 1 deployment left it
+fn downgrade_to_v1(env: &Env, contract_id: &Address) {
+    env.as_contract(contract_id, || {
+        let next_trade_id: u64 = env.storage().instance().get(&DataKey::NextTradeId).unwrap();
+        for trade_id in 1..next_trade_id {
+            let trade: TradeEscrow = env
+                .storage()
+                .instance()
+                .get(&DataKey::Trade(trade_id))
+                .unwrap();
+            let old = TradeEscrowV1 {
+                trade_id: trade.trade_id,
+                buyer: trade.buyer,
+                seller: trade.seller,
+                amount: trade.amount,
+                state: trade.state,
+                created_at: trade.created_at,
+                fulfilled_at: trade.fulfilled_at,
+                settled_at: trade.settled_at,
+                marketplace_fee: trade.marketplace_fee,
+                escrow_balance: trade.escrow_balance,
+            };
+            env.storage()
+                .instance()
+                .set(&DataKey::Trade(trade_id), &old);
+
+            let po: PurchaseOrder = env
+                .storage()
+                .instance()
+                .get(&DataKey::PurchaseOrder(trade_id))
+                .unwrap();
+            let old = PurchaseOrderV1 {
+                po_description: po.po_description,
+                quantity: po.quantity,
+                unit_price: po.unit_price,
+                total_price: po.total_price,
+                po_json_ipfs_hash: po.po_json_ipfs_hash,
+                created_by: po.created_by,
+                created_at: po.created_at,
+            };
+            env.storage()
+                .instance()
+                .set(&DataKey::PurchaseOrder(trade_id), &old);
+        }
+        env.storage().instance().remove(&DataKey::ContractVersion);
+    });
+}
+
+#[test]
+fn test_new_deployment_is_current_version() {
+    let (env, contract_id, _admin, _buyer, _seller) = create_contract();
+    let client = MarketplaceEscrowV1Client::new(&env, &contract_id);
+
+    assert_eq!(client.get_contract_version(), CONTRACT_VERSION);
+
+    // Nothing to migrate
+    assert!(client.migrate(&10));
+}
+
+#[test]
+fn test_migrate_v1_trades_in_batches() {
+    let (env, contract_id, _admin, buyer, seller) = create_contract();
+    let client = MarketplaceEscrowV1Client::new(&env, &contract_id);
+
+    env.ledger().set_timestamp(1_000);
+    let ordered_id = setup_trade(&env, &client, &buyer, &seller);
+    let funded_id = setup_trade(&env, &client, &buyer, &seller);
+    let EscrowCost {
+        total_required,
+        marketplace_fee: fee,
+        ..
+    } = client.calculate_escrow_cost(&buyer, &seller, &15000_0000000);
+    client.fund_escrow(&buyer, &funded_id, &total_required);
+    let cancelled_id = setup_trade(&env, &client, &buyer, &seller);
+    env.ledger().set_timestamp(2_000);
+    client.cancel_trade(&buyer, &cancelled_id);
+
+    downgrade_to_v1(&env, &contract_id);
+    assert_eq!(client.get_contract_version(), 1);
+
+    // Live trades and their documents are unreadable until the migration has run
+    assert_eq!(
+        client.try_get_trade(&ordered_id),
+        Err(Ok(ContractError::MigrationRequired))
+    );
+    assert_eq!(
+        client.try_get_trade_history(&ordered_id),
+        Err(Ok(ContractError::MigrationRequired))
+    );
+    assert_eq!(
+        client.try_get_purchase_order(&ordered_id),
+        Err(Ok(ContractError::MigrationRequired))
+    );
+    assert_eq!(
+        client.try_get_customer_invoice(&funded_id),
+        Err(Ok(ContractError::MigrationRequired))
+    );
+    assert_eq!(
+        client.try_get_warehouse_receipt(&funded_id),
+        Err(Ok(ContractError::MigrationRequired))
+    );
+    assert_eq!(
+        client.try_cancel_trade(&buyer, &ordered_id),
+        Err(Ok(ContractError::MigrationRequired))
+    );
+    assert_eq!(
+        client.try_migrate(&0),
+        Err(Ok(ContractError::InvalidMigrationBatch))
+    );
+
+    // First batch only covers two of the three trades
+    assert!(!client.migrate(&2));
+    assert_eq!(client.get_contract_version(), 1);
+    assert!(client.migrate(&2));
+    assert_eq!(client.get_contract_version(), CONTRACT_VERSION);
+
+    let ordered = client.get_trade(&ordered_id);
+    assert_eq!(ordered.state, TradeState::Ordered);
+    assert_eq!(ordered.updated_at, 1_000);
+
+    let funded = client.get_trade(&funded_id);
+    assert_eq!(funded.escrow_balance, total_required);
+    assert_eq!(funded.marketplace_fee, fee);
+
+    let cancelled = client.get_trade(&cancelled_id);
+    assert_eq!(cancelled.state, TradeState::Cancelled);
+    assert_eq!(cancelled.updated_at, 1_000);
+
+    // Documents are re-encoded without product identifiers or delivery terms
+    let po = client.get_purchase_order(&funded_id);
+    assert_eq!(po.quantity, 1000);
+    assert_eq!(po.product.gtin, None);
+    assert_eq!(po.delivery.incoterm, Incoterm::Exw);
+
+    // History is rebuilt from what version 1 recorded
+    let history = client.get_trade_history(&cancelled_id);
+    assert_eq!(history.len(), 2);
+    assert_eq!(history.get(1).unwrap().to, TradeState::Cancelled);
+    assert_eq!(history.get(1).unwrap().actor, buyer);
+
+    // Migrated trades continue through the lifecycle
+    client.validate_buyer_vlei(&funded_id);
+    client.fulfill_order(
+        &seller,
+        &funded_id,
+        &CustomerInvoiceDetails {
+            ci_description: String::from_str(&env, "Cotton T-shirts"),
+            product: product_id(&env),
+            quantity: 1000,
+            unit_price: 15_0000000,
+            total_price: 15000_0000000,
+            ci_json_ipfs_hash: String::from_str(&env, CI_CID),
+            ci_json_hash: doc_hash(&env, CI_JSON),
+            incoterm: Incoterm::Dap,
+            named_place: String::from_str(&env, DELIVERY_PLACE),
+        },
+        &WarehouseReceiptDetails {
+            wr_description: String::from_str(&env, "Cotton T-shirts"),
+            product: product_id(&env),
+            quantity: 1000,
+            unit_price: 15_0000000,
+            total_price: 15000_0000000,
+            wr_json_ipfs_hash: String::from_str(&env, WR_CID),
+            wr_json_hash: doc_hash(&env, WR_JSON),
+            warehouse_location: String::from_str(&env, WAREHOUSE_LOCATION),
+            lot: String::from_str(&env, LOT),
+        },
+    );
+    client.accept_trade(&buyer, &funded_id);
+    assert_eq!(client.get_trade(&funded_id).state, TradeState::Settled);
+
+    // New trades are written in the current layout
+    let new_id = setup_trade(&env, &client, &buyer, &seller);
+    assert_eq!(client.get_trade(&new_id).updated_at, 2_000);
+}
+
+#[test]
+fn test_newer_schema_is_rejected() {
+    let (env, contract_id, _admin, buyer, seller) = create_contract();
+    let client = MarketplaceEscrowV1Client::new(&env, &contract_id);
+
+    let trade_id = setup_trade(&env, &client, &buyer, &seller);
+    env.as_contract(&contract_id, || {
+        env.storage()
+            .instance()
+            .set(&DataKey::ContractVersion, &(CONTRACT_VERSION + 1));
+    });
+
+    assert_eq!(
+        client.try_get_trade(&trade_id),
+        Err(Ok(ContractError::UnsupportedContractVersion))
+    );
+    assert_eq!(
+        client.try_migrate(&10),
+        Err(Ok(ContractError::UnsupportedContractVersion))
+    );
+}
+
+/// Read the treasury and payment token the contract was set up with
+fn payment_accounts(env: &Env, contract_id: &Address) -> (Address, TokenClient<'static>) {
+    let (treasury, token): (Address, Address) = env.as_contract(contract_id, || {
+        (
+            env.storage()
+                .instance()
+                .get(&DataKey::PlatformTreasury)
+                .unwrap(),
+            env.storage()
+                .instance()
+                .get(&DataKey::PaymentToken)
+                .unwrap(),
+        )
+    });
+    (treasury, TokenClient::new(env, &token))
+}
+
+/// Product identifiers shared by the standard test documents
+fn product_id(env: &Env) -> ProductId {
+    ProductId {
+        gtin: Some(String::from_str(env, "00012345600012")),
+        hs_code: Some(String::from_str(env, "610910")),
+        sku: None,
+    }
+}
+
+// IPFS references and canonical JSON of the standard test documents
+const PO_CID: &str = "QmYwAPJzv5CZsnA625s3Xf2nemtYgPpHdWEz79ojWnPbdG";
+const PO_JSON: &str = r#"{"doc":"PO","product":"Cotton T-Shirts","quantity":1000}"#;
+const PO_CHANGE_CID: &str = "QmXoypizjW3WknFiJnKLwHCnL72vedxjQkDDP1mXWo6uco";
+const PO_CHANGE_JSON: &str = r#"{"doc":"PO","product":"Cotton T-Shirts","version":2}"#;
+const CI_CID: &str = "QmT78zSuBmuS4z925WZfrqQ1qHaJ56DQaTfyMUF7F8ff5o";
+const CI_JSON: &str = r#"{"doc":"CI","product":"Cotton T-Shirts","quantity":1000}"#;
+const WR_CID: &str = "bafybeigdyrzt5sfp7udm7hu76uh7y26nf3efuylqabf3oclgtqy55fbzdi";
+const WR_JSON: &str = r#"{"doc":"WR","product":"Cotton T-Shirts","quantity":1000}"#;
+
+/// Named place of delivery on the standard test documents
+const DELIVERY_PLACE: &str = "Port of Rotterdam";
+
+/// Warehouse and lot the goods on the standard WR are stored at
+const WAREHOUSE_LOCATION: &str = "Maasvlakte Distripark, Bay 12";
+const LOT: &str = "LOT-2024-0117";
+
+/// Delivery terms at `DELIVERY_PLACE` within the next 60 days
+fn delivery_terms(env: &Env, incoterm: Incoterm) -> DeliveryTerms {
+    let now = env.ledger().timestamp();
+    DeliveryTerms {
+        incoterm,
+        named_place: String::from_str(env, DELIVERY_PLACE),
+        window_start: now,
+        window_end: now + 60 * 86400,
+    }
+}
+
+/// SHA-256 of a document's canonical JSON
+fn doc_hash(env: &Env, json: &str) -> BytesN<32> {
+    env.crypto()
+        .sha256(&Bytes::from_slice(env, json.as_bytes()))
+        .into()
+}
+
+/// Fulfill a trade opened by `setup_trade` with documents matching the PO
+fn fulfill_matching(
+    env: &Env,
+    client: &MarketplaceEscrowV1Client,
+    seller: &Address,
+    trade_id: u64,
+) {
+    let (invoice, receipt) = matching_documents(env, client, trade_id);
+    client.validate_buyer_vlei(&trade_id);
+    client.fulfill_order(seller, &trade_id, &invoice, &receipt);
+}
+
+/// Invoice and receipt agreeing with the standard PO and its delivery terms
+fn matching_documents(
+    env: &Env,
+    client: &MarketplaceEscrowV1Client,
+    trade_id: u64,
+) -> (CustomerInvoiceDetails, WarehouseReceiptDetails) {
+    let delivery = client.get_purchase_order(&trade_id).delivery;
+    (
+        CustomerInvoiceDetails {
+            ci_description: String::from_str(env, "Cotton T-shirts"),
+            product: product_id(env),
+            quantity: 1000,
+            unit_price: 15_0000000,
+            total_price: 15000_0000000,
+            ci_json_ipfs_hash: String::from_str(env, CI_CID),
+            ci_json_hash: doc_hash(env, CI_JSON),
+            incoterm: delivery.incoterm,
+            named_place: delivery.named_place,
+        },
+        WarehouseReceiptDetails {
+            wr_description: String::from_str(env, "Cotton T-shirts"),
+            product: product_id(env),
+            quantity: 1000,
+            unit_price: 15_0000000,
+            total_price: 15000_0000000,
+            wr_json_ipfs_hash: String::from_str(env, WR_CID),
+            wr_json_hash: doc_hash(env, WR_JSON),
+            warehouse_location: String::from_str(env, WAREHOUSE_LOCATION),
+            lot: String::from_str(env, LOT),
+        },
+    )
+}
+
+#[test]
+fn test_fee_payer_policy() {
+    let (env, contract_id, _admin, buyer, seller) = create_contract();
+    let client = MarketplaceEscrowV1Client::new(&env, &contract_id);
+    setup_trade(&env, &client, &buyer, &seller);
+
+    // Buyer pays by default
+    assert_eq!(client.get_fee_payer(&seller), FeePayer::Buyer);
+
+    // Marketplace-wide default moves the fee to sellers
+    client.set_default_fee_payer(&FeePayer::Seller);
+    let cost = client.calculate_escrow_cost(&buyer, &seller, &10001);
+    assert_eq!(cost.fee_payer, FeePayer::Seller);
+    assert_eq!(cost.marketplace_fee, 25);
+    assert_eq!(cost.total_required, 10001);
+    assert_eq!(cost.seller_payout, 9976);
+
+    // Per-seller override wins over the default; odd unit goes to the seller
+    client.set_seller_fee_payer(&seller, &FeePayer::Split);
+    let cost = client.calculate_escrow_cost(&buyer, &seller, &10001);
+    assert_eq!(cost.fee_payer, FeePayer::Split);
+    assert_eq!(cost.buyer_fee, 12);
+    assert_eq!(cost.seller_fee, 13);
+    assert_eq!(cost.total_required, 10013);
+    assert_eq!(cost.seller_payout, 9988);
+
+    client.clear_seller_fee_payer(&seller);
+    assert_eq!(client.get_fee_payer(&seller), FeePayer::Seller);
+
+    // Overrides are only accepted for registered sellers
+    assert_eq!(
+        client.try_set_seller_fee_payer(&Address::generate(&env), &FeePayer::Buyer),
+        Err(Ok(ContractError::SellerNotRegistered))
+    );
+}
+
+#[test]
+fn test_settlement_transfers_by_fee_payer() {
+    let (env, contract_id, _admin, buyer, seller) = create_contract();
+    let client = MarketplaceEscrowV1Client::new(&env, &contract_id);
+    let (treasury, token) = payment_accounts(&env, &contract_id);
+    let starting_balance = token.balance(&buyer);
+
+    // Buyer-paid: fee on top of the escrow, seller receives the full amount
+    let trade_id = setup_trade(&env, &client, &buyer, &seller);
+    let cost = client.calculate_escrow_cost(&buyer, &seller, &15000_0000000);
+    client.fund_escrow(&buyer, &trade_id, &cost.total_required);
+    assert_eq!(token.balance(&contract_id), cost.total_required);
+    fulfill_matching(&env, &client, &seller, trade_id);
+    client.accept_trade(&buyer, &trade_id);
+
+    assert_eq!(token.balance(&buyer), starting_balance - 15037_5000000);
+    assert_eq!(token.balance(&seller), 15000_0000000);
+    assert_eq!(token.balance(&treasury), 37_5000000);
+    assert_eq!(token.balance(&contract_id), 0);
+    assert_eq!(client.get_trade(&trade_id).escrow_balance, 0);
+
+    // Seller-paid: buyer funds the bare amount, fee comes out of the payout
+    client.set_seller_fee_payer(&seller, &FeePayer::Seller);
+    let trade_id = setup_trade(&env, &client, &buyer, &seller);
+    let cost = client.calculate_escrow_cost(&buyer, &seller, &15000_0000000);
+    assert_eq!(cost.total_required, 15000_0000000);
+    client.fund_escrow(&buyer, &trade_id, &cost.total_required);
+    fulfill_matching(&env, &client, &seller, trade_id);
+    client.accept_trade(&buyer, &trade_id);
+
+    let trade = client.get_trade(&trade_id);
+    assert_eq!(trade.fee_payer, FeePayer::Seller);
+    assert_eq!(trade.seller_fee, 37_5000000);
+    assert_eq!(token.balance(&buyer), starting_balance - 30037_5000000);
+    assert_eq!(token.balance(&seller), 29962_5000000);
+    assert_eq!(token.balance(&treasury), 75_0000000);
+    assert_eq!(token.balance(&contract_id), 0);
+}
+
+#[test]
+fn test_cancel_and_reject_refund_buyer() {
+    let (env, contract_id, _admin, buyer, seller) = create_contract();
+    let client = MarketplaceEscrowV1Client::new(&env, &contract_id);
+    let (_treasury, token) = payment_accounts(&env, &contract_id);
+    let starting_balance = token.balance(&buyer);
+    let total_required = client
+        .calculate_escrow_cost(&buyer, &seller, &15000_0000000)
+        .total_required;
+
+    let cancelled_id = setup_trade(&env, &client, &buyer, &seller);
+    client.fund_escrow(&buyer, &cancelled_id, &total_required);
+    client.cancel_trade(&buyer, &cancelled_id);
+
+    let rejected_id = setup_trade(&env, &client, &buyer, &seller);
+    client.fund_escrow(&buyer, &rejected_id, &total_required);
+    client.reject_order(&seller, &rejected_id);
+
+    assert_eq!(token.balance(&buyer), starting_balance);
+    assert_eq!(token.balance(&contract_id), 0);
+    assert_eq!(client.get_trade(&cancelled_id).escrow_balance, 0);
+    assert_eq!(client.get_trade(&rejected_id).escrow_balance, 0);
+}
+
+/// Open, fund, fulfill and settle a 15,000 XLM trade
+fn settle_trade(
+    env: &Env,
+    client: &MarketplaceEscrowV1Client,
+    buyer: &Address,
+    seller: &Address,
+) -> u64 {
+    let trade_id = setup_trade(env, client, buyer, seller);
+    let cost = client.calculate_escrow_cost(buyer, seller, &15000_0000000);
+    client.fund_escrow(buyer, &trade_id, &cost.total_required);
+    fulfill_matching(env, client, seller, trade_id);
+    client.accept_trade(buyer, &trade_id);
+    trade_id
+}
+
+#[test]
+fn test_volume_tiered_fee_schedule() {
+    let (env, contract_id, _admin, buyer, seller) = create_contract();
+    let client = MarketplaceEscrowV1Client::new(&env, &contract_id);
+    env.ledger().set_timestamp(100 * 86400);
+
+    // 1% until 20,000 XLM of trailing volume, then 0.5%; fees between 10 and 100 XLM
+    let mut tiers = Vec::new(&env);
+    tiers.push_back(FeeTier {
+        min_volume: 0,
+        fee_rate: 100,
+    });
+    tiers.push_back(FeeTier {
+        min_volume: 20000_0000000,
+        fee_rate: 50,
+    });
+    client.set_fee_schedule(&FeeSchedule {
+        tiers,
+        volume_window_days: 30,
+        min_fee: 10_0000000,
+        max_fee: 100_0000000,
+    });
+
+    // 1% of 15,000 is 150 XLM, capped at 100
+    let cost = client.calculate_escrow_cost(&buyer, &seller, &15000_0000000);
+    assert_eq!(cost.fee_rate, 100);
+    assert_eq!(cost.marketplace_fee, 100_0000000);
+
+    let first_id = settle_trade(&env, &client, &buyer, &seller);
+    assert_eq!(client.get_trade(&first_id).marketplace_fee, 100_0000000);
+    assert_eq!(client.get_trailing_volume(&buyer, &30), 15000_0000000);
+    assert_eq!(client.get_fee_rate(&buyer, &seller), 100);
 
+    // Second settlement lifts both parties into the 0.5% tier
+    settle_trade(&env, &client, &buyer, &seller);
+    assert_eq!(client.get_trailing_volume(&seller, &30), 30000_0000000);
+    assert_eq!(client.get_fee_rate(&buyer, &seller), 50);
+
+    let trade_id = setup_trade(&env, &client, &buyer, &seller);
+    let cost = client.calculate_escrow_cost(&buyer, &seller, &15000_0000000);
+    client.fund_escrow(&buyer, &trade_id, &cost.total_required);
+    assert_eq!(client.get_trade(&trade_id).marketplace_fee, 75_0000000);
+
+    // Volume ages out of the trailing window
+    env.ledger().set_timestamp(130 * 86400);
+    assert_eq!(client.get_trailing_volume(&buyer, &30), 0);
+    assert_eq!(client.get_fee_rate(&buyer, &seller), 100);
+}
+
+#[test]
+fn test_negotiated_fee_rates() {
+    let (env, contract_id, _admin, buyer, seller) = create_contract();
+    let client = MarketplaceEscrowV1Client::new(&env, &contract_id);
+
+    let mut tiers = Vec::new(&env);
+    tiers.push_back(FeeTier {
+        min_volume: 0,
+        fee_rate: 100,
+    });
+    client.set_fee_schedule(&FeeSchedule {
+        tiers,
+        volume_window_days: 30,
+        min_fee: 10_0000000,
+        max_fee: 0,
+    });
+
+    // Seller's negotiated rate replaces the schedule
+    client.set_negotiated_fee_rate(&seller, &10);
+    let cost = client.calculate_escrow_cost(&buyer, &seller, &15000_0000000);
+    assert_eq!(cost.fee_rate, 10);
+    assert_eq!(cost.marketplace_fee, 15_0000000);
+
+    // Lower of the two negotiated rates applies, still subject to the minimum fee
+    client.set_negotiated_fee_rate(&buyer, &5);
+    let cost = client.calculate_escrow_cost(&buyer, &seller, &15000_0000000);
+    assert_eq!(cost.fee_rate, 5);
+    assert_eq!(cost.marketplace_fee, 10_0000000);
+
+    client.clear_negotiated_fee_rate(&buyer);
+    client.clear_negotiated_fee_rate(&seller);
+    assert_eq!(client.get_fee_rate(&buyer, &seller), 100);
+
+    // Without a schedule the flat constructor rate applies again
+    client.clear_fee_schedule();
+    assert_eq!(client.get_fee_rate(&buyer, &seller), 25);
+
+    assert_eq!(
+        client.try_set_negotiated_fee_rate(&buyer, &1001),
+        Err(Ok(ContractError::InvalidFeeRate))
+    );
+}
+
+#[test]
+fn test_invalid_fee_schedules() {
+    let (env, contract_id, _admin, _buyer, _seller) = create_contract();
+    let client = MarketplaceEscrowV1Client::new(&env, &contract_id);
+
+    let schedule = |tiers: &[(i128, u32)], min_fee: i128, max_fee: i128| {
+        let mut fee_tiers = Vec::new(&env);
+        for (min_volume, fee_rate) in tiers {
+            fee_tiers.push_back(FeeTier {
+                min_volume: *min_volume,
+                fee_rate: *fee_rate,
+            });
+        }
+        FeeSchedule {
+            tiers: fee_tiers,
+            volume_window_days: 30,
+            min_fee,
+            max_fee,
+        }
+    };
+
+    let invalid = [
+        schedule(&[], 0, 0),
+        schedule(&[(100, 50)], 0, 0),
+        schedule(&[(0, 50), (0, 40)], 0, 0),
+        schedule(&[(0, 50)], 20, 10),
+    ];
+    for fee_schedule in invalid.iter() {
+        assert_eq!(
+            client.try_set_fee_schedule(fee_schedule),
+            Err(Ok(ContractError::InvalidFeeSchedule))
+        );
+    }
+
+    assert_eq!(
+        client.try_set_fee_schedule(&schedule(&[(0, 1001)], 0, 0)),
+        Err(Ok(ContractError::InvalidFeeRate))
+    );
+    assert_eq!(client.get_fee_schedule(), None);
+}
+
+/// Register a broker for referral tests
+fn register_broker(env: &Env, client: &MarketplaceEscrowV1Client) -> Address {
+    let broker = Address::generate(env);
+    client.register_broker(
+        &broker,
+        &String::from_str(env, "Sourcing Agents Ltd"),
+        &String::from_str(env, "984500ABCDEF5678XYZ"),
+    );
+    broker
+}
+
+#[test]
+fn test_broker_commission_split() {
+    let (env, contract_id, _admin, buyer, seller) = create_contract();
+    let client = MarketplaceEscrowV1Client::new(&env, &contract_id);
+    let (treasury, token) = payment_accounts(&env, &contract_id);
+    let broker = register_broker(&env, &client);
+    setup_trade(&env, &client, &buyer, &seller);
+
+    assert_eq!(client.get_all_brokers().len(), 1);
+    assert!(client.get_broker_info(&broker).is_active);
+
+    // 2% commission introduced by the broker
+    let trade_id = client.create_trade(
+        &buyer,
+        &seller,
+        &PurchaseOrderDetails {
+            po_description: String::from_str(&env, "Cotton T-shirts"),
+            product: product_id(&env),
+            quantity: 1000,
+            unit_price: 15_0000000,
+            total_price: 15000_0000000,
+            po_json_ipfs_hash: String::from_str(&env, PO_CID),
+            po_json_hash: doc_hash(&env, PO_JSON),
+            delivery: delivery_terms(&env, Incoterm::Dap),
+            requires_inspection: false,
+            inspection_agency: None,
+            payment_schedule: Vec::new(&env),
+            performance_bond_rate: 0,
+            payment_terms_days: 0,
+            early_payment_discount_bps: 0,
+            discount_window_days: 0,
+            late_penalty_bps_per_day: 0,
+            requires_title_receipt: false,
+        },
+        &String::from_str(&env, "QmBuyerLEI"),
+        &String::from_str(&env, "QmSellerLEI"),
+        &Some(BrokerCommission {
+            broker: broker.clone(),
+            commission_rate: 200,
+        }),
+    );
+    // The seller agreed to the commission taken from its payout
+    assert!(env.auths().iter().any(|(address, _)| address == &seller));
     let trade = client.get_trade(&trade_id);
-    assert_eq!(trade.state, CANCELLED);
+    assert_eq!(trade.broker, Some(broker.clone()));
+    assert_eq!(trade.broker_commission, 300_0000000);
+
+    let cost = client.calculate_escrow_cost(&buyer, &seller, &15000_0000000);
+    client.fund_escrow(&buyer, &trade_id, &cost.total_required);
+    fulfill_matching(&env, &client, &seller, trade_id);
+    client.accept_trade(&buyer, &trade_id);
+
+    assert_eq!(token.balance(&seller), 14700_0000000);
+    assert_eq!(token.balance(&broker), 300_0000000);
+    assert_eq!(token.balance(&treasury), 37_5000000);
+    assert_eq!(token.balance(&contract_id), 0);
+    assert_eq!(client.get_broker_earnings(&broker), 300_0000000);
+}
+
+#[test]
+fn test_broker_validation() {
+    let (env, contract_id, _admin, buyer, seller) = create_contract();
+    let client = MarketplaceEscrowV1Client::new(&env, &contract_id);
+    let broker = register_broker(&env, &client);
+    setup_trade(&env, &client, &buyer, &seller);
+
+    let create = |broker: &Address, commission_rate: u32| {
+        client.try_create_trade(
+            &buyer,
+            &seller,
+            &PurchaseOrderDetails {
+                po_description: String::from_str(&env, "Cotton T-shirts"),
+                product: product_id(&env),
+                quantity: 1000,
+                unit_price: 15_0000000,
+                total_price: 15000_0000000,
+                po_json_ipfs_hash: String::from_str(&env, PO_CID),
+                po_json_hash: doc_hash(&env, PO_JSON),
+                delivery: delivery_terms(&env, Incoterm::Dap),
+                requires_inspection: false,
+                inspection_agency: None,
+                payment_schedule: Vec::new(&env),
+                performance_bond_rate: 0,
+                payment_terms_days: 0,
+                early_payment_discount_bps: 0,
+                discount_window_days: 0,
+                late_penalty_bps_per_day: 0,
+                requires_title_receipt: false,
+            },
+            &String::from_str(&env, "QmBuyerLEI"),
+            &String::from_str(&env, "QmSellerLEI"),
+            &Some(BrokerCommission {
+                broker: broker.clone(),
+                commission_rate,
+            }),
+        )
+    };
+
+    assert_eq!(
+        create(&broker, 2001),
+        Err(Ok(ContractError::InvalidCommissionRate))
+    );
+    assert_eq!(
+        create(&seller, 100),
+        Err(Ok(ContractError::BrokerCannotBeParty))
+    );
+    assert_eq!(
+        create(&Address::generate(&env), 100),
+        Err(Ok(ContractError::BrokerNotRegistered))
+    );
+
+    client.deactivate_broker(&broker);
+    assert_eq!(create(&broker, 100), Err(Ok(ContractError::BrokerInactive)));
+}
+
+#[test]
+fn test_fee_ledger_by_period() {
+    let (env, contract_id, _admin, buyer, seller) = create_contract();
+    let client = MarketplaceEscrowV1Client::new(&env, &contract_id);
+    let (treasury, token) = payment_accounts(&env, &contract_id);
+    let asset = token.address.clone();
+
+    // Day 10: one trade funded then cancelled, one settled
+    env.ledger().set_timestamp(10 * 86400);
+    let cancelled_id = setup_trade(&env, &client, &buyer, &seller);
+    let cost = client.calculate_escrow_cost(&buyer, &seller, &15000_0000000);
+    client.fund_escrow(&buyer, &cancelled_id, &cost.total_required);
+    client.cancel_trade(&buyer, &cancelled_id);
+    settle_trade(&env, &client, &buyer, &seller);
+
+    // Day 12: another settled trade
+    env.ledger().set_timestamp(12 * 86400);
+    settle_trade(&env, &client, &buyer, &seller);
+
+    // Only the settled trades booked a fee
+    let ledger = client.get_fee_ledger(&asset);
+    assert_eq!(ledger.earned, 75_0000000);
+    assert_eq!(ledger.withdrawn, 0);
+
+    // Fees were pushed straight to the treasury
+    assert_eq!(token.balance(&treasury), 75_0000000);
+    assert_eq!(client.get_retained_fees(&asset), 0);
+
+    let totals = client.get_fee_totals(&asset, &9, &13);
+    assert_eq!(totals.len(), 2);
+    assert_eq!(totals.get(0).unwrap().period, 10);
+    assert_eq!(totals.get(0).unwrap().ledger.earned, 37_5000000);
+    assert_eq!(totals.get(1).unwrap().period, 12);
+    assert_eq!(totals.get(1).unwrap().ledger.earned, 37_5000000);
+
+    assert_eq!(
+        client.try_get_fee_totals(&asset, &13, &9),
+        Err(Ok(ContractError::InvalidPeriodRange))
+    );
+    assert_eq!(
+        client.try_get_fee_totals(&asset, &0, &366),
+        Err(Ok(ContractError::InvalidPeriodRange))
+    );
+
+    // Ledgers and history stay out of instance storage
+    env.as_contract(&contract_id, || {
+        for key in [
+            DataKey::FeeLedger(asset.clone()),
+            DataKey::FeePeriod(asset.clone(), 10),
+            DataKey::SettledVolume(buyer.clone()),
+            DataKey::TradeHistory(cancelled_id),
+        ] {
+            assert!(env.storage().persistent().has(&key));
+            assert!(!env.storage().instance().has(&key));
+        }
+    });
+}
+
+#[test]
+fn test_fees_booked_at_settlement() {
+    let (env, contract_id, _admin, buyer, seller) = create_contract();
+    let client = MarketplaceEscrowV1Client::new(&env, &contract_id);
+    let (treasury, token) = payment_accounts(&env, &contract_id);
+    let asset = token.address.clone();
+    let cost = client.calculate_escrow_cost(&buyer, &seller, &15000_0000000);
+
+    // Funded trades that are cancelled, rejected or repriced book nothing
+    let cancelled_id = setup_trade(&env, &client, &buyer, &seller);
+    client.fund_escrow(&buyer, &cancelled_id, &cost.total_required);
+    assert_eq!(client.get_fee_ledger(&asset).earned, 0);
+    client.cancel_trade(&buyer, &cancelled_id);
+
+    let rejected_id = setup_trade(&env, &client, &buyer, &seller);
+    client.fund_escrow(&buyer, &rejected_id, &cost.total_required);
+    client.reject_order(&seller, &rejected_id);
+
+    let repriced_id = setup_trade(&env, &client, &buyer, &seller);
+    client.fund_escrow(&buyer, &repriced_id, &cost.total_required);
+    client.propose_change_order(
+        &buyer,
+        &repriced_id,
+        &change_terms(&env, 900, 13500_0000000),
+    );
+    client.accept_change_order(&seller, &repriced_id, &1, &0);
+    assert_eq!(client.get_fee_ledger(&asset).earned, 0);
+    client.cancel_trade(&buyer, &repriced_id);
+    assert_eq!(client.get_fee_ledger(&asset).earned, 0);
+
+    // Settling books the fee the treasury was actually paid
+    settle_trade(&env, &client, &buyer, &seller);
+    let ledger = client.get_fee_ledger(&asset);
+    assert_eq!(ledger.earned, cost.marketplace_fee);
+    assert_eq!(ledger.earned, token.balance(&treasury));
+}
+
+#[test]
+fn test_withdraw_retained_fees() {
+    let (env, contract_id, _admin, buyer, seller) = create_contract();
+    let client = MarketplaceEscrowV1Client::new(&env, &contract_id);
+    let (treasury, token) = payment_accounts(&env, &contract_id);
+    let asset = token.address.clone();
+    let fee_manager = Address::generate(&env);
+    let finance = Address::generate(&env);
+
+    client.set_fee_retention(&true);
+    client.set_fee_manager(&fee_manager);
+    assert_eq!(client.get_fee_manager(), Some(fee_manager.clone()));
+
+    settle_trade(&env, &client, &buyer, &seller);
+    assert_eq!(token.balance(&treasury), 0);
+    assert_eq!(client.get_retained_fees(&asset), 37_5000000);
+    assert_eq!(token.balance(&contract_id), 37_5000000);
+
+    // Only the owner or fee manager may withdraw, and only what is retained
+    assert_eq!(
+        client.try_withdraw_fees(&buyer, &asset, &10_0000000, &buyer),
+        Err(Ok(ContractError::Unauthorized))
+    );
+    assert_eq!(
+        client.try_withdraw_fees(&fee_manager, &asset, &40_0000000, &finance),
+        Err(Ok(ContractError::InsufficientRetainedFees))
+    );
+
+    client.withdraw_fees(&fee_manager, &asset, &20_0000000, &finance);
+    assert_eq!(token.balance(&finance), 20_0000000);
+    assert_eq!(client.get_retained_fees(&asset), 17_5000000);
+    assert_eq!(client.get_fee_ledger(&asset).withdrawn, 20_0000000);
+
+    client.withdraw_fees(&contract_id, &asset, &17_5000000, &treasury);
+    assert_eq!(token.balance(&treasury), 17_5000000);
+    assert_eq!(token.balance(&contract_id), 0);
+}
+
+#[test]
+fn test_trade_history() {
+    let (env, contract_id, _admin, buyer, seller) = create_contract();
+    let client = MarketplaceEscrowV1Client::new(&env, &contract_id);
+
+    env.ledger().set_timestamp(100);
+    let trade_id = setup_trade(&env, &client, &buyer, &seller);
+    let cost = client.calculate_escrow_cost(&buyer, &seller, &15000_0000000);
+    client.fund_escrow(&buyer, &trade_id, &cost.total_required);
+
+    env.ledger().set_timestamp(200);
+    fulfill_matching(&env, &client, &seller, trade_id);
+
+    env.ledger().set_timestamp(300);
+    client.accept_trade(&buyer, &trade_id);
+
+    let history = client.get_trade_history(&trade_id);
+    assert_eq!(history.len(), 3);
+    assert_eq!(
+        history.get(0).unwrap(),
+        StateChange {
+            from: TradeState::Ordered,
+            to: TradeState::Ordered,
+            timestamp: 100,
+            actor: buyer.clone(),
+        }
+    );
+    assert_eq!(
+        history.get(1).unwrap(),
+        StateChange {
+            from: TradeState::Ordered,
+            to: TradeState::Fulfilled,
+            timestamp: 200,
+            actor: seller.clone(),
+        }
+    );
+    assert_eq!(
+        history.get(2).unwrap(),
+        StateChange {
+            from: TradeState::Fulfilled,
+            to: TradeState::Settled,
+            timestamp: 300,
+            actor: buyer.clone(),
+        }
+    );
+    assert_eq!(client.get_trade(&trade_id).updated_at, 300);
+
+    assert_eq!(
+        client.try_get_trade_history(&99),
+        Err(Ok(ContractError::TradeNotFound))
+    );
+}
+
+#[test]
+fn test_illegal_transitions_rejected() {
+    let (env, contract_id, _admin, buyer, seller) = create_contract();
+    let client = MarketplaceEscrowV1Client::new(&env, &contract_id);
+
+    // Terminal states cannot be left
+    let settled_id = settle_trade(&env, &client, &buyer, &seller);
+    assert_eq!(
+        client.try_cancel_trade(&buyer, &settled_id),
+        Err(Ok(ContractError::InvalidTradeState))
+    );
+    assert_eq!(
+        client.try_reject_order(&seller, &settled_id),
+        Err(Ok(ContractError::InvalidTradeState))
+    );
+
+    let cancelled_id = setup_trade(&env, &client, &buyer, &seller);
+    client.cancel_trade(&buyer, &cancelled_id);
+    assert_eq!(
+        client.try_reject_order(&seller, &cancelled_id),
+        Err(Ok(ContractError::InvalidTradeState))
+    );
+    assert_eq!(
+        client.try_accept_trade(&buyer, &cancelled_id),
+        Err(Ok(ContractError::TradeNotFulfilled))
+    );
+    assert_eq!(client.get_trade_history(&cancelled_id).len(), 2);
+
+    // Only the four lifecycle edges are allowed
+    let states = [
+        TradeState::Ordered,
+        TradeState::Fulfilled,
+        TradeState::Settled,
+        TradeState::Rejected,
+        TradeState::Cancelled,
+    ];
+    let mut allowed = 0;
+    for from in states {
+        for to in states {
+            if state::can_transition(from, to) {
+                allowed += 1;
+            }
+        }
+    }
+    assert_eq!(allowed, 4);
+    assert!(state::can_transition(
+        TradeState::Fulfilled,
+        TradeState::Settled
+    ));
+    assert!(!state::can_transition(
+        TradeState::Cancelled,
+        TradeState::Settled
+    ));
+}
+
+#[test]
+fn test_fund_escrow_pulls_exact_amount() {
+    let (env, contract_id, _admin, buyer, seller) = create_contract();
+    let client = MarketplaceEscrowV1Client::new(&env, &contract_id);
+    let (_treasury, token) = payment_accounts(&env, &contract_id);
+
+    let trade_id = setup_trade(&env, &client, &buyer, &seller);
+    let cost = client.calculate_escrow_cost(&buyer, &seller, &15000_0000000);
+    let buyer_start = token.balance(&buyer);
+
+    // Overpaying only authorizes a ceiling; the surplus never leaves the buyer
+    client.fund_escrow(&buyer, &trade_id, &(cost.total_required + 500_0000000));
+
+    assert_eq!(token.balance(&buyer), buyer_start - cost.total_required);
+    assert_eq!(token.balance(&contract_id), cost.total_required);
+    assert_eq!(
+        client.get_trade(&trade_id).escrow_balance,
+        cost.total_required
+    );
+
+    // Nothing to top up on a fully funded trade
+    assert_eq!(
+        client.try_top_up_escrow(&buyer, &trade_id, &1_0000000),
+        Err(Ok(ContractError::EscrowAlreadyFunded))
+    );
+
+    // Settlement leaves nothing stranded in the contract
+    fulfill_matching(&env, &client, &seller, trade_id);
+    client.accept_trade(&buyer, &trade_id);
+    assert_eq!(token.balance(&contract_id), 0);
+    assert_eq!(client.get_trade(&trade_id).escrow_balance, 0);
+}
+
+#[test]
+fn test_top_up_escrow() {
+    let (env, contract_id, _admin, buyer, seller) = create_contract();
+    let client = MarketplaceEscrowV1Client::new(&env, &contract_id);
+    let (_treasury, token) = payment_accounts(&env, &contract_id);
+
+    let trade_id = setup_trade(&env, &client, &buyer, &seller);
+    assert_eq!(
+        client.try_top_up_escrow(&buyer, &trade_id, &1_0000000),
+        Err(Ok(ContractError::EscrowNotFunded))
+    );
+
+    let cost = client.calculate_escrow_cost(&buyer, &seller, &15000_0000000);
+    client.fund_escrow(&buyer, &trade_id, &cost.total_required);
+
+    // Raise the order value underneath the funded escrow
+    env.as_contract(&contract_id, || {
+        let mut trade: TradeEscrow = env
+            .storage()
+            .instance()
+            .get(&DataKey::Trade(trade_id))
+            .unwrap();
+        trade.amount += 1000_0000000;
+        env.storage()
+            .instance()
+            .set(&DataKey::Trade(trade_id), &trade);
+    });
+
+    // Seller cannot deliver against an underfunded escrow
+    client.validate_buyer_vlei(&trade_id);
+    assert_eq!(
+        client.try_fulfill_order(
+            &seller,
+            &trade_id,
+            &CustomerInvoiceDetails {
+                ci_description: String::from_str(&env, "Cotton T-Shirts"),
+                product: product_id(&env),
+                quantity: 1000,
+                unit_price: 15_0000000,
+                total_price: 15000_0000000,
+                ci_json_ipfs_hash: String::from_str(&env, CI_CID),
+                ci_json_hash: doc_hash(&env, CI_JSON),
+                incoterm: Incoterm::Dap,
+                named_place: String::from_str(&env, DELIVERY_PLACE),
+            },
+            &WarehouseReceiptDetails {
+                wr_description: String::from_str(&env, "Cotton T-Shirts"),
+                product: product_id(&env),
+                quantity: 1000,
+                unit_price: 15_0000000,
+                total_price: 15000_0000000,
+                wr_json_ipfs_hash: String::from_str(&env, WR_CID),
+                wr_json_hash: doc_hash(&env, WR_JSON),
+                warehouse_location: String::from_str(&env, WAREHOUSE_LOCATION),
+                lot: String::from_str(&env, LOT),
+            },
+        ),
+        Err(Ok(ContractError::InsufficientEscrowFunding))
+    );
+
+    assert_eq!(
+        client.try_top_up_escrow(&seller, &trade_id, &1000_0000000),
+        Err(Ok(ContractError::NotBuyer))
+    );
+    assert_eq!(
+        client.try_top_up_escrow(&buyer, &trade_id, &999_0000000),
+        Err(Ok(ContractError::InsufficientEscrowFunding))
+    );
+
+    let buyer_before = token.balance(&buyer);
+    assert_eq!(
+        client.top_up_escrow(&buyer, &trade_id, &2000_0000000),
+        1000_0000000
+    );
+    assert_eq!(token.balance(&buyer), buyer_before - 1000_0000000);
+    assert_eq!(
+        client.get_trade(&trade_id).escrow_balance,
+        cost.total_required + 1000_0000000
+    );
+}
+
+fn change_terms(env: &Env, quantity: u64, total_price: i128) -> PurchaseOrderDetails {
+    PurchaseOrderDetails {
+        po_description: String::from_str(env, "Cotton T-shirts"),
+        product: product_id(env),
+        quantity,
+        unit_price: 15_0000000,
+        total_price,
+        po_json_ipfs_hash: String::from_str(env, PO_CHANGE_CID),
+        po_json_hash: doc_hash(env, PO_CHANGE_JSON),
+        delivery: delivery_terms(env, Incoterm::Dap),
+        requires_inspection: false,
+        inspection_agency: None,
+        payment_schedule: Vec::new(env),
+        performance_bond_rate: 0,
+        payment_terms_days: 0,
+        early_payment_discount_bps: 0,
+        discount_window_days: 0,
+        late_penalty_bps_per_day: 0,
+        requires_title_receipt: false,
+    }
+}
+
+#[test]
+fn test_change_order_increase_accepted_by_buyer() {
+    let (env, contract_id, _admin, buyer, seller) = create_contract();
+    let client = MarketplaceEscrowV1Client::new(&env, &contract_id);
+    let (_treasury, token) = payment_accounts(&env, &contract_id);
+
+    let trade_id = setup_trade(&env, &client, &buyer, &seller);
+    let original = client.calculate_escrow_cost(&buyer, &seller, &15000_0000000);
+    client.fund_escrow(&buyer, &trade_id, &original.total_required);
+
+    // Seller asks for a larger order
+    env.ledger().set_timestamp(500);
+    let terms = change_terms(&env, 1200, 18000_0000000);
+    assert_eq!(client.propose_change_order(&seller, &trade_id, &terms), 2);
+    let pending = client.get_pending_change_order(&trade_id).unwrap();
+    assert_eq!(pending.proposal, 1);
+    assert_eq!(pending.proposed_by, seller);
+    assert_eq!(pending.terms, terms);
+
+    // Consent must come from the counterparty
+    assert_eq!(
+        client.try_accept_change_order(&seller, &trade_id, &1, &0),
+        Err(Ok(ContractError::CannotAcceptOwnChangeOrder))
+    );
+    assert_eq!(
+        client.try_accept_change_order(&Address::generate(&env), &trade_id, &1, &0),
+        Err(Ok(ContractError::NotTradeParty))
+    );
+
+    // Terms swapped after the buyer reviewed them are not accepted
+    client.propose_change_order(&seller, &trade_id, &change_terms(&env, 2000, 30000_0000000));
+    let shortfall = client
+        .calculate_escrow_cost(&buyer, &seller, &18000_0000000)
+        .total_required
+        - original.total_required;
+    assert_eq!(
+        client.try_accept_change_order(&buyer, &trade_id, &1, &shortfall),
+        Err(Ok(ContractError::ChangeOrderMismatch))
+    );
+    client.propose_change_order(&seller, &trade_id, &terms);
+    assert_eq!(
+        client.get_pending_change_order(&trade_id).unwrap().proposal,
+        3
+    );
+
+    // The buyer caps what the shortfall may pull from them
+    assert_eq!(
+        client.try_accept_change_order(&buyer, &trade_id, &3, &(shortfall - 1)),
+        Err(Ok(ContractError::InsufficientEscrowFunding))
+    );
+
+    // Buyer accepts and the shortfall is pulled in the same call
+    let buyer_before = token.balance(&buyer);
+    assert_eq!(
+        client.accept_change_order(&buyer, &trade_id, &3, &shortfall),
+        2
+    );
+
+    let repriced = client.calculate_escrow_cost(&buyer, &seller, &18000_0000000);
+    let trade = client.get_trade(&trade_id);
+    assert_eq!(trade.amount, 18000_0000000);
+    assert_eq!(trade.po_version, 2);
+    assert_eq!(trade.marketplace_fee, repriced.marketplace_fee);
+    assert_eq!(trade.escrow_balance, repriced.total_required);
+    assert_eq!(
+        token.balance(&buyer),
+        buyer_before - (repriced.total_required - original.total_required)
+    );
+    assert_eq!(token.balance(&contract_id), repriced.total_required);
+
+    // Both versions remain readable
+    let po = client.get_purchase_order(&trade_id);
+    assert_eq!(po.quantity, 1200);
+    assert_eq!(po.created_by, seller);
+    assert_eq!(po.created_at, 500);
+    assert_eq!(client.get_purchase_order_version(&trade_id, &2), po);
+    assert_eq!(
+        client.get_purchase_order_version(&trade_id, &1).quantity,
+        1000
+    );
+    assert_eq!(
+        client.try_get_purchase_order_version(&trade_id, &3),
+        Err(Ok(ContractError::PurchaseOrderVersionNotFound))
+    );
+    assert_eq!(client.get_pending_change_order(&trade_id), None);
+    assert_eq!(
+        client.try_accept_change_order(&buyer, &trade_id, &3, &0),
+        Err(Ok(ContractError::ChangeOrderNotFound))
+    );
+}
+
+#[test]
+fn test_change_order_refunds_and_top_up() {
+    let (env, contract_id, _admin, buyer, seller) = create_contract();
+    let client = MarketplaceEscrowV1Client::new(&env, &contract_id);
+    let (_treasury, token) = payment_accounts(&env, &contract_id);
+
+    let trade_id = setup_trade(&env, &client, &buyer, &seller);
+    let original = client.calculate_escrow_cost(&buyer, &seller, &15000_0000000);
+    client.fund_escrow(&buyer, &trade_id, &original.total_required);
+
+    // Buyer scales the order down; surplus comes back on the seller's acceptance
+    client.propose_change_order(&buyer, &trade_id, &change_terms(&env, 900, 13500_0000000));
+    let buyer_before = token.balance(&buyer);
+    client.accept_change_order(&seller, &trade_id, &1, &0);
+
+    let reduced = client.calculate_escrow_cost(&buyer, &seller, &13500_0000000);
+    assert_eq!(
+        token.balance(&buyer),
+        buyer_before + (original.total_required - reduced.total_required)
+    );
+    assert_eq!(
+        client.get_trade(&trade_id).escrow_balance,
+        reduced.total_required
+    );
+
+    // Scaling back up on the seller's acceptance leaves a shortfall to top up
+    client.propose_change_order(&buyer, &trade_id, &change_terms(&env, 1000, 15000_0000000));
+    client.accept_change_order(&seller, &trade_id, &2, &0);
+
+    let trade = client.get_trade(&trade_id);
+    assert_eq!(trade.po_version, 3);
+    assert_eq!(trade.escrow_balance, reduced.total_required);
+    assert_eq!(
+        client.top_up_escrow(&buyer, &trade_id, &original.total_required),
+        original.total_required - reduced.total_required
+    );
+    assert_eq!(
+        client.get_trade(&trade_id).escrow_balance,
+        original.total_required
+    );
+
+    // Terms are frozen once the seller delivers
+    fulfill_matching(&env, &client, &seller, trade_id);
+    assert_eq!(
+        client.try_propose_change_order(&buyer, &trade_id, &change_terms(&env, 1, 15_0000000)),
+        Err(Ok(ContractError::InvalidTradeState))
+    );
+    client.accept_trade(&buyer, &trade_id);
+    assert_eq!(token.balance(&contract_id), 0);
+}
+
+#[test]
+fn test_document_totals_must_add_up() {
+    let (env, contract_id, _admin, buyer, seller) = create_contract();
+    let client = MarketplaceEscrowV1Client::new(&env, &contract_id);
+    let trade_id = setup_trade(&env, &client, &buyer, &seller);
+
+    // 1000 × 15 XLM is not 16,000 XLM
+    assert_eq!(
+        client.try_create_trade(
+            &buyer,
+            &seller,
+            &PurchaseOrderDetails {
+                po_description: String::from_str(&env, "Cotton T-shirts"),
+                product: product_id(&env),
+                quantity: 1000,
+                unit_price: 15_0000000,
+                total_price: 16000_0000000,
+                po_json_ipfs_hash: String::from_str(&env, PO_CID),
+                po_json_hash: doc_hash(&env, PO_JSON),
+                delivery: delivery_terms(&env, Incoterm::Dap),
+                requires_inspection: false,
+                inspection_agency: None,
+                payment_schedule: Vec::new(&env),
+                performance_bond_rate: 0,
+                payment_terms_days: 0,
+                early_payment_discount_bps: 0,
+                discount_window_days: 0,
+                late_penalty_bps_per_day: 0,
+                requires_title_receipt: false,
+            },
+            &String::from_str(&env, "QmBuyerLEI"),
+            &String::from_str(&env, "QmSellerLEI"),
+            &None,
+        ),
+        Err(Ok(ContractError::InconsistentDocumentTotals))
+    );
+    assert_eq!(
+        client.try_create_trade(
+            &buyer,
+            &seller,
+            &PurchaseOrderDetails {
+                po_description: String::from_str(&env, "Cotton T-shirts"),
+                product: product_id(&env),
+                quantity: 0,
+                unit_price: 15_0000000,
+                total_price: 15000_0000000,
+                po_json_ipfs_hash: String::from_str(&env, PO_CID),
+                po_json_hash: doc_hash(&env, PO_JSON),
+                delivery: delivery_terms(&env, Incoterm::Dap),
+                requires_inspection: false,
+                inspection_agency: None,
+                payment_schedule: Vec::new(&env),
+                performance_bond_rate: 0,
+                payment_terms_days: 0,
+                early_payment_discount_bps: 0,
+                discount_window_days: 0,
+                late_penalty_bps_per_day: 0,
+                requires_title_receipt: false,
+            },
+            &String::from_str(&env, "QmBuyerLEI"),
+            &String::from_str(&env, "QmSellerLEI"),
+            &None,
+        ),
+        Err(Ok(ContractError::InvalidAmount))
+    );
+    assert_eq!(
+        client.try_propose_change_order(
+            &buyer,
+            &trade_id,
+            &change_terms(&env, 1200, 15000_0000000)
+        ),
+        Err(Ok(ContractError::InconsistentDocumentTotals))
+    );
+
+    let cost = client.calculate_escrow_cost(&buyer, &seller, &15000_0000000);
+    client.fund_escrow(&buyer, &trade_id, &cost.total_required);
+    client.validate_buyer_vlei(&trade_id);
+
+    // Warehouse receipt total is checked on its own, not copied from the invoice
+    assert_eq!(
+        client.try_fulfill_order(
+            &seller,
+            &trade_id,
+            &CustomerInvoiceDetails {
+                ci_description: String::from_str(&env, "Cotton T-shirts"),
+                product: product_id(&env),
+                quantity: 1000,
+                unit_price: 15_0000000,
+                total_price: 15000_0000000,
+                ci_json_ipfs_hash: String::from_str(&env, CI_CID),
+                ci_json_hash: doc_hash(&env, CI_JSON),
+                incoterm: Incoterm::Dap,
+                named_place: String::from_str(&env, DELIVERY_PLACE),
+            },
+            &WarehouseReceiptDetails {
+                wr_description: String::from_str(&env, "Cotton T-shirts"),
+                product: product_id(&env),
+                quantity: 1000,
+                unit_price: 15_0000000,
+                total_price: 14000_0000000,
+                wr_json_ipfs_hash: String::from_str(&env, WR_CID),
+                wr_json_hash: doc_hash(&env, WR_JSON),
+                warehouse_location: String::from_str(&env, WAREHOUSE_LOCATION),
+                lot: String::from_str(&env, LOT),
+            },
+        ),
+        Err(Ok(ContractError::InconsistentDocumentTotals))
+    );
+
+    // Rounding within 0.1% is tolerated
+    client.fulfill_order(
+        &seller,
+        &trade_id,
+        &CustomerInvoiceDetails {
+            ci_description: String::from_str(&env, "Cotton T-shirts"),
+            product: product_id(&env),
+            quantity: 1000,
+            unit_price: 15_0000000,
+            total_price: 15000_0000000,
+            ci_json_ipfs_hash: String::from_str(&env, CI_CID),
+            ci_json_hash: doc_hash(&env, CI_JSON),
+            incoterm: Incoterm::Dap,
+            named_place: String::from_str(&env, DELIVERY_PLACE),
+        },
+        &WarehouseReceiptDetails {
+            wr_description: String::from_str(&env, "Cotton T-shirts"),
+            product: product_id(&env),
+            quantity: 1000,
+            unit_price: 15_0000000,
+            total_price: 15010_0000000,
+            wr_json_ipfs_hash: String::from_str(&env, WR_CID),
+            wr_json_hash: doc_hash(&env, WR_JSON),
+            warehouse_location: String::from_str(&env, WAREHOUSE_LOCATION),
+            lot: String::from_str(&env, LOT),
+        },
+    );
+    assert_eq!(
+        client.get_warehouse_receipt(&trade_id).total_price,
+        15010_0000000
+    );
+}
+
+#[test]
+fn test_unit_price_variance_fails() {
+    let (env, contract_id, _admin, buyer, seller) = create_contract();
+    let client = MarketplaceEscrowV1Client::new(&env, &contract_id);
+    let trade_id = setup_trade(&env, &client, &buyer, &seller);
+
+    let cost = client.calculate_escrow_cost(&buyer, &seller, &15000_0000000);
+    client.fund_escrow(&buyer, &trade_id, &cost.total_required);
+    client.validate_buyer_vlei(&trade_id);
+
+    // Totals stay within 2% but the unit price is 2% over the PO
+    client.fulfill_order(
+        &seller,
+        &trade_id,
+        &CustomerInvoiceDetails {
+            ci_description: String::from_str(&env, "Cotton T-shirts"),
+            product: product_id(&env),
+            quantity: 1000,
+            unit_price: 15_3000000,
+            total_price: 15300_0000000,
+            ci_json_ipfs_hash: String::from_str(&env, CI_CID),
+            ci_json_hash: doc_hash(&env, CI_JSON),
+            incoterm: Incoterm::Dap,
+            named_place: String::from_str(&env, DELIVERY_PLACE),
+        },
+        &WarehouseReceiptDetails {
+            wr_description: String::from_str(&env, "Cotton T-shirts"),
+            product: product_id(&env),
+            quantity: 1000,
+            unit_price: 15_3000000,
+            total_price: 15300_0000000,
+            wr_json_ipfs_hash: String::from_str(&env, WR_CID),
+            wr_json_hash: doc_hash(&env, WR_JSON),
+            warehouse_location: String::from_str(&env, WAREHOUSE_LOCATION),
+            lot: String::from_str(&env, LOT),
+        },
+    );
+
+    assert_eq!(
+        client.try_accept_trade(&buyer, &trade_id),
+        Err(Ok(ContractError::UnitPriceVarianceTooHigh))
+    );
+}
+
+#[test]
+fn test_preview_match_report() {
+    let (env, contract_id, _admin, buyer, seller) = create_contract();
+    let client = MarketplaceEscrowV1Client::new(&env, &contract_id);
+    let trade_id = setup_trade(&env, &client, &buyer, &seller);
+
+    assert_eq!(
+        client.try_preview_match(&99),
+        Err(Ok(ContractError::TradeNotFound))
+    );
+
+    // Before fulfillment the invoice and receipt are reported missing
+    let pending = client.preview_match(&trade_id);
+    assert!(!pending.passed);
+    assert_eq!(
+        pending.missing,
+        Vec::from_array(
+            &env,
+            [
+                DocumentKind::CustomerInvoice,
+                DocumentKind::WarehouseReceipt
+            ]
+        )
+    );
+    assert!(pending.checks.is_empty());
+
+    let cost = client.calculate_escrow_cost(&buyer, &seller, &15000_0000000);
+    client.fund_escrow(&buyer, &trade_id, &cost.total_required);
+    client.validate_buyer_vlei(&trade_id);
+
+    // Invoice is 5.5% over on quantity and total, receipt matches the PO
+    client.fulfill_order(
+        &seller,
+        &trade_id,
+        &CustomerInvoiceDetails {
+            ci_description: String::from_str(&env, "Cotton T-shirts"),
+            product: product_id(&env),
+            quantity: 1055,
+            unit_price: 15_0000000,
+            total_price: 15825_0000000,
+            ci_json_ipfs_hash: String::from_str(&env, CI_CID),
+            ci_json_hash: doc_hash(&env, CI_JSON),
+            incoterm: Incoterm::Dap,
+            named_place: String::from_str(&env, DELIVERY_PLACE),
+        },
+        &WarehouseReceiptDetails {
+            wr_description: String::from_str(&env, "Cotton T-shirts"),
+            product: product_id(&env),
+            quantity: 1000,
+            unit_price: 15_0000000,
+            total_price: 15000_0000000,
+            wr_json_ipfs_hash: String::from_str(&env, WR_CID),
+            wr_json_hash: doc_hash(&env, WR_JSON),
+            warehouse_location: String::from_str(&env, WAREHOUSE_LOCATION),
+            lot: String::from_str(&env, LOT),
+        },
+    );
+
+    let report = client.preview_match(&trade_id);
+    assert_eq!(report.trade_id, trade_id);
+    assert!(!report.passed);
+    assert!(report.missing.is_empty());
+    assert_eq!(report.checks.len(), 15);
+
+    let find = |field: MatchField, pair: DocumentPair| {
+        report
+            .checks
+            .iter()
+            .find(|check| check.field == field && check.pair == pair)
+            .unwrap()
+    };
+
+    assert!(find(MatchField::ProductId, DocumentPair::PoCi).passed);
+    assert!(find(MatchField::Description, DocumentPair::PoCi).passed);
+    assert_eq!(
+        find(MatchField::Quantity, DocumentPair::PoCi),
+        MatchCheck {
+            field: MatchField::Quantity,
+            pair: DocumentPair::PoCi,
+            variance_bps: 550,
+            tolerance_bps: 500,
+            passed: false,
+        }
+    );
+    assert!(find(MatchField::Quantity, DocumentPair::PoWr).passed);
+    assert_eq!(
+        find(MatchField::Quantity, DocumentPair::PoWr).variance_bps,
+        0
+    );
+    // Variance is measured against the first document of the pair
+    assert_eq!(
+        find(MatchField::Quantity, DocumentPair::CiWr).variance_bps,
+        521
+    );
+    assert!(!find(MatchField::Quantity, DocumentPair::CiWr).passed);
+    assert_eq!(
+        find(MatchField::TotalPrice, DocumentPair::PoCi).tolerance_bps,
+        200
+    );
+    assert!(!find(MatchField::TotalPrice, DocumentPair::PoCi).passed);
+    assert!(find(MatchField::UnitPrice, DocumentPair::CiWr).passed);
+
+    // Settlement fails on the first out-of-tolerance comparison
+    assert_eq!(
+        client.try_accept_trade(&buyer, &trade_id),
+        Err(Ok(ContractError::QuantityVarianceTooHigh))
+    );
+}
+
+#[test]
+fn test_preview_match_passes() {
+    let (env, contract_id, _admin, buyer, seller) = create_contract();
+    let client = MarketplaceEscrowV1Client::new(&env, &contract_id);
+    let trade_id = setup_trade(&env, &client, &buyer, &seller);
+
+    let cost = client.calculate_escrow_cost(&buyer, &seller, &15000_0000000);
+    client.fund_escrow(&buyer, &trade_id, &cost.total_required);
+    fulfill_matching(&env, &client, &seller, trade_id);
+
+    let report = client.preview_match(&trade_id);
+    assert!(report.passed);
+    assert!(report.checks.iter().all(|check| check.variance_bps == 0));
+
+    client.accept_trade(&buyer, &trade_id);
+}
+
+/// Fund a `setup_trade` trade and fulfill it with the given invoice identity
+fn fulfill_with_identity(
+    env: &Env,
+    client: &MarketplaceEscrowV1Client,
+    buyer: &Address,
+    seller: &Address,
+    description: &str,
+    product: ProductId,
+) -> u64 {
+    let trade_id = setup_trade(env, client, buyer, seller);
+    let cost = client.calculate_escrow_cost(buyer, seller, &15000_0000000);
+    client.fund_escrow(buyer, &trade_id, &cost.total_required);
+    client.validate_buyer_vlei(&trade_id);
+    client.fulfill_order(
+        seller,
+        &trade_id,
+        &CustomerInvoiceDetails {
+            ci_description: String::from_str(env, description),
+            product,
+            quantity: 1000,
+            unit_price: 15_0000000,
+            total_price: 15000_0000000,
+            ci_json_ipfs_hash: String::from_str(env, CI_CID),
+            ci_json_hash: doc_hash(env, CI_JSON),
+            incoterm: Incoterm::Dap,
+            named_place: String::from_str(env, DELIVERY_PLACE),
+        },
+        &WarehouseReceiptDetails {
+            wr_description: String::from_str(env, "Cotton T-shirts"),
+            product: product_id(env),
+            quantity: 1000,
+            unit_price: 15_0000000,
+            total_price: 15000_0000000,
+            wr_json_ipfs_hash: String::from_str(env, WR_CID),
+            wr_json_hash: doc_hash(env, WR_JSON),
+            warehouse_location: String::from_str(env, WAREHOUSE_LOCATION),
+            lot: String::from_str(env, LOT),
+        },
+    );
+    trade_id
+}
+
+#[test]
+fn test_description_match_ignores_case_and_whitespace() {
+    let (env, contract_id, _admin, buyer, seller) = create_contract();
+    let client = MarketplaceEscrowV1Client::new(&env, &contract_id);
+
+    let trade_id = fulfill_with_identity(
+        &env,
+        &client,
+        &buyer,
+        &seller,
+        "  COTTON   t-shirts ",
+        product_id(&env),
+    );
+
+    client.accept_trade(&buyer, &trade_id);
+    assert_eq!(client.get_trade(&trade_id).state, TradeState::Settled);
+}
+
+#[test]
+fn test_product_id_mismatch() {
+    let (env, contract_id, _admin, buyer, seller) = create_contract();
+    let client = MarketplaceEscrowV1Client::new(&env, &contract_id);
+
+    // Same description, different GTIN
+    let mut other_gtin = product_id(&env);
+    other_gtin.gtin = Some(String::from_str(&env, "00098765400021"));
+    let trade_id = fulfill_with_identity(
+        &env,
+        &client,
+        &buyer,
+        &seller,
+        "Cotton T-shirts",
+        other_gtin,
+    );
+    assert_eq!(
+        client.try_accept_trade(&buyer, &trade_id),
+        Err(Ok(ContractError::ProductIdMismatch))
+    );
+
+    // Identifiers of different kinds cannot confirm the product
+    let sku_only = ProductId {
+        gtin: None,
+        hs_code: None,
+        sku: Some(String::from_str(&env, "JK-TS-001")),
+    };
+    let trade_id =
+        fulfill_with_identity(&env, &client, &buyer, &seller, "Cotton T-shirts", sku_only);
+    let report = client.preview_match(&trade_id);
+    assert!(!report.checks.get(0).unwrap().passed);
+    assert_eq!(
+        client.try_accept_trade(&buyer, &trade_id),
+        Err(Ok(ContractError::ProductIdMismatch))
+    );
+}
+
+#[test]
+fn test_documents_require_product_id() {
+    let (env, contract_id, _admin, buyer, seller) = create_contract();
+    let client = MarketplaceEscrowV1Client::new(&env, &contract_id);
+    let trade_id = setup_trade(&env, &client, &buyer, &seller);
+
+    let mut details = change_terms(&env, 1000, 15000_0000000);
+    details.product = ProductId {
+        gtin: None,
+        hs_code: None,
+        sku: None,
+    };
+    assert_eq!(
+        client.try_propose_change_order(&buyer, &trade_id, &details),
+        Err(Ok(ContractError::InvalidProductId))
+    );
+
+    details.product.sku = Some(String::from_str(&env, ""));
+    assert_eq!(
+        client.try_propose_change_order(&buyer, &trade_id, &details),
+        Err(Ok(ContractError::InvalidProductId))
+    );
+
+    let mut long = [b'x'; 257];
+    long[0] = b'C';
+    details.product = product_id(&env);
+    details.po_description = String::from_bytes(&env, &long);
+    assert_eq!(
+        client.try_create_trade(
+            &buyer,
+            &seller,
+            &details,
+            &String::from_str(&env, "QmBuyerLEI"),
+            &String::from_str(&env, "QmSellerLEI"),
+            &None,
+        ),
+        Err(Ok(ContractError::DescriptionTooLong))
+    );
+}
+
+#[test]
+fn test_verify_document_content() {
+    let (env, contract_id, _admin, buyer, seller) = create_contract();
+    let client = MarketplaceEscrowV1Client::new(&env, &contract_id);
+    let trade_id = setup_trade(&env, &client, &buyer, &seller);
+
+    let cost = client.calculate_escrow_cost(&buyer, &seller, &15000_0000000);
+    client.fund_escrow(&buyer, &trade_id, &cost.total_required);
+    fulfill_matching(&env, &client, &seller, trade_id);
+
+    for (kind, json) in [
+        (DocumentKind::PurchaseOrder, PO_JSON),
+        (DocumentKind::CustomerInvoice, CI_JSON),
+        (DocumentKind::WarehouseReceipt, WR_JSON),
+    ] {
+        let bytes = Bytes::from_slice(&env, json.as_bytes());
+        assert!(client.verify_document(&trade_id, &kind, &bytes));
+    }
+
+    // Any change to the content breaks the proof
+    let tampered = Bytes::from_slice(&env, PO_JSON.replace("1000", "900").as_bytes());
+    assert!(!client.verify_document(&trade_id, &DocumentKind::PurchaseOrder, &tampered));
+    let wrong_doc = Bytes::from_slice(&env, CI_JSON.as_bytes());
+    assert!(!client.verify_document(&trade_id, &DocumentKind::WarehouseReceipt, &wrong_doc));
+
+    assert_eq!(
+        client.try_verify_document(&99, &DocumentKind::PurchaseOrder, &wrong_doc),
+        Err(Ok(ContractError::PurchaseOrderNotFound))
+    );
+}
+
+#[test]
+fn test_document_references_validated() {
+    let (env, contract_id, _admin, buyer, seller) = create_contract();
+    let client = MarketplaceEscrowV1Client::new(&env, &contract_id);
+    let trade_id = setup_trade(&env, &client, &buyer, &seller);
+
+    let mut details = change_terms(&env, 1000, 15000_0000000);
+    for cid in [
+        "QmPO123",
+        // Base58 has no zero
+        "QmYwAPJzv5CZsnA625s3Xf2nemtYgPpHdWEz79ojWnPb0G",
+        // Base32 CIDv1 must be lowercase
+        "BAFYBEIGDYRZT5SFP7UDM7HU76UH7Y26NF3EFUYLQABF3OCLGTQY55FBZDI",
+        "bafybeigdyrzt5sfp7udm7hu76uh7y26nf3efuylqabf3oclgtqy55fbz01",
+        "",
+    ] {
+        details.po_json_ipfs_hash = String::from_str(&env, cid);
+        assert_eq!(
+            client.try_propose_change_order(&buyer, &trade_id, &details),
+            Err(Ok(ContractError::InvalidDocumentCid))
+        );
+    }
+
+    // CIDv1 references are accepted alongside CIDv0
+    details.po_json_ipfs_hash = String::from_str(&env, WR_CID);
+    assert_eq!(client.propose_change_order(&buyer, &trade_id, &details), 2);
+
+    details.po_json_hash = BytesN::from_array(&env, &[0u8; 32]);
+    assert_eq!(
+        client.try_propose_change_order(&buyer, &trade_id, &details),
+        Err(Ok(ContractError::InvalidDocumentHash))
+    );
+
+    let cost = client.calculate_escrow_cost(&buyer, &seller, &15000_0000000);
+    client.fund_escrow(&buyer, &trade_id, &cost.total_required);
+    client.validate_buyer_vlei(&trade_id);
+    assert_eq!(
+        client.try_fulfill_order(
+            &seller,
+            &trade_id,
+            &CustomerInvoiceDetails {
+                ci_description: String::from_str(&env, "Cotton T-Shirts"),
+                product: product_id(&env),
+                quantity: 1000,
+                unit_price: 15_0000000,
+                total_price: 15000_0000000,
+                ci_json_ipfs_hash: String::from_str(&env, CI_CID),
+                ci_json_hash: doc_hash(&env, CI_JSON),
+                incoterm: Incoterm::Dap,
+                named_place: String::from_str(&env, DELIVERY_PLACE),
+            },
+            &WarehouseReceiptDetails {
+                wr_description: String::from_str(&env, "Cotton T-Shirts"),
+                product: product_id(&env),
+                quantity: 1000,
+                unit_price: 15_0000000,
+                total_price: 15000_0000000,
+                wr_json_ipfs_hash: String::from_str(&env, ""),
+                wr_json_hash: doc_hash(&env, WR_JSON),
+                warehouse_location: String::from_str(&env, WAREHOUSE_LOCATION),
+                lot: String::from_str(&env, LOT),
+            },
+        ),
+        Err(Ok(ContractError::InvalidDocumentCid))
+    );
+}
+
+const BL_CID: &str = "QmZ4tDuvesekSs4qM5ZBKpXiZGun7S2CYtEZRB3DYXkjGx";
+const BL_JSON: &str = r#"{"doc":"BL","vessel":"Maersk Essen","quantity":1000}"#;
+
+/// Bill of lading for `quantity` units loaded at Chittagong
+fn bill_of_lading(env: &Env, quantity: u64) -> BillOfLadingDetails {
+    BillOfLadingDetails {
+        vessel: String::from_str(env, "Maersk Essen"),
+        voyage: String::from_str(env, "412W"),
+        port_of_loading: String::from_str(env, "BDCGP"),
+        port_of_discharge: String::from_str(env, "NLRTM"),
+        quantity,
+        container_ids: Vec::from_array(env, [String::from_str(env, "MSKU1234565")]),
+        bl_json_ipfs_hash: String::from_str(env, BL_CID),
+        bl_json_hash: doc_hash(env, BL_JSON),
+    }
+}
+
+/// Open a funded `setup_trade` trade whose PO was amended to `terms`
+fn setup_amended_trade(
+    env: &Env,
+    client: &MarketplaceEscrowV1Client,
+    buyer: &Address,
+    seller: &Address,
+    terms: &PurchaseOrderDetails,
+) -> u64 {
+    let trade_id = setup_trade(env, client, buyer, seller);
+    client.propose_change_order(buyer, &trade_id, terms);
+    client.accept_change_order(seller, &trade_id, &1, &0);
+
+    let cost = client.calculate_escrow_cost(buyer, seller, &terms.total_price);
+    client.fund_escrow(buyer, &trade_id, &cost.total_required);
+    trade_id
+}
+
+/// Open a funded FOB trade, which requires a bill of lading
+fn setup_four_way_trade(
+    env: &Env,
+    client: &MarketplaceEscrowV1Client,
+    buyer: &Address,
+    seller: &Address,
+) -> u64 {
+    let mut terms = change_terms(env, 1000, 15000_0000000);
+    terms.delivery.incoterm = Incoterm::Fob;
+    setup_amended_trade(env, client, buyer, seller, &terms)
+}
+
+#[test]
+fn test_four_way_match_with_bill_of_lading() {
+    let (env, contract_id, _admin, buyer, seller) = create_contract();
+    let client = MarketplaceEscrowV1Client::new(&env, &contract_id);
+    let trade_id = setup_four_way_trade(&env, &client, &buyer, &seller);
+    let carrier = Address::generate(&env);
+
+    assert_eq!(
+        client.try_submit_bill_of_lading(&carrier, &trade_id, &bill_of_lading(&env, 1000)),
+        Err(Ok(ContractError::CarrierNotRegistered))
+    );
+
+    client.register_carrier(
+        &carrier,
+        &String::from_str(&env, "Maersk"),
+        &String::from_str(&env, "549300D2K6PKKKXVNN73"),
+    );
+    fulfill_matching(&env, &client, &seller, trade_id);
+
+    // Settlement waits for shipment evidence
+    assert_eq!(
+        client.try_accept_trade(&buyer, &trade_id),
+        Err(Ok(ContractError::BillOfLadingNotFound))
+    );
+
+    // Under FOB the buyer books the main carriage and picks the carrier
+    assert_eq!(
+        client.try_submit_bill_of_lading(&carrier, &trade_id, &bill_of_lading(&env, 1000)),
+        Err(Ok(ContractError::CarrierNotDesignated))
+    );
+    assert_eq!(
+        client.try_nominate_carrier(&seller, &trade_id, &carrier),
+        Err(Ok(ContractError::NotBuyer))
+    );
+    client.nominate_carrier(&buyer, &trade_id, &carrier);
+    assert_eq!(
+        client.get_designated_carrier(&trade_id),
+        Some(carrier.clone())
+    );
+
+    // Any other registered carrier is refused
+    let other = Address::generate(&env);
+    client.register_carrier(
+        &other,
+        &String::from_str(&env, "MSC"),
+        &String::from_str(&env, "549300KZ5B4PHX0ZDL81"),
+    );
+    assert_eq!(
+        client.try_submit_bill_of_lading(&other, &trade_id, &bill_of_lading(&env, 1000)),
+        Err(Ok(ContractError::CarrierNotDesignated))
+    );
+
+    client.submit_bill_of_lading(&carrier, &trade_id, &bill_of_lading(&env, 1000));
+    assert_eq!(
+        client.try_submit_bill_of_lading(&carrier, &trade_id, &bill_of_lading(&env, 1000)),
+        Err(Ok(ContractError::BillOfLadingAlreadySubmitted))
+    );
+
+    let bill = client.get_bill_of_lading(&trade_id);
+    assert_eq!(bill.carrier, carrier);
+    assert!(client.verify_document(
+        &trade_id,
+        &DocumentKind::BillOfLading,
+        &Bytes::from_slice(&env, BL_JSON.as_bytes()),
+    ));
+
+    // Three extra quantity comparisons against the bill of lading
+    let report = client.preview_match(&trade_id);
+    assert_eq!(report.checks.len(), 18);
+    assert_eq!(report.checks.get(17).unwrap().pair, DocumentPair::WrBl);
+    assert!(report.passed);
+
+    client.accept_trade(&buyer, &trade_id);
+    assert_eq!(client.get_trade(&trade_id).state, TradeState::Settled);
+}
+
+#[test]
+fn test_bill_of_lading_quantity_variance() {
+    let (env, contract_id, _admin, buyer, seller) = create_contract();
+    let client = MarketplaceEscrowV1Client::new(&env, &contract_id);
+    let carrier = Address::generate(&env);
+    client.register_carrier(
+        &carrier,
+        &String::from_str(&env, "Maersk"),
+        &String::from_str(&env, "549300D2K6PKKKXVNN73"),
+    );
+
+    // Three-way trades have no use for a bill of lading
+    let plain_id = setup_trade(&env, &client, &buyer, &seller);
+    assert_eq!(
+        client.try_submit_bill_of_lading(&carrier, &plain_id, &bill_of_lading(&env, 1000)),
+        Err(Ok(ContractError::BillOfLadingNotRequired))
+    );
+
+    // 940 shipped against 1000 ordered is a 6% shortfall
+    let trade_id = setup_four_way_trade(&env, &client, &buyer, &seller);
+    client.nominate_carrier(&buyer, &trade_id, &carrier);
+    client.submit_bill_of_lading(&carrier, &trade_id, &bill_of_lading(&env, 940));
+    fulfill_matching(&env, &client, &seller, trade_id);
+
+    let report = client.preview_match(&trade_id);
+    assert!(!report.passed);
+    let failed = report.checks.iter().find(|check| !check.passed).unwrap();
+    assert_eq!(failed.pair, DocumentPair::PoBl);
+    assert_eq!(failed.variance_bps, 600);
+
+    assert_eq!(
+        client.try_accept_trade(&buyer, &trade_id),
+        Err(Ok(ContractError::QuantityVarianceTooHigh))
+    );
+
+    client.deactivate_carrier(&carrier);
+    assert_eq!(
+        client.try_submit_bill_of_lading(&carrier, &plain_id, &bill_of_lading(&env, 1000)),
+        Err(Ok(ContractError::CarrierInactive))
+    );
+}
+
+const INSPECTION_CID: &str = "QmSsw6EcnwEiTT9c4rnAGeSENvsJMepNHmbrgi2S9bXNJr";
+const INSPECTION_REPORT: &str = r#"{"doc":"inspection","aql":"2.5","defects":1}"#;
+
+/// Inspection certificate for 80 sampled units
+fn inspection_certificate(env: &Env, passed: bool) -> InspectionCertificateDetails {
+    InspectionCertificateDetails {
+        passed,
+        grade: String::from_str(env, if passed { "A" } else { "C" }),
+        sampled_quantity: 80,
+        report_ipfs_hash: String::from_str(env, INSPECTION_CID),
+        report_hash: doc_hash(env, INSPECTION_REPORT),
+    }
+}
+
+/// Register an inspection agency and open a funded trade that needs its certificate
+fn setup_inspected_trade(
+    env: &Env,
+    client: &MarketplaceEscrowV1Client,
+    buyer: &Address,
+    seller: &Address,
+) -> (u64, Address) {
+    let agency = Address::generate(env);
+    client.register_inspection_agency(
+        &agency,
+        &String::from_str(env, "SGS"),
+        &String::from_str(env, "549300RV9V7K1XMZ6S45"),
+    );
+
+    let mut terms = change_terms(env, 1000, 15000_0000000);
+    terms.requires_inspection = true;
+    terms.inspection_agency = Some(agency.clone());
+    let trade_id = setup_amended_trade(env, client, buyer, seller, &terms);
+    (trade_id, agency)
+}
+
+#[test]
+fn test_passing_inspection_allows_settlement() {
+    let (env, contract_id, _admin, buyer, seller) = create_contract();
+    let client = MarketplaceEscrowV1Client::new(&env, &contract_id);
+    let (trade_id, agency) = setup_inspected_trade(&env, &client, &buyer, &seller);
+    fulfill_matching(&env, &client, &seller, trade_id);
+
+    // Documents match but quality is unproven
+    assert!(client.preview_match(&trade_id).passed);
+    assert_eq!(
+        client.try_accept_trade(&buyer, &trade_id),
+        Err(Ok(ContractError::InspectionCertificateNotFound))
+    );
+
+    let outsider = Address::generate(&env);
+    assert_eq!(
+        client.try_submit_inspection_certificate(
+            &outsider,
+            &trade_id,
+            &inspection_certificate(&env, true)
+        ),
+        Err(Ok(ContractError::InspectionAgencyNotRegistered))
+    );
+
+    // Only the agency named on the PO may certify the goods
+    let rival = Address::generate(&env);
+    client.register_inspection_agency(
+        &rival,
+        &String::from_str(&env, "Bureau Veritas"),
+        &String::from_str(&env, "969500TJ5KRTCJQWXH05"),
+    );
+    assert_eq!(
+        client.try_submit_inspection_certificate(
+            &rival,
+            &trade_id,
+            &inspection_certificate(&env, false)
+        ),
+        Err(Ok(ContractError::InspectionAgencyNotDesignated))
+    );
+    assert_eq!(client.get_trade(&trade_id).state, TradeState::Fulfilled);
+
+    let mut oversampled = inspection_certificate(&env, true);
+    oversampled.sampled_quantity = 1001;
+    assert_eq!(
+        client.try_submit_inspection_certificate(&agency, &trade_id, &oversampled),
+        Err(Ok(ContractError::InvalidAmount))
+    );
+
+    client.submit_inspection_certificate(&agency, &trade_id, &inspection_certificate(&env, true));
+    assert_eq!(
+        client.try_submit_inspection_certificate(
+            &agency,
+            &trade_id,
+            &inspection_certificate(&env, true)
+        ),
+        Err(Ok(ContractError::InspectionCertificateAlreadySubmitted))
+    );
+    assert!(client.verify_document(
+        &trade_id,
+        &DocumentKind::InspectionCertificate,
+        &Bytes::from_slice(&env, INSPECTION_REPORT.as_bytes()),
+    ));
+
+    client.accept_trade(&buyer, &trade_id);
+    assert_eq!(client.get_trade(&trade_id).state, TradeState::Settled);
+}
+
+#[test]
+fn test_failed_inspection_refunds_buyer() {
+    let (env, contract_id, _admin, buyer, seller) = create_contract();
+    let client = MarketplaceEscrowV1Client::new(&env, &contract_id);
+    let (_treasury, token) = payment_accounts(&env, &contract_id);
+    let starting_balance = token.balance(&buyer);
+
+    // Trades without the requirement take no certificate
+    let plain_id = setup_trade(&env, &client, &buyer, &seller);
+    let (trade_id, agency) = setup_inspected_trade(&env, &client, &buyer, &seller);
+    assert_eq!(
+        client.try_submit_inspection_certificate(
+            &agency,
+            &plain_id,
+            &inspection_certificate(&env, false)
+        ),
+        Err(Ok(ContractError::InspectionNotRequired))
+    );
+
+    // Pre-shipment inspection fails: the buyer gets the escrow back
+    client.submit_inspection_certificate(&agency, &trade_id, &inspection_certificate(&env, false));
+
+    let trade = client.get_trade(&trade_id);
+    assert_eq!(trade.state, TradeState::Refunded);
+    assert_eq!(trade.escrow_balance, 0);
+    assert_eq!(token.balance(&buyer), starting_balance);
+
+    let history = client.get_trade_history(&trade_id);
+    let last = history.last().unwrap();
+    assert_eq!(last.to, TradeState::Refunded);
+    assert_eq!(last.actor, agency);
+
+    assert_eq!(
+        client.try_fulfill_order(
+            &seller,
+            &trade_id,
+            &CustomerInvoiceDetails {
+                ci_description: String::from_str(&env, "Cotton T-shirts"),
+                product: product_id(&env),
+                quantity: 1000,
+                unit_price: 15_0000000,
+                total_price: 15000_0000000,
+                ci_json_ipfs_hash: String::from_str(&env, CI_CID),
+                ci_json_hash: doc_hash(&env, CI_JSON),
+                incoterm: Incoterm::Dap,
+                named_place: String::from_str(&env, DELIVERY_PLACE),
+            },
+            &WarehouseReceiptDetails {
+                wr_description: String::from_str(&env, "Cotton T-shirts"),
+                product: product_id(&env),
+                quantity: 1000,
+                unit_price: 15_0000000,
+                total_price: 15000_0000000,
+                wr_json_ipfs_hash: String::from_str(&env, WR_CID),
+                wr_json_hash: doc_hash(&env, WR_JSON),
+                warehouse_location: String::from_str(&env, WAREHOUSE_LOCATION),
+                lot: String::from_str(&env, LOT),
+            },
+        ),
+        Err(Ok(ContractError::InvalidTradeState))
+    );
+}
+
+#[test]
+fn test_delivery_terms_validated() {
+    let (env, contract_id, _admin, buyer, seller) = create_contract();
+    let client = MarketplaceEscrowV1Client::new(&env, &contract_id);
+    let trade_id = setup_trade(&env, &client, &buyer, &seller);
+    env.ledger().set_timestamp(10 * 86400);
+
+    let mut terms = change_terms(&env, 1000, 15000_0000000);
+    terms.delivery.named_place = String::from_str(&env, "");
+    assert_eq!(
+        client.try_propose_change_order(&buyer, &trade_id, &terms),
+        Err(Ok(ContractError::InvalidDeliveryTerms))
+    );
+
+    // Window must be ordered and not already over
+    terms.delivery = delivery_terms(&env, Incoterm::Cif);
+    terms.delivery.window_start = terms.delivery.window_end + 1;
+    assert_eq!(
+        client.try_propose_change_order(&buyer, &trade_id, &terms),
+        Err(Ok(ContractError::InvalidDeliveryTerms))
+    );
+    terms.delivery.window_start = 0;
+    terms.delivery.window_end = 86400;
+    assert_eq!(
+        client.try_propose_change_order(&buyer, &trade_id, &terms),
+        Err(Ok(ContractError::InvalidDeliveryTerms))
+    );
+
+    terms.delivery = delivery_terms(&env, Incoterm::Cif);
+    client.propose_change_order(&buyer, &trade_id, &terms);
+    client.accept_change_order(&seller, &trade_id, &1, &0);
+    assert_eq!(
+        client.get_purchase_order(&trade_id).delivery,
+        terms.delivery
+    );
+}
+
+#[test]
+fn test_invoice_must_restate_delivery_terms() {
+    let (env, contract_id, _admin, buyer, seller) = create_contract();
+    let client = MarketplaceEscrowV1Client::new(&env, &contract_id);
+    let trade_id = setup_trade(&env, &client, &buyer, &seller);
+    let cost = client.calculate_escrow_cost(&buyer, &seller, &15000_0000000);
+    client.fund_escrow(&buyer, &trade_id, &cost.total_required);
+    client.validate_buyer_vlei(&trade_id);
+
+    let mut invoice = CustomerInvoiceDetails {
+        ci_description: String::from_str(&env, "Cotton T-shirts"),
+        product: product_id(&env),
+        quantity: 1000,
+        unit_price: 15_0000000,
+        total_price: 15000_0000000,
+        ci_json_ipfs_hash: String::from_str(&env, CI_CID),
+        ci_json_hash: doc_hash(&env, CI_JSON),
+        incoterm: Incoterm::Fob,
+        named_place: String::from_str(&env, DELIVERY_PLACE),
+    };
+    let receipt = WarehouseReceiptDetails {
+        wr_description: String::from_str(&env, "Cotton T-shirts"),
+        product: product_id(&env),
+        quantity: 1000,
+        unit_price: 15_0000000,
+        total_price: 15000_0000000,
+        wr_json_ipfs_hash: String::from_str(&env, WR_CID),
+        wr_json_hash: doc_hash(&env, WR_JSON),
+        warehouse_location: String::from_str(&env, WAREHOUSE_LOCATION),
+        lot: String::from_str(&env, LOT),
+    };
+
+    // The PO is DAP Port of Rotterdam
+    assert_eq!(
+        client.try_fulfill_order(&seller, &trade_id, &invoice, &receipt),
+        Err(Ok(ContractError::DeliveryTermsMismatch))
+    );
+    invoice.incoterm = Incoterm::Dap;
+    invoice.named_place = String::from_str(&env, "Port of Antwerp");
+    assert_eq!(
+        client.try_fulfill_order(&seller, &trade_id, &invoice, &receipt),
+        Err(Ok(ContractError::DeliveryTermsMismatch))
+    );
+
+    invoice.named_place = String::from_str(&env, DELIVERY_PLACE);
+    client.fulfill_order(&seller, &trade_id, &invoice, &receipt);
+    assert_eq!(
+        client.get_customer_invoice(&trade_id).incoterm,
+        Incoterm::Dap
+    );
+}
+
+#[test]
+fn test_fulfillment_within_delivery_window() {
+    let (env, contract_id, _admin, buyer, seller) = create_contract();
+    let client = MarketplaceEscrowV1Client::new(&env, &contract_id);
+
+    // Goods are due between day 10 and day 20
+    let mut terms = change_terms(&env, 1000, 15000_0000000);
+    terms.delivery.window_start = 10 * 86400;
+    terms.delivery.window_end = 20 * 86400;
+    let trade_id = setup_amended_trade(&env, &client, &buyer, &seller, &terms);
+    let (invoice, receipt) = matching_documents(&env, &client, trade_id);
+    client.validate_buyer_vlei(&trade_id);
+
+    env.ledger().set_timestamp(10 * 86400 - 1);
+    assert_eq!(
+        client.try_fulfill_order(&seller, &trade_id, &invoice, &receipt),
+        Err(Ok(ContractError::OutsideDeliveryWindow))
+    );
+    env.ledger().set_timestamp(20 * 86400 + 1);
+    assert_eq!(
+        client.try_fulfill_order(&seller, &trade_id, &invoice, &receipt),
+        Err(Ok(ContractError::OutsideDeliveryWindow))
+    );
+
+    env.ledger().set_timestamp(20 * 86400);
+    client.fulfill_order(&seller, &trade_id, &invoice, &receipt);
+    assert_eq!(client.get_trade(&trade_id).fulfilled_at, 20 * 86400);
+}
+
+#[test]
+fn test_incoterm_decides_shipment_evidence() {
+    let (env, contract_id, _admin, buyer, seller) = create_contract();
+    let client = MarketplaceEscrowV1Client::new(&env, &contract_id);
+    let carrier = Address::generate(&env);
+    client.register_carrier(
+        &carrier,
+        &String::from_str(&env, "Maersk"),
+        &String::from_str(&env, "549300D2K6PKKKXVNN73"),
+    );
+
+    for (incoterm, needs_bill) in [
+        (Incoterm::Exw, false),
+        (Incoterm::Fca, false),
+        (Incoterm::Cpt, true),
+        (Incoterm::Cif, true),
+        (Incoterm::Ddp, false),
+    ] {
+        let mut terms = change_terms(&env, 1000, 15000_0000000);
+        terms.delivery.incoterm = incoterm;
+        let trade_id = setup_amended_trade(&env, &client, &buyer, &seller, &terms);
+
+        // Under the C-rules the seller books the main carriage
+        let nominated = client.try_nominate_carrier(&seller, &trade_id, &carrier);
+        let result =
+            client.try_submit_bill_of_lading(&carrier, &trade_id, &bill_of_lading(&env, 1000));
+        if needs_bill {
+            assert_eq!(nominated, Ok(Ok(())));
+            assert_eq!(result, Ok(Ok(())));
+        } else {
+            assert_eq!(nominated, Err(Ok(ContractError::BillOfLadingNotRequired)));
+            assert_eq!(result, Err(Ok(ContractError::BillOfLadingNotRequired)));
+        }
+    }
+}
+
+fn milestone(
+    env: &Env,
+    label: &str,
+    amount: MilestoneAmount,
+    evidence: &[DocumentKind],
+) -> Milestone {
+    Milestone {
+        label: String::from_str(env, label),
+        amount,
+        evidence: Vec::from_slice(env, evidence),
+    }
+}
+
+/// FOB terms paying 30% on order, 50% on shipment and the rest on delivery
+fn staged_terms(env: &Env) -> PurchaseOrderDetails {
+    let mut terms = change_terms(env, 1000, 15000_0000000);
+    terms.delivery.incoterm = Incoterm::Fob;
+    terms.payment_schedule = Vec::from_array(
+        env,
+        [
+            milestone(
+                env,
+                "deposit",
+                MilestoneAmount::Percentage(3000),
+                &[DocumentKind::PurchaseOrder],
+            ),
+            milestone(
+                env,
+                "shipped",
+                MilestoneAmount::Percentage(5000),
+                &[DocumentKind::BillOfLading],
+            ),
+            milestone(
+                env,
+                "delivered",
+                MilestoneAmount::Fixed(3000_0000000),
+                &[
+                    DocumentKind::CustomerInvoice,
+                    DocumentKind::WarehouseReceipt,
+                    DocumentKind::BillOfLading,
+                ],
+            ),
+        ],
+    );
+    terms
+}
+
+#[test]
+fn test_milestone_payments_released_in_stages() {
+    let (env, contract_id, _admin, buyer, seller) = create_contract();
+    let client = MarketplaceEscrowV1Client::new(&env, &contract_id);
+    let (_treasury, token) = payment_accounts(&env, &contract_id);
+    let trade_id = setup_amended_trade(&env, &client, &buyer, &seller, &staged_terms(&env));
+    let trade = client.get_trade(&trade_id);
+    let net = trade.amount - trade.seller_fee - trade.broker_commission;
+    let seller_before = token.balance(&seller);
+    assert_eq!(client.get_pending_milestones(&trade_id).len(), 3);
+
+    // Deposit needs only the PO
+    assert_eq!(client.release_milestone(&buyer, &trade_id), 0);
+    let deposit = net * 3 / 10;
+    assert_eq!(token.balance(&seller), seller_before + deposit);
+    let trade = client.get_trade(&trade_id);
+    assert_eq!(trade.released_to_seller, deposit);
+    assert_eq!(trade.milestones_released, 1);
+    assert_eq!(trade.state, TradeState::Ordered);
+
+    // Shipment waits for the carrier
+    assert_eq!(
+        client.try_release_milestone(&buyer, &trade_id),
+        Err(Ok(ContractError::BillOfLadingNotFound))
+    );
+    let carrier = Address::generate(&env);
+    client.register_carrier(
+        &carrier,
+        &String::from_str(&env, "Maersk"),
+        &String::from_str(&env, "549300D2K6PKKKXVNN73"),
+    );
+    client.nominate_carrier(&buyer, &trade_id, &carrier);
+    client.submit_bill_of_lading(&carrier, &trade_id, &bill_of_lading(&env, 1000));
+    assert_eq!(client.release_milestone(&buyer, &trade_id), 1);
+    let shipped = net / 2;
+    assert_eq!(token.balance(&seller), seller_before + deposit + shipped);
+    assert_eq!(client.get_pending_milestones(&trade_id).len(), 1);
+
+    // Terms already paid against can no longer change
+    client.propose_change_order(&seller, &trade_id, &staged_terms(&env));
+    assert_eq!(
+        client.try_accept_change_order(&buyer, &trade_id, &2, &0),
+        Err(Ok(ContractError::MilestonesAlreadyReleased))
+    );
+
+    // Delivery settles the trade through the full DvP check
+    assert_eq!(
+        client.try_release_milestone(&buyer, &trade_id),
+        Err(Ok(ContractError::TradeNotFulfilled))
+    );
+    fulfill_matching(&env, &client, &seller, trade_id);
+    assert_eq!(client.release_milestone(&buyer, &trade_id), 2);
+
+    let trade = client.get_trade(&trade_id);
+    assert_eq!(trade.state, TradeState::Settled);
+    assert_eq!(trade.milestones_released, 3);
+    assert_eq!(trade.escrow_balance, 0);
+    assert_eq!(token.balance(&seller), seller_before + net);
+    assert_eq!(client.get_pending_milestones(&trade_id).len(), 0);
+    assert_eq!(
+        client.try_release_milestone(&buyer, &trade_id),
+        Err(Ok(ContractError::InvalidTradeState))
+    );
+}
+
+#[test]
+fn test_rejected_trade_owes_released_milestones() {
+    let (env, contract_id, _admin, buyer, seller) = create_contract();
+    let client = MarketplaceEscrowV1Client::new(&env, &contract_id);
+    let (_treasury, token) = payment_accounts(&env, &contract_id);
+    let rejected_id = setup_amended_trade(&env, &client, &buyer, &seller, &staged_terms(&env));
+    let trade = client.get_trade(&rejected_id);
+    let funded = trade.escrow_balance;
+    let deposit = (trade.amount - trade.seller_fee - trade.broker_commission) * 3 / 10;
+
+    // Seller takes the deposit, then walks away from the order
+    client.release_milestone(&buyer, &rejected_id);
+    let buyer_before = token.balance(&buyer);
+    client.reject_order(&seller, &rejected_id);
+    assert_eq!(token.balance(&buyer), buyer_before + funded - deposit);
+
+    let debts = client.get_seller_debts(&seller);
+    assert_eq!(debts.len(), 1);
+    let debt = debts.get(0).unwrap();
+    assert_eq!(debt.trade_id, rejected_id);
+    assert_eq!(debt.creditor, buyer);
+    assert_eq!(debt.outstanding, deposit);
+
+    // The seller's next payout makes the buyer whole
+    let cost = client.calculate_escrow_cost(&buyer, &seller, &15000_0000000);
+    let trade_id = setup_trade(&env, &client, &buyer, &seller);
+    client.fund_escrow(&buyer, &trade_id, &cost.total_required);
+    fulfill_matching(&env, &client, &seller, trade_id);
+    let buyer_before = token.balance(&buyer);
+    client.accept_trade(&buyer, &trade_id);
+    assert_eq!(token.balance(&buyer), buyer_before + deposit);
+    assert_eq!(client.get_seller_debts(&seller).len(), 0);
+}
+
+#[test]
+fn test_payment_schedule_validated() {
+    let (env, contract_id, _admin, buyer, seller) = create_contract();
+    let client = MarketplaceEscrowV1Client::new(&env, &contract_id);
+    let trade_id = setup_amended_trade(
+        &env,
+        &client,
+        &buyer,
+        &seller,
+        &change_terms(&env, 1000, 15000_0000000),
+    );
+
+    assert_eq!(
+        client.try_release_milestone(&buyer, &trade_id),
+        Err(Ok(ContractError::NoPaymentSchedule))
+    );
+
+    // Short of the total
+    let mut terms = staged_terms(&env);
+    terms.payment_schedule.set(
+        2,
+        milestone(&env, "delivered", MilestoneAmount::Fixed(2000_0000000), &[]),
+    );
+    assert_eq!(
+        client.try_propose_change_order(&buyer, &trade_id, &terms),
+        Err(Ok(ContractError::InvalidPaymentSchedule))
+    );
+
+    // Empty milestone
+    let mut terms = staged_terms(&env);
+    terms
+        .payment_schedule
+        .push_back(milestone(&env, "bonus", MilestoneAmount::Fixed(0), &[]));
+    assert_eq!(
+        client.try_propose_change_order(&buyer, &trade_id, &terms),
+        Err(Ok(ContractError::InvalidPaymentSchedule))
+    );
+
+    // DAP delivery produces no bill of lading
+    let mut terms = staged_terms(&env);
+    terms.delivery.incoterm = Incoterm::Dap;
+    assert_eq!(
+        client.try_propose_change_order(&buyer, &trade_id, &terms),
+        Err(Ok(ContractError::InvalidPaymentSchedule))
+    );
+
+    // Inspection evidence needs an inspection
+    let mut terms = staged_terms(&env);
+    terms.payment_schedule.set(
+        0,
+        milestone(
+            &env,
+            "inspected",
+            MilestoneAmount::Percentage(3000),
+            &[DocumentKind::InspectionCertificate],
+        ),
+    );
+    assert_eq!(
+        client.try_propose_change_order(&buyer, &trade_id, &terms),
+        Err(Ok(ContractError::InvalidPaymentSchedule))
+    );
+    terms.requires_inspection = true;
+    assert_eq!(
+        client.try_propose_change_order(&buyer, &trade_id, &terms),
+        Err(Ok(ContractError::InspectionAgencyNotDesignated))
+    );
+    let agency = Address::generate(&env);
+    client.register_inspection_agency(
+        &agency,
+        &String::from_str(&env, "SGS"),
+        &String::from_str(&env, "549300RV9V7K1XMZ6S45"),
+    );
+    terms.inspection_agency = Some(agency);
+    client.propose_change_order(&buyer, &trade_id, &terms);
+
+    // Rounding dust from percentages is left for the final release
+    let mut terms = change_terms(&env, 1000, 15000_0000001);
+    terms.payment_schedule = Vec::from_array(
+        &env,
+        [
+            milestone(&env, "deposit", MilestoneAmount::Percentage(5000), &[]),
+            milestone(&env, "balance", MilestoneAmount::Percentage(5000), &[]),
+        ],
+    );
+    client.propose_change_order(&buyer, &trade_id, &terms);
+}
+
+#[test]
+fn test_seller_advance_deducted_at_settlement() {
+    let (env, contract_id, _admin, buyer, seller) = create_contract();
+    let client = MarketplaceEscrowV1Client::new(&env, &contract_id);
+    let (_treasury, token) = payment_accounts(&env, &contract_id);
+    let seller_before = token.balance(&seller);
+
+    let trade_id = setup_trade(&env, &client, &buyer, &seller);
+    assert_eq!(
+        client.try_authorize_advance(&buyer, &trade_id, &3000_0000000),
+        Err(Ok(ContractError::EscrowNotFunded))
+    );
+    let cost = client.calculate_escrow_cost(&buyer, &seller, &15000_0000000);
+    client.fund_escrow(&buyer, &trade_id, &cost.total_required);
+
+    // Advances are off until the owner sets a rate
+    assert_eq!(
+        client.try_authorize_advance(&buyer, &trade_id, &3000_0000000),
+        Err(Ok(ContractError::AdvanceLimitExceeded))
+    );
+    assert_eq!(
+        client.try_set_max_advance_rate(&10001),
+        Err(Ok(ContractError::InvalidAdvanceRate))
+    );
+    client.set_max_advance_rate(&3000);
+
+    client.authorize_advance(&buyer, &trade_id, &3000_0000000);
+    assert_eq!(
+        client.try_authorize_advance(&buyer, &trade_id, &2000_0000000),
+        Err(Ok(ContractError::AdvanceLimitExceeded))
+    );
+    client.authorize_advance(&buyer, &trade_id, &1500_0000000);
+    assert_eq!(token.balance(&seller), seller_before + 4500_0000000);
+
+    let trade = client.get_trade(&trade_id);
+    assert_eq!(trade.advance_paid, 4500_0000000);
+    assert_eq!(trade.escrow_balance, cost.total_required - 4500_0000000);
+
+    // New terms can't leave the seller holding more than the limit
+    client.propose_change_order(&buyer, &trade_id, &change_terms(&env, 600, 9000_0000000));
+    assert_eq!(
+        client.try_accept_change_order(&seller, &trade_id, &1, &0),
+        Err(Ok(ContractError::AdvanceLimitExceeded))
+    );
+
+    fulfill_matching(&env, &client, &seller, trade_id);
+    assert_eq!(
+        client.try_authorize_advance(&buyer, &trade_id, &100_0000000),
+        Err(Ok(ContractError::TradeNotOrdered))
+    );
+    client.accept_trade(&buyer, &trade_id);
+
+    let trade = client.get_trade(&trade_id);
+    assert_eq!(trade.escrow_balance, 0);
+    assert_eq!(
+        token.balance(&seller),
+        seller_before + trade.amount - trade.seller_fee - trade.broker_commission
+    );
+}
+
+#[test]
+fn test_unwound_advance_netted_against_later_payouts() {
+    let (env, contract_id, _admin, buyer, seller) = create_contract();
+    let client = MarketplaceEscrowV1Client::new(&env, &contract_id);
+    let (_treasury, token) = payment_accounts(&env, &contract_id);
+    client.set_max_advance_rate(&3000);
+    let cost = client.calculate_escrow_cost(&buyer, &seller, &15000_0000000);
+
+    // Seller takes an advance, then walks away from the order
+    let rejected_id = setup_trade(&env, &client, &buyer, &seller);
+    client.fund_escrow(&buyer, &rejected_id, &cost.total_required);
+    let buyer_before = token.balance(&buyer);
+    client.authorize_advance(&buyer, &rejected_id, &3000_0000000);
+    client.reject_order(&seller, &rejected_id);
+    assert_eq!(
+        token.balance(&buyer),
+        buyer_before + cost.total_required - 3000_0000000
+    );
+
+    let debts = client.get_seller_debts(&seller);
+    assert_eq!(debts.len(), 1);
+    let debt = debts.get(0).unwrap();
+    assert_eq!(debt.trade_id, rejected_id);
+    assert_eq!(debt.creditor, buyer);
+    assert_eq!(debt.outstanding, 3000_0000000);
+
+    // The next payout repays the buyer before the seller sees anything
+    let trade_id = setup_trade(&env, &client, &buyer, &seller);
+    client.fund_escrow(&buyer, &trade_id, &cost.total_required);
+    fulfill_matching(&env, &client, &seller, trade_id);
+    let buyer_before = token.balance(&buyer);
+    let seller_before = token.balance(&seller);
+    client.accept_trade(&buyer, &trade_id);
+
+    let trade = client.get_trade(&trade_id);
+    let net = trade.amount - trade.seller_fee - trade.broker_commission;
+    assert_eq!(token.balance(&buyer), buyer_before + 3000_0000000);
+    assert_eq!(token.balance(&seller), seller_before + net - 3000_0000000);
+    assert_eq!(client.get_seller_debts(&seller).len(), 0);
+}
+
+/// Open a funded trade whose PO asks the seller for a 10% performance bond
+fn setup_bonded_trade(
+    env: &Env,
+    client: &MarketplaceEscrowV1Client,
+    token: &TokenClient,
+    buyer: &Address,
+    seller: &Address,
+) -> u64 {
+    StellarAssetClient::new(env, &token.address).mint(seller, &10000_0000000);
+    let mut terms = change_terms(env, 1000, 15000_0000000);
+    terms.performance_bond_rate = 1000;
+    setup_amended_trade(env, client, buyer, seller, &terms)
+}
+
+#[test]
+fn test_performance_bond_returned_on_settlement() {
+    let (env, contract_id, _admin, buyer, seller) = create_contract();
+    let client = MarketplaceEscrowV1Client::new(&env, &contract_id);
+    let (_treasury, token) = payment_accounts(&env, &contract_id);
+    let trade_id = setup_bonded_trade(&env, &client, &token, &buyer, &seller);
+    let seller_before = token.balance(&seller);
+
+    // No delivery without the bond
+    let (invoice, receipt) = matching_documents(&env, &client, trade_id);
+    client.validate_buyer_vlei(&trade_id);
+    assert_eq!(
+        client.try_fulfill_order(&seller, &trade_id, &invoice, &receipt),
+        Err(Ok(ContractError::PerformanceBondRequired))
+    );
+
+    assert_eq!(
+        client.post_performance_bond(&seller, &trade_id),
+        1500_0000000
+    );
+    assert_eq!(
+        client.try_post_performance_bond(&seller, &trade_id),
+        Err(Ok(ContractError::PerformanceBondAlreadyPosted))
+    );
+    assert_eq!(token.balance(&seller), seller_before - 1500_0000000);
+    let trade = client.get_trade(&trade_id);
+    assert_eq!(trade.bond_balance, 1500_0000000);
+    assert_eq!(
+        trade.bond_deadline,
+        client.get_purchase_order(&trade_id).delivery.window_end
+    );
+
+    client.fulfill_order(&seller, &trade_id, &invoice, &receipt);
+    client.accept_trade(&buyer, &trade_id);
+
+    let trade = client.get_trade(&trade_id);
+    assert_eq!(trade.bond_balance, 0);
+    assert_eq!(
+        token.balance(&seller),
+        seller_before + trade.amount - trade.seller_fee - trade.broker_commission
+    );
+
+    // Unbonded orders take no bond
+    let unbonded_id = setup_trade(&env, &client, &buyer, &seller);
+    assert_eq!(
+        client.try_post_performance_bond(&seller, &unbonded_id),
+        Err(Ok(ContractError::PerformanceBondNotRequired))
+    );
+}
+
+#[test]
+fn test_performance_bond_forfeited_to_buyer() {
+    let (env, contract_id, _admin, buyer, seller) = create_contract();
+    let client = MarketplaceEscrowV1Client::new(&env, &contract_id);
+    let (_treasury, token) = payment_accounts(&env, &contract_id);
+    let starting_balance = token.balance(&buyer);
+
+    // Rejecting a bonded order hands the bond to the buyer
+    let rejected_id = setup_bonded_trade(&env, &client, &token, &buyer, &seller);
+    client.post_performance_bond(&seller, &rejected_id);
+    client.reject_order(&seller, &rejected_id);
+    assert_eq!(token.balance(&buyer), starting_balance + 1500_0000000);
+    assert_eq!(client.get_trade(&rejected_id).bond_balance, 0);
+
+    // Missing the delivery date does too, and ends the trade
+    let late_id = setup_bonded_trade(&env, &client, &token, &buyer, &seller);
+    client.post_performance_bond(&seller, &late_id);
+    assert_eq!(
+        client.try_claim_performance_bond(&buyer, &late_id),
+        Err(Ok(ContractError::DeliveryDeadlineNotPassed))
+    );
+    let deadline = client.get_trade(&late_id).bond_deadline;
+    env.ledger().set_timestamp(deadline + 1);
+    assert_eq!(
+        client.claim_performance_bond(&buyer, &late_id),
+        1500_0000000
+    );
+
+    let trade = client.get_trade(&late_id);
+    assert_eq!(trade.state, TradeState::Cancelled);
+    assert_eq!(trade.bond_balance, 0);
+    assert_eq!(trade.escrow_balance, 0);
+    assert_eq!(token.balance(&buyer), starting_balance + 3000_0000000);
+    assert_eq!(token.balance(&contract_id), 0);
+}
+
+#[test]
+fn test_performance_bond_needs_covered_escrow() {
+    let (env, contract_id, _admin, buyer, seller) = create_contract();
+    let client = MarketplaceEscrowV1Client::new(&env, &contract_id);
+    let (_treasury, token) = payment_accounts(&env, &contract_id);
+    let mut terms = change_terms(&env, 1000, 15000_0000000);
+    terms.performance_bond_rate = 1000;
+
+    // A buyer holding back funding can't get a bond to claim
+    let unfunded_id = setup_trade(&env, &client, &buyer, &seller);
+    client.propose_change_order(&buyer, &unfunded_id, &terms);
+    client.accept_change_order(&seller, &unfunded_id, &1, &0);
+    assert_eq!(
+        client.try_post_performance_bond(&seller, &unfunded_id),
+        Err(Ok(ContractError::EscrowNotFunded))
+    );
+
+    // Nor can one who lets the escrow fall short until the deadline passes
+    let trade_id = setup_bonded_trade(&env, &client, &token, &buyer, &seller);
+    let seller_before = token.balance(&seller);
+    client.post_performance_bond(&seller, &trade_id);
+    client.propose_change_order(&buyer, &trade_id, &change_terms(&env, 1200, 18000_0000000));
+    client.accept_change_order(&seller, &trade_id, &2, &0);
+    env.ledger()
+        .set_timestamp(client.get_trade(&trade_id).bond_deadline + 1);
+    assert_eq!(
+        client.try_claim_performance_bond(&buyer, &unfunded_id),
+        Err(Ok(ContractError::PerformanceBondNotPosted))
+    );
+    assert_eq!(
+        client.try_claim_performance_bond(&buyer, &trade_id),
+        Err(Ok(ContractError::InsufficientEscrowFunding))
+    );
+
+    // The seller keeps the bond when the buyer walks away instead
+    client.cancel_trade(&buyer, &trade_id);
+    assert_eq!(client.get_trade(&trade_id).bond_balance, 0);
+    assert_eq!(token.balance(&seller), seller_before);
+}
+
+/// Open account terms for the standard order, paid `days` after settlement
+fn net_terms(env: &Env, days: u32) -> PurchaseOrderDetails {
+    let mut terms = change_terms(env, 1000, 15000_0000000);
+    terms.payment_terms_days = days;
+    terms
+}
+
+fn create_with_terms(
+    env: &Env,
+    client: &MarketplaceEscrowV1Client,
+    buyer: &Address,
+    seller: &Address,
+    terms: &PurchaseOrderDetails,
+) -> u64 {
+    client.create_trade(
+        buyer,
+        seller,
+        terms,
+        &String::from_str(env, "QmBuyerLEI"),
+        &String::from_str(env, "QmSellerLEI"),
+        &None,
+    )
+}
+
+#[test]
+fn test_deferred_payment_after_settlement() {
+    let (env, contract_id, _admin, buyer, seller) = create_contract();
+    let client = MarketplaceEscrowV1Client::new(&env, &contract_id);
+    let (_treasury, token) = payment_accounts(&env, &contract_id);
+    let escrow_id = setup_trade(&env, &client, &buyer, &seller);
+    let lei = String::from_str(&env, "QmBuyerLEI");
+
+    // Open account needs credit
+    assert_eq!(
+        client.try_create_trade(&buyer, &seller, &net_terms(&env, 30), &lei, &lei, &None),
+        Err(Ok(ContractError::CreditLimitExceeded))
+    );
+    client.set_credit_limit(&buyer, &20000_0000000);
+    assert_eq!(
+        client.try_create_trade(&buyer, &seller, &net_terms(&env, 181), &lei, &lei, &None),
+        Err(Ok(ContractError::InvalidPaymentTerms))
+    );
+
+    let trade_id = create_with_terms(&env, &client, &buyer, &seller, &net_terms(&env, 30));
+    assert_eq!(client.get_credit_used(&buyer), 15000_0000000);
+    assert_eq!(
+        client.try_create_trade(&buyer, &seller, &net_terms(&env, 60), &lei, &lei, &None),
+        Err(Ok(ContractError::CreditLimitExceeded))
+    );
+    assert_eq!(
+        client.try_fund_escrow(&buyer, &trade_id, &20000_0000000),
+        Err(Ok(ContractError::InvalidPaymentTerms))
+    );
+
+    // Goods are delivered and accepted with nothing in escrow
+    let buyer_before = token.balance(&buyer);
+    let seller_before = token.balance(&seller);
+    fulfill_matching(&env, &client, &seller, trade_id);
+    client.accept_trade(&buyer, &trade_id);
+
+    let trade = client.get_trade(&trade_id);
+    assert_eq!(trade.state, TradeState::Settled);
+    assert_eq!(trade.payment_due, env.ledger().timestamp() + 30 * 86400);
+    assert_eq!(token.balance(&seller), seller_before);
+    assert_eq!(client.get_overdue_trades(&buyer).len(), 0);
+
+    let paid = client.pay_deferred(&buyer, &trade_id);
+    let trade = client.get_trade(&trade_id);
+    assert_eq!(token.balance(&buyer), buyer_before - paid);
+    assert_eq!(
+        token.balance(&seller),
+        seller_before + trade.amount - trade.seller_fee - trade.broker_commission
+    );
+    assert_eq!(trade.escrow_balance, 0);
+    assert_eq!(client.get_credit_used(&buyer), 0);
+    assert_eq!(
+        client.try_pay_deferred(&buyer, &trade_id),
+        Err(Ok(ContractError::DeferredPaymentAlreadyMade))
+    );
+    assert_eq!(
+        client.try_pay_deferred(&buyer, &escrow_id),
+        Err(Ok(ContractError::NoDeferredPayment))
+    );
+}
+
+#[test]
+fn test_overdue_buyer_blocked_from_new_trades() {
+    let (env, contract_id, _admin, buyer, seller) = create_contract();
+    let client = MarketplaceEscrowV1Client::new(&env, &contract_id);
+    setup_trade(&env, &client, &buyer, &seller);
+    client.set_credit_limit(&buyer, &30000_0000000);
+
+    // Cancelling frees the credit straight away
+    let cancelled_id = create_with_terms(&env, &client, &buyer, &seller, &net_terms(&env, 30));
+    client.cancel_trade(&buyer, &cancelled_id);
+    assert_eq!(client.get_credit_used(&buyer), 0);
+
+    let trade_id = create_with_terms(&env, &client, &buyer, &seller, &net_terms(&env, 30));
+    fulfill_matching(&env, &client, &seller, trade_id);
+    client.accept_trade(&buyer, &trade_id);
+
+    // Once the due date passes the buyer is overdue
+    let due = client.get_trade(&trade_id).payment_due;
+    env.ledger().set_timestamp(due);
+    setup_trade(&env, &client, &buyer, &seller);
+    env.ledger().set_timestamp(due + 1);
+    assert_eq!(
+        client.get_overdue_trades(&buyer),
+        Vec::from_array(&env, [trade_id])
+    );
+    assert_eq!(
+        client.try_create_trade(
+            &buyer,
+            &seller,
+            &change_terms(&env, 1000, 15000_0000000),
+            &String::from_str(&env, "QmBuyerLEI"),
+            &String::from_str(&env, "QmSellerLEI"),
+            &None,
+        ),
+        Err(Ok(ContractError::BuyerPaymentsOverdue))
+    );
+
+    // Paying late clears the block
+    client.pay_deferred(&buyer, &trade_id);
+    assert_eq!(client.get_overdue_trades(&buyer).len(), 0);
+    setup_trade(&env, &client, &buyer, &seller);
+}
+
+/// "2/10 net 30" with a 0.1% daily late penalty
+fn discount_terms(env: &Env) -> PurchaseOrderDetails {
+    let mut terms = net_terms(env, 30);
+    terms.early_payment_discount_bps = 200;
+    terms.discount_window_days = 10;
+    terms.late_penalty_bps_per_day = 10;
+    terms
+}
+
+#[test]
+fn test_early_payment_discount_and_late_penalty() {
+    let (env, contract_id, _admin, buyer, seller) = create_contract();
+    let client = MarketplaceEscrowV1Client::new(&env, &contract_id);
+    let (_treasury, token) = payment_accounts(&env, &contract_id);
+    setup_trade(&env, &client, &buyer, &seller);
+    client.set_credit_limit(&buyer, &40000_0000000);
+    let lei = String::from_str(&env, "QmBuyerLEI");
+
+    // Discounts need a window inside the payment term, and only deferred
+    // trades take either adjustment
+    let mut terms = discount_terms(&env);
+    terms.discount_window_days = 31;
+    assert_eq!(
+        client.try_create_trade(&buyer, &seller, &terms, &lei, &lei, &None),
+        Err(Ok(ContractError::InvalidPaymentTerms))
+    );
+    terms.discount_window_days = 0;
+    assert_eq!(
+        client.try_create_trade(&buyer, &seller, &terms, &lei, &lei, &None),
+        Err(Ok(ContractError::InvalidPaymentTerms))
+    );
+    let mut terms = change_terms(&env, 1000, 15000_0000000);
+    terms.late_penalty_bps_per_day = 10;
+    assert_eq!(
+        client.try_create_trade(&buyer, &seller, &terms, &lei, &lei, &None),
+        Err(Ok(ContractError::InvalidPaymentTerms))
+    );
+
+    // Paying inside the window takes 2% off
+    let early_id = create_with_terms(&env, &client, &buyer, &seller, &discount_terms(&env));
+    fulfill_matching(&env, &client, &seller, early_id);
+    client.accept_trade(&buyer, &early_id);
+    env.ledger()
+        .set_timestamp(env.ledger().timestamp() + 10 * 86400);
+
+    let quote = client.quote_deferred_payment(&early_id);
+    assert_eq!(quote.discount, 300_0000000);
+    assert_eq!(quote.penalty, 0);
+    let seller_before = token.balance(&seller);
+    assert_eq!(client.pay_deferred(&buyer, &early_id), quote.total_due);
+
+    let trade = client.get_trade(&early_id);
+    assert_eq!(trade.early_payment_discount, 300_0000000);
+    assert_eq!(trade.late_payment_penalty, 0);
+    assert_eq!(
+        token.balance(&seller),
+        seller_before + trade.amount - trade.seller_fee - 300_0000000
+    );
+
+    // Paying two and a half days late costs three days of penalty
+    let late_id = create_with_terms(&env, &client, &buyer, &seller, &discount_terms(&env));
+    fulfill_matching(&env, &client, &seller, late_id);
+    client.accept_trade(&buyer, &late_id);
+    let due = client.get_trade(&late_id).payment_due;
+    env.ledger().set_timestamp(due + 2 * 86400 + 43200);
+
+    let quote = client.quote_deferred_payment(&late_id);
+    assert_eq!(quote.discount, 0);
+    assert_eq!(quote.penalty, 45_0000000);
+    let seller_before = token.balance(&seller);
+    assert_eq!(client.pay_deferred(&buyer, &late_id), quote.total_due);
+
+    let trade = client.get_trade(&late_id);
+    assert_eq!(trade.late_payment_penalty, 45_0000000);
+    assert_eq!(
+        token.balance(&seller),
+        seller_before + trade.amount - trade.seller_fee + 45_0000000
+    );
+    assert_eq!(
+        client.try_quote_deferred_payment(&late_id),
+        Err(Ok(ContractError::DeferredPaymentAlreadyMade))
+    );
+}
+
+/// Warehouse-receipt contract with one approved warehouse
+fn warehouse_receipts(env: &Env) -> (WarehouseReceiptContractClient<'_>, Address) {
+    let receipts_id = env.register(WarehouseReceiptContract, (Address::generate(env),));
+    let receipts = WarehouseReceiptContractClient::new(env, &receipts_id);
+    let warehouse = Address::generate(env);
+    receipts.add_warehouse(&warehouse);
+
+    (receipts, warehouse)
+}
+
+/// Issue the seller a receipt for `quantity` of the standard WR's lot, where it is stored
+fn issue_receipt(
+    env: &Env,
+    receipts: &WarehouseReceiptContractClient,
+    warehouse: &Address,
+    seller: &Address,
+    quantity: u64,
+) -> u32 {
+    receipts.issue(
+        warehouse,
+        seller,
+        &String::from_str(env, LOT),
+        &quantity,
+        &String::from_str(env, WAREHOUSE_LOCATION),
+    )
+}
+
+#[test]
+fn test_receivable_holder_paid_at_settlement() {
+    let (env, contract_id, _admin, buyer, seller) = create_contract();
+    let client = MarketplaceEscrowV1Client::new(&env, &contract_id);
+    let (_treasury, token) = payment_accounts(&env, &contract_id);
+    let nft_id = env.register(ExampleContract, (&contract_id,));
+    let nft = ExampleContractClient::new(&env, &nft_id);
+    client.set_receivable_contract(&nft_id);
+    let factor = Address::generate(&env);
+
+    let trade_id = setup_trade(&env, &client, &buyer, &seller);
+    let cost = client.calculate_escrow_cost(&buyer, &seller, &15000_0000000);
+    client.fund_escrow(&buyer, &trade_id, &cost.total_required);
+
+    // Nothing is owed until the goods are delivered
+    assert_eq!(
+        client.try_mint_receivable(&seller, &trade_id),
+        Err(Ok(ContractError::InvalidTradeState))
+    );
+    fulfill_matching(&env, &client, &seller, trade_id);
+    assert_eq!(
+        client.try_mint_receivable(&buyer, &trade_id),
+        Err(Ok(ContractError::NotSeller))
+    );
+
+    let token_id = client.mint_receivable(&seller, &trade_id);
+    assert_eq!(nft.owner_of(&token_id), seller);
+    let trade = client.get_trade(&trade_id);
+    assert_eq!(trade.receivable_contract, Some(nft_id.clone()));
+    assert_eq!(trade.receivable_token_id, token_id);
+    assert_eq!(
+        client.try_mint_receivable(&seller, &trade_id),
+        Err(Ok(ContractError::ReceivableAlreadyMinted))
+    );
+
+    // The seller factors the receivable, and the factor is paid at settlement
+    nft.transfer(&seller, &factor, &token_id);
+    let seller_before = token.balance(&seller);
+    client.accept_trade(&buyer, &trade_id);
+
+    let trade = client.get_trade(&trade_id);
+    assert_eq!(
+        token.balance(&factor),
+        trade.amount - trade.seller_fee - trade.broker_commission
+    );
+    assert_eq!(token.balance(&seller), seller_before);
+    assert_eq!(trade.escrow_balance, 0);
+}
+
+#[test]
+fn test_receivable_restrictions() {
+    let (env, contract_id, _admin, buyer, seller) = create_contract();
+    let client = MarketplaceEscrowV1Client::new(&env, &contract_id);
+    let (_treasury, token) = payment_accounts(&env, &contract_id);
+    client.set_max_advance_rate(&3000);
+    let cost = client.calculate_escrow_cost(&buyer, &seller, &15000_0000000);
+
+    let trade_id = setup_trade(&env, &client, &buyer, &seller);
+    client.fund_escrow(&buyer, &trade_id, &cost.total_required);
+    fulfill_matching(&env, &client, &seller, trade_id);
+    assert_eq!(
+        client.try_mint_receivable(&seller, &trade_id),
+        Err(Ok(ContractError::ReceivableContractNotSet))
+    );
+    let nft_id = env.register(ExampleContract, (&contract_id,));
+    client.set_receivable_contract(&nft_id);
+
+    // A seller owing an unwound advance can't sell the payout it is netted from
+    let rejected_id = setup_trade(&env, &client, &buyer, &seller);
+    client.fund_escrow(&buyer, &rejected_id, &cost.total_required);
+    client.authorize_advance(&buyer, &rejected_id, &3000_0000000);
+    client.reject_order(&seller, &rejected_id);
+    assert_eq!(
+        client.try_mint_receivable(&seller, &trade_id),
+        Err(Ok(ContractError::ReceivableNotAllowed))
+    );
+
+    // Once the debt is repaid, a receivable the seller keeps pays the seller
+    let repaid_id = setup_trade(&env, &client, &buyer, &seller);
+    client.fund_escrow(&buyer, &repaid_id, &cost.total_required);
+    fulfill_matching(&env, &client, &seller, repaid_id);
+    client.accept_trade(&buyer, &repaid_id);
+    assert_eq!(client.get_seller_debts(&seller).len(), 0);
+
+    client.mint_receivable(&seller, &trade_id);
+    let seller_before = token.balance(&seller);
+    client.accept_trade(&buyer, &trade_id);
+
+    let trade = client.get_trade(&trade_id);
+    assert_eq!(
+        token.balance(&seller),
+        seller_before + trade.amount - trade.seller_fee - trade.broker_commission
+    );
+}
+
+#[test]
+fn test_title_receipt_passes_to_buyer_at_settlement() {
+    let (env, contract_id, _admin, buyer, seller) = create_contract();
+    let client = MarketplaceEscrowV1Client::new(&env, &contract_id);
+    let (_treasury, token) = payment_accounts(&env, &contract_id);
+    let (receipts, warehouse) = warehouse_receipts(&env);
+
+    let mut terms = change_terms(&env, 1000, 15000_0000000);
+    terms.requires_title_receipt = true;
+    let trade_id = setup_amended_trade(&env, &client, &buyer, &seller, &terms);
+    fulfill_matching(&env, &client, &seller, trade_id);
+    let wr = client.get_warehouse_receipt(&trade_id);
+    assert_eq!(
+        wr.warehouse_location,
+        String::from_str(&env, WAREHOUSE_LOCATION)
+    );
+    assert_eq!(wr.lot, String::from_str(&env, LOT));
+
+    // The documents match, but the buyer doesn't pay without title
+    assert_eq!(
+        client.try_accept_trade(&buyer, &trade_id),
+        Err(Ok(ContractError::TitleReceiptRequired))
+    );
+    let short_id = issue_receipt(&env, &receipts, &warehouse, &seller, 900);
+    let token_id = issue_receipt(&env, &receipts, &warehouse, &seller, 1000);
+    let elsewhere_id = receipts.issue(
+        &warehouse,
+        &seller,
+        &String::from_str(&env, LOT),
+        &1000,
+        &String::from_str(&env, "Port of Antwerp, Shed 4"),
+    );
+    let other_lot_id = receipts.issue(
+        &warehouse,
+        &seller,
+        &String::from_str(&env, "LOT-2024-0342"),
+        &1000,
+        &String::from_str(&env, WAREHOUSE_LOCATION),
+    );
+    assert_eq!(
+        client.try_deposit_title_receipt(&seller, &trade_id, &token_id),
+        Err(Ok(ContractError::TitleReceiptContractNotSet))
+    );
+    client.set_title_receipt_contract(&receipts.address);
+    assert_eq!(
+        client.try_deposit_title_receipt(&seller, &trade_id, &short_id),
+        Err(Ok(ContractError::TitleReceiptMismatch))
+    );
+    // Nor is title to another lot, or to goods stored elsewhere
+    assert_eq!(
+        client.try_deposit_title_receipt(&seller, &trade_id, &other_lot_id),
+        Err(Ok(ContractError::TitleReceiptMismatch))
+    );
+    assert_eq!(
+        client.try_deposit_title_receipt(&seller, &trade_id, &elsewhere_id),
+        Err(Ok(ContractError::TitleReceiptMismatch))
+    );
+
+    client.deposit_title_receipt(&seller, &trade_id, &token_id);
+    assert_eq!(receipts.owner_of(&token_id), contract_id);
+    assert_eq!(
+        client.try_deposit_title_receipt(&seller, &trade_id, &short_id),
+        Err(Ok(ContractError::TitleReceiptAlreadyDeposited))
+    );
+
+    // Title and payment change hands together
+    let seller_before = token.balance(&seller);
+    client.accept_trade(&buyer, &trade_id);
+
+    let trade = client.get_trade(&trade_id);
+    assert_eq!(receipts.owner_of(&token_id), buyer);
+    assert_eq!(
+        token.balance(&seller),
+        seller_before + trade.amount - trade.seller_fee - trade.broker_commission
+    );
+}
+
+#[test]
+fn test_title_receipt_returned_when_trade_unwinds() {
+    let (env, contract_id, _admin, buyer, seller) = create_contract();
+    let client = MarketplaceEscrowV1Client::new(&env, &contract_id);
+    let (receipts, warehouse) = warehouse_receipts(&env);
+    client.set_title_receipt_contract(&receipts.address);
+
+    let (trade_id, agency) = setup_inspected_trade(&env, &client, &buyer, &seller);
+    let token_id = issue_receipt(&env, &receipts, &warehouse, &seller, 1000);
+    assert_eq!(
+        client.try_deposit_title_receipt(&seller, &trade_id, &token_id),
+        Err(Ok(ContractError::InvalidTradeState))
+    );
+    fulfill_matching(&env, &client, &seller, trade_id);
+    client.deposit_title_receipt(&seller, &trade_id, &token_id);
+
+    // Goods fail inspection: the buyer is refunded and the seller keeps title
+    client.submit_inspection_certificate(&agency, &trade_id, &inspection_certificate(&env, false));
+    assert_eq!(client.get_trade(&trade_id).state, TradeState::Refunded);
+    assert_eq!(receipts.owner_of(&token_id), seller);
+}
+
+/// Two-of-three officer sign-off on the default buyer's trades over 10,000 XLM
+fn officer_policy(env: &Env) -> ApprovalPolicy {
+    ApprovalPolicy {
+        threshold: 10000_0000000,
+        approvers: Vec::from_array(
+            env,
+            [
+                Address::generate(env),
+                Address::generate(env),
+                Address::generate(env),
+            ],
+        ),
+        required: 2,
+    }
+}
+
+#[test]
+fn test_high_value_trade_held_for_approvals() {
+    let (env, contract_id, _admin, buyer, seller) = create_contract();
+    let client = MarketplaceEscrowV1Client::new(&env, &contract_id);
+    let (_treasury, token) = payment_accounts(&env, &contract_id);
+    let policy = officer_policy(&env);
+    let cfo = policy.approvers.get(0).unwrap();
+    let treasurer = policy.approvers.get(1).unwrap();
+    let cost = client.calculate_escrow_cost(&buyer, &seller, &15000_0000000);
+
+    assert_eq!(
+        client.try_set_approval_policy(&buyer, &policy, &Vec::new(&env)),
+        Err(Ok(ContractError::BuyerNotRegistered))
+    );
+    let earlier_id = setup_trade(&env, &client, &buyer, &seller);
+    let mut unreachable = policy.clone();
+    unreachable.required = 4;
+    assert_eq!(
+        client.try_set_approval_policy(&buyer, &unreachable, &Vec::new(&env)),
+        Err(Ok(ContractError::InvalidApprovalPolicy))
+    );
+    client.set_approval_policy(&buyer, &policy, &Vec::new(&env));
+
+    // Opening the trade waits for two officers
+    let trade_id = setup_trade(&env, &client, &buyer, &seller);
+    assert_eq!(
+        client.get_pending_approval(&trade_id).unwrap().action,
+        ApprovalAction::CreateTrade
+    );
+    assert_eq!(client.get_pending_approval(&earlier_id), None);
+    assert_eq!(
+        client.try_fund_escrow(&buyer, &trade_id, &cost.total_required),
+        Err(Ok(ContractError::ApprovalPending))
+    );
+    assert_eq!(
+        client.try_approve_action(&seller, &trade_id),
+        Err(Ok(ContractError::NotApprover))
+    );
+    assert!(!client.approve_action(&cfo, &trade_id));
+    assert_eq!(
+        client.try_approve_action(&cfo, &trade_id),
+        Err(Ok(ContractError::ApprovalAlreadyGiven))
+    );
+    assert!(client.approve_action(&treasurer, &trade_id));
+    assert_eq!(client.get_pending_approval(&trade_id), None);
+
+    // Funding is parked outside the escrow until approved
+    let buyer_before = token.balance(&buyer);
+    client.fund_escrow(&buyer, &trade_id, &cost.total_required);
+    assert_eq!(token.balance(&buyer), buyer_before - cost.total_required);
+    assert_eq!(client.get_trade(&trade_id).escrow_balance, 0);
+    assert_eq!(
+        client.get_pending_approval(&trade_id).unwrap().action,
+        ApprovalAction::FundEscrow(cost.clone())
+    );
+    client.approve_action(&treasurer, &trade_id);
+    client.approve_action(&cfo, &trade_id);
+    assert_eq!(
+        client.get_trade(&trade_id).escrow_balance,
+        cost.total_required
+    );
+
+    // Settlement waits too, and the last approval pays the seller
+    fulfill_matching(&env, &client, &seller, trade_id);
+    client.accept_trade(&buyer, &trade_id);
+    assert_eq!(client.get_trade(&trade_id).state, TradeState::Fulfilled);
+    let seller_before = token.balance(&seller);
+    client.approve_action(&cfo, &trade_id);
+    client.approve_action(&treasurer, &trade_id);
+
+    let trade = client.get_trade(&trade_id);
+    assert_eq!(trade.state, TradeState::Settled);
+    assert_eq!(
+        token.balance(&seller),
+        seller_before + trade.amount - trade.seller_fee - trade.broker_commission
+    );
+    assert_eq!(
+        client.get_trade_history(&trade_id).last().unwrap().actor,
+        treasurer
+    );
+}
+
+#[test]
+fn test_approval_policy_replacement_and_parked_refund() {
+    let (env, contract_id, _admin, buyer, seller) = create_contract();
+    let client = MarketplaceEscrowV1Client::new(&env, &contract_id);
+    let (_treasury, token) = payment_accounts(&env, &contract_id);
+    let policy = officer_policy(&env);
+    let cost = client.calculate_escrow_cost(&buyer, &seller, &15000_0000000);
+    setup_trade(&env, &client, &buyer, &seller);
+    client.set_approval_policy(&buyer, &policy, &Vec::new(&env));
+
+    // Loosening the policy needs the current officers' sign-off
+    let mut raised = policy.clone();
+    raised.threshold = 20000_0000000;
+    assert_eq!(
+        client.try_set_approval_policy(
+            &buyer,
+            &raised,
+            &Vec::from_array(&env, [policy.approvers.get(0).unwrap()])
+        ),
+        Err(Ok(ContractError::InsufficientApprovals))
+    );
+    assert_eq!(
+        client.try_set_approval_policy(
+            &buyer,
+            &raised,
+            &Vec::from_array(&env, [seller.clone(), policy.approvers.get(0).unwrap()])
+        ),
+        Err(Ok(ContractError::NotApprover))
+    );
+    client.set_approval_policy(&buyer, &raised, &policy.approvers.slice(1..));
+    assert_eq!(client.get_approval_policy(&buyer), Some(raised.clone()));
+
+    // Under the new threshold nothing is held
+    let trade_id = setup_trade(&env, &client, &buyer, &seller);
+    assert_eq!(client.get_pending_approval(&trade_id), None);
+
+    // Parked funds go back to the buyer if the trade is cancelled instead
+    raised.threshold = 0;
+    client.set_approval_policy(&buyer, &raised, &policy.approvers.slice(..2));
+    let buyer_before = token.balance(&buyer);
+    client.fund_escrow(&buyer, &trade_id, &cost.total_required);
+    client.cancel_trade(&buyer, &trade_id);
+
+    assert_eq!(token.balance(&buyer), buyer_before);
+    assert_eq!(token.balance(&contract_id), 0);
+    assert_eq!(client.get_pending_approval(&trade_id), None);
+}
+
+#[test]
+fn test_advance_and_milestones_held_for_approvals() {
+    let (env, contract_id, _admin, buyer, seller) = create_contract();
+    let client = MarketplaceEscrowV1Client::new(&env, &contract_id);
+    let (_treasury, token) = payment_accounts(&env, &contract_id);
+    let policy = officer_policy(&env);
+    let cfo = policy.approvers.get(0).unwrap();
+    let treasurer = policy.approvers.get(1).unwrap();
+    client.set_max_advance_rate(&3000);
+    let cost = client.calculate_escrow_cost(&buyer, &seller, &15000_0000000);
+    let advanced_id = setup_trade(&env, &client, &buyer, &seller);
+    client.fund_escrow(&buyer, &advanced_id, &cost.total_required);
+    let staged_id = setup_amended_trade(&env, &client, &buyer, &seller, &staged_terms(&env));
+    client.set_approval_policy(&buyer, &policy, &Vec::new(&env));
+    let seller_before = token.balance(&seller);
+
+    // Advances wait for two officers, and nothing else moves meanwhile
+    assert_eq!(
+        client.try_authorize_advance(&buyer, &advanced_id, &5000_0000000),
+        Err(Ok(ContractError::AdvanceLimitExceeded))
+    );
+    client.authorize_advance(&buyer, &advanced_id, &3000_0000000);
+    assert_eq!(
+        client.get_pending_approval(&advanced_id).unwrap().action,
+        ApprovalAction::AuthorizeAdvance(3000_0000000)
+    );
+    assert_eq!(token.balance(&seller), seller_before);
+    assert_eq!(
+        client.try_authorize_advance(&buyer, &advanced_id, &1000_0000000),
+        Err(Ok(ContractError::ApprovalPending))
+    );
+    assert!(!client.approve_action(&cfo, &advanced_id));
+    assert!(client.approve_action(&treasurer, &advanced_id));
+    assert_eq!(token.balance(&seller), seller_before + 3000_0000000);
+    assert_eq!(client.get_trade(&advanced_id).advance_paid, 3000_0000000);
+
+    // So do milestone releases short of settlement
+    let trade = client.get_trade(&staged_id);
+    let deposit = (trade.amount - trade.seller_fee - trade.broker_commission) * 3 / 10;
+    assert_eq!(client.release_milestone(&buyer, &staged_id), 0);
+    assert_eq!(
+        client.get_pending_approval(&staged_id).unwrap().action,
+        ApprovalAction::ReleaseMilestone(0)
+    );
+    assert_eq!(client.get_trade(&staged_id).milestones_released, 0);
+    assert_eq!(
+        client.try_release_milestone(&buyer, &staged_id),
+        Err(Ok(ContractError::ApprovalPending))
+    );
+    client.approve_action(&cfo, &staged_id);
+    client.approve_action(&treasurer, &staged_id);
+
+    let trade = client.get_trade(&staged_id);
+    assert_eq!(trade.milestones_released, 1);
+    assert_eq!(trade.released_to_seller, deposit);
+    assert_eq!(
+        token.balance(&seller),
+        seller_before + 3000_0000000 + deposit
+    );
+}
+
+#[test]
+fn test_change_order_over_threshold_held_for_approvals() {
+    let (env, contract_id, _admin, buyer, seller) = create_contract();
+    let client = MarketplaceEscrowV1Client::new(&env, &contract_id);
+    let (_treasury, token) = payment_accounts(&env, &contract_id);
+    let mut policy = officer_policy(&env);
+    policy.threshold = 20000_0000000;
+    let cfo = policy.approvers.get(0).unwrap();
+    let treasurer = policy.approvers.get(1).unwrap();
+    let original = client.calculate_escrow_cost(&buyer, &seller, &15000_0000000);
+    let repriced = client.calculate_escrow_cost(&buyer, &seller, &22500_0000000);
+    let shortfall = repriced.total_required - original.total_required;
+
+    // Under the threshold the trade opens and funds without sign-off
+    let trade_id = setup_trade(&env, &client, &buyer, &seller);
+    client.set_approval_policy(&buyer, &policy, &Vec::new(&env));
+    client.fund_escrow(&buyer, &trade_id, &original.total_required);
+    assert_eq!(client.get_pending_approval(&trade_id), None);
+
+    // Raising it over the threshold waits for two officers, with the
+    // buyer's shortfall parked outside the escrow
+    client.propose_change_order(&seller, &trade_id, &change_terms(&env, 1500, 22500_0000000));
+    let buyer_before = token.balance(&buyer);
+    assert_eq!(
+        client.accept_change_order(&buyer, &trade_id, &1, &shortfall),
+        1
+    );
+    assert_eq!(
+        client.get_pending_approval(&trade_id).unwrap().action,
+        ApprovalAction::AcceptChangeOrder(1, shortfall)
+    );
+    assert_eq!(token.balance(&buyer), buyer_before - shortfall);
+    let trade = client.get_trade(&trade_id);
+    assert_eq!(trade.amount, 15000_0000000);
+    assert_eq!(trade.escrow_balance, original.total_required);
+    assert_eq!(
+        client.try_propose_change_order(
+            &buyer,
+            &trade_id,
+            &change_terms(&env, 1000, 15000_0000000)
+        ),
+        Err(Ok(ContractError::ApprovalPending))
+    );
+    assert_eq!(
+        client.try_top_up_escrow(&buyer, &trade_id, &shortfall),
+        Err(Ok(ContractError::ApprovalPending))
+    );
+    assert!(!client.approve_action(&cfo, &trade_id));
+    assert!(client.approve_action(&treasurer, &trade_id));
+
+    let trade = client.get_trade(&trade_id);
+    assert_eq!(trade.amount, 22500_0000000);
+    assert_eq!(trade.po_version, 2);
+    assert_eq!(trade.escrow_balance, repriced.total_required);
+    assert_eq!(client.get_pending_change_order(&trade_id), None);
+
+    // A seller's acceptance waits too, and the buyer's top-up after it
+    let other_id = setup_trade(&env, &client, &buyer, &seller);
+    client.fund_escrow(&buyer, &other_id, &original.total_required);
+    client.propose_change_order(&buyer, &other_id, &change_terms(&env, 1500, 22500_0000000));
+    client.accept_change_order(&seller, &other_id, &1, &0);
+    assert_eq!(
+        client.get_pending_approval(&other_id).unwrap().action,
+        ApprovalAction::AcceptChangeOrder(1, 0)
+    );
+    client.approve_action(&cfo, &other_id);
+    client.approve_action(&treasurer, &other_id);
+    assert_eq!(client.get_trade(&other_id).amount, 22500_0000000);
+
+    let buyer_before = token.balance(&buyer);
+    assert_eq!(
+        client.top_up_escrow(&buyer, &other_id, &shortfall),
+        shortfall
+    );
+    assert_eq!(
+        client.get_pending_approval(&other_id).unwrap().action,
+        ApprovalAction::TopUpEscrow(shortfall)
+    );
+    assert_eq!(
+        client.get_trade(&other_id).escrow_balance,
+        original.total_required
+    );
+    assert_eq!(token.balance(&buyer), buyer_before - shortfall);
+    client.approve_action(&cfo, &other_id);
+    client.approve_action(&treasurer, &other_id);
+    assert_eq!(
+        client.get_trade(&other_id).escrow_balance,
+        repriced.total_required
+    );
+
+    // A held change order's parked funds go back if the trade is cancelled
+    let cancelled_id = setup_trade(&env, &client, &buyer, &seller);
+    client.fund_escrow(&buyer, &cancelled_id, &original.total_required);
+    client.propose_change_order(
+        &seller,
+        &cancelled_id,
+        &change_terms(&env, 1500, 22500_0000000),
+    );
+    let buyer_before = token.balance(&buyer);
+    client.accept_change_order(&buyer, &cancelled_id, &1, &shortfall);
+    client.cancel_trade(&buyer, &cancelled_id);
+    assert_eq!(
+        token.balance(&buyer),
+        buyer_before + original.total_required
+    );
+}
+
+/// Delegation for the next 30 days
+fn operator_permissions(
+    env: &Env,
+    max_trade_amount: i128,
+    fulfill_orders: bool,
+    accept_trades: bool,
+) -> OperatorPermissions {
+    OperatorPermissions {
+        max_trade_amount,
+        fulfill_orders,
+        accept_trades,
+        expires_at: env.ledger().timestamp() + 30 * 86400,
+    }
+}
+
+#[test]
+fn test_operators_act_for_participants() {
+    let (env, contract_id, _admin, buyer, seller) = create_contract();
+    let client = MarketplaceEscrowV1Client::new(&env, &contract_id);
+    let clerk = Address::generate(&env);
+    let packer = Address::generate(&env);
+    setup_trade(&env, &client, &buyer, &seller);
+    client.grant_operator(
+        &buyer,
+        &clerk,
+        &operator_permissions(&env, 15000_0000000, false, true),
+    );
+    client.grant_operator(
+        &seller,
+        &packer,
+        &operator_permissions(&env, 0, true, false),
+    );
+
+    // The clerk opens trades for the buyer up to its limit
+    let trade_id = create_with_terms(
+        &env,
+        &client,
+        &clerk,
+        &seller,
+        &change_terms(&env, 1000, 15000_0000000),
+    );
+    assert_eq!(client.get_trade(&trade_id).buyer, buyer);
+    assert_eq!(client.get_purchase_order(&trade_id).created_by, clerk);
+    assert_eq!(
+        client.try_create_trade(
+            &clerk,
+            &seller,
+            &change_terms(&env, 2000, 30000_0000000),
+            &String::from_str(&env, "QmBuyerLEI"),
+            &String::from_str(&env, "QmSellerLEI"),
+            &None,
+        ),
+        Err(Ok(ContractError::OperatorNotPermitted))
+    );
+
+    let cost = client.calculate_escrow_cost(&buyer, &seller, &15000_0000000);
+    client.fund_escrow(&buyer, &trade_id, &cost.total_required);
+    fulfill_matching(&env, &client, &packer, trade_id);
+
+    // Each operator only takes the actions delegated to it
+    assert_eq!(
+        client.try_accept_trade(&packer, &trade_id),
+        Err(Ok(ContractError::NotBuyer))
+    );
+    client.grant_operator(&seller, &packer, &operator_permissions(&env, 0, true, true));
+    assert_eq!(
+        client.try_accept_trade(&seller, &trade_id),
+        Err(Ok(ContractError::NotBuyer))
+    );
+    client.accept_trade(&clerk, &trade_id);
+
+    // The history records which operator acted
+    let actors: Vec<Address> = Vec::from_iter(
+        &env,
+        client
+            .get_trade_history(&trade_id)
+            .iter()
+            .map(|change| change.actor),
+    );
+    assert_eq!(
+        actors,
+        Vec::from_array(&env, [clerk.clone(), packer.clone(), clerk.clone()])
+    );
+    assert_eq!(client.get_trade(&trade_id).state, TradeState::Settled);
+}
+
+#[test]
+fn test_operator_grants_expire_and_are_revoked() {
+    let (env, contract_id, _admin, buyer, seller) = create_contract();
+    let client = MarketplaceEscrowV1Client::new(&env, &contract_id);
+    let clerk = Address::generate(&env);
+    env.ledger().set_timestamp(1000);
+    let permissions = operator_permissions(&env, 15000_0000000, false, true);
+
+    assert_eq!(
+        client.try_grant_operator(&buyer, &clerk, &permissions),
+        Err(Ok(ContractError::SellerNotRegistered))
+    );
+    let trade_id = setup_trade(&env, &client, &buyer, &seller);
+    let mut lapsed = permissions.clone();
+    lapsed.expires_at = 1000;
+    assert_eq!(
+        client.try_grant_operator(&buyer, &clerk, &lapsed),
+        Err(Ok(ContractError::InvalidOperatorGrant))
+    );
+    client.grant_operator(&buyer, &clerk, &permissions);
+    assert_eq!(client.get_operator(&clerk).unwrap().participant, buyer);
+
+    // An operator acts for one participant at a time
+    assert_eq!(
+        client.try_grant_operator(&seller, &clerk, &permissions),
+        Err(Ok(ContractError::OperatorAlreadyAssigned))
+    );
+    assert_eq!(
+        client.try_revoke_operator(&seller, &clerk),
+        Err(Ok(ContractError::NotOperator))
+    );
+
+    let cost = client.calculate_escrow_cost(&buyer, &seller, &15000_0000000);
+    client.fund_escrow(&buyer, &trade_id, &cost.total_required);
+    fulfill_matching(&env, &client, &seller, trade_id);
+
+    // A lapsed delegation no longer lets the clerk accept
+    env.ledger().set_timestamp(permissions.expires_at);
+    assert_eq!(
+        client.try_accept_trade(&clerk, &trade_id),
+        Err(Ok(ContractError::OperatorExpired))
+    );
+
+    // Nor does a revoked one
+    client.grant_operator(
+        &buyer,
+        &clerk,
+        &operator_permissions(&env, 15000_0000000, false, true),
+    );
+    client.revoke_operator(&buyer, &clerk);
+    assert_eq!(client.get_operator(&clerk), None);
+    assert_eq!(
+        client.try_accept_trade(&clerk, &trade_id),
+        Err(Ok(ContractError::NotBuyer))
+    );
+    client.accept_trade(&buyer, &trade_id);
 }
@@ -0,0 +1,121 @@
+//! Warehouse receipt NFTs held in escrow and delivered as title at settlement
+
+use soroban_sdk::{contractclient, Address, Env};
+
+use crate::errors::ContractError;
+use crate::state;
+use crate::storage::DataKey;
+use crate::types::{PurchaseOrder, ReceiptMetadata, TradeEscrow, TradeState, WarehouseReceipt};
+
+/// Warehouse receipt NFT contract interface
+///
+/// Matches the warehouse-receipt contract. Only the generated client is used.
+#[allow(dead_code)]
+#[contractclient(name = "TitleReceiptClient")]
+pub trait TitleReceiptNft {
+    /// Move a token, with the authorization of its holder
+    fn transfer(env: Env, from: Address, to: Address, token_id: u32);
+
+    /// Goods a receipt gives title to
+    fn receipt(env: Env, token_id: u32) -> ReceiptMetadata;
+}
+
+/// Get the NFT contract warehouse receipts are accepted from, if one is configured
+pub fn get_title_receipt_contract(env: &Env) -> Option<Address> {
+    env.storage().instance().get(&DataKey::TitleReceiptContract)
+}
+
+/// Lodge the seller's warehouse receipt NFT with the marketplace
+///
+/// The receipt must be for the lot, quantity and warehouse location on the
+/// trade's warehouse receipt document. The marketplace holds it until the
+/// trade settles, when it goes to the buyer, or unwinds, when it goes back
+/// to the seller.
+pub fn deposit_title_receipt(
+    env: &Env,
+    seller: &Address,
+    trade_id: u64,
+    token_id: u32,
+) -> Result<(), ContractError> {
+    let mut trade: TradeEscrow = env
+        .storage()
+        .instance()
+        .get(&DataKey::Trade(trade_id))
+        .ok_or(ContractError::TradeNotFound)?;
+
+    // Verify caller is seller
+    if &trade.seller != seller {
+        return Err(ContractError::NotSeller);
+    }
+
+    // The receipt stands for the goods fulfillment reported as warehoused
+    state::require_state(&trade, TradeState::Fulfilled)?;
+
+    if trade.title_receipt_contract.is_some() {
+        return Err(ContractError::TitleReceiptAlreadyDeposited);
+    }
+
+    let wr: WarehouseReceipt = env
+        .storage()
+        .instance()
+        .get(&DataKey::WarehouseReceipt(trade_id))
+        .ok_or(ContractError::WarehouseReceiptNotFound)?;
+    let contract =
+        get_title_receipt_contract(env).ok_or(ContractError::TitleReceiptContractNotSet)?;
+    let client = TitleReceiptClient::new(env, &contract);
+    let receipt = client.receipt(&token_id);
+    // Receipts migrated from version 1 name no lot, so no NFT can stand for them
+    if wr.lot.is_empty()
+        || receipt.lot != wr.lot
+        || receipt.quantity != wr.quantity
+        || receipt.location != wr.warehouse_location
+    {
+        return Err(ContractError::TitleReceiptMismatch);
+    }
+
+    client.transfer(seller, &env.current_contract_address(), &token_id);
+
+    trade.title_receipt_contract = Some(contract);
+    trade.title_receipt_token_id = token_id;
+    trade.updated_at = env.ledger().timestamp();
+
+    env.storage()
+        .instance()
+        .set(&DataKey::Trade(trade_id), &trade);
+
+    Ok(())
+}
+
+/// Fail if the trade's PO requires a warehouse receipt that was never deposited
+pub fn require_deposited(env: &Env, trade: &TradeEscrow) -> Result<(), ContractError> {
+    let po: PurchaseOrder = env
+        .storage()
+        .instance()
+        .get(&DataKey::PurchaseOrder(trade.trade_id))
+        .ok_or(ContractError::PurchaseOrderNotFound)?;
+    if po.requires_title_receipt && trade.title_receipt_contract.is_none() {
+        return Err(ContractError::TitleReceiptRequired);
+    }
+
+    Ok(())
+}
+
+/// Pass a trade's deposited receipt, and with it title, to the buyer
+pub fn deliver(env: &Env, trade: &TradeEscrow) {
+    transfer_out(env, trade, &trade.buyer);
+}
+
+/// Hand a trade's deposited receipt back to the seller
+pub fn return_to_seller(env: &Env, trade: &TradeEscrow) {
+    transfer_out(env, trade, &trade.seller);
+}
+
+fn transfer_out(env: &Env, trade: &TradeEscrow, to: &Address) {
+    if let Some(contract) = &trade.title_receipt_contract {
+        TitleReceiptClient::new(env, contract).transfer(
+            &env.current_contract_address(),
+            to,
+            &trade.title_receipt_token_id,
+        );
+    }
+}
@@ -2,27 +2,47 @@
 
 use soroban_sdk::{Address, Env, String, Vec};
 
+use crate::advance;
+use crate::approval;
+use crate::bond;
+use crate::deferred;
+use crate::delivery;
+use crate::documents;
 use crate::errors::ContractError;
-use crate::registry::{get_buyer_info, get_seller_info, is_buyer_active, is_seller_active};
+use crate::inspection;
+use crate::fees;
+use crate::matching;
+use crate::milestones;
+use crate::operators::{self, OperatorAction};
+use crate::receivable;
+use crate::registry::{
+    get_buyer_info, get_seller_info, is_broker_active, is_buyer_active, is_seller_active,
+};
+use crate::settlement;
+use crate::state;
 use crate::storage::DataKey;
+use crate::title_receipt;
+use crate::treasury;
 use crate::types::{
-    CustomerInvoice, PurchaseOrder, TradeEscrow, VLEIDocuments, WarehouseReceipt, CANCELLED,
-    FULFILLED, ORDERED, REJECTED, SETTLED,
+    ApprovalAction, BrokerCommission, CustomerInvoice, CustomerInvoiceDetails, EscrowCost,
+    PurchaseOrder, PurchaseOrderDetails, TradeEscrow, TradeState, VLEIDocuments, WarehouseReceipt,
+    WarehouseReceiptDetails,
 };
 
 /// Create a new trade with purchase order
+///
+/// The caller is the buyer or one of its operators.
 pub fn create_trade(
     env: &Env,
-    buyer: &Address,
+    caller: &Address,
     seller: &Address,
-    po_description: String,
-    quantity: u64,
-    unit_price: i128,
-    total_price: i128,
-    po_json_ipfs_hash: String,
+    details: PurchaseOrderDetails,
     buyer_lei_ipfs: String,
     seller_lei_ipfs: String,
+    broker: Option<BrokerCommission>,
 ) -> Result<u64, ContractError> {
+    let buyer = &operators::resolve_buyer(env, caller, details.total_price)?;
+
     // Verify buyer and seller are different
     if buyer == seller {
         return Err(ContractError::BuyerCannotBeSeller);
@@ -31,14 +51,46 @@ pub fn create_trade(
     // Verify buyer is registered and active
     is_buyer_active(env, buyer)?;
 
+    // Buyers behind on deferred payments can't open new trades
+    deferred::check_buyer_standing(env, buyer)?;
+
     // Verify seller is registered and active
     is_seller_active(env, seller)?;
 
     // Validate amount
+    let total_price = details.total_price;
     if total_price <= 0 {
         return Err(ContractError::InvalidAmount);
     }
 
+    // Verify the PO identifies the product, adds up and references its content
+    matching::check_document_identity(&details.po_description, &details.product)?;
+    matching::check_document_totals(details.quantity, details.unit_price, total_price)?;
+    documents::check_document_reference(&details.po_json_ipfs_hash, &details.po_json_hash)?;
+    delivery::check_delivery_terms(env, &details.delivery)?;
+    inspection::check_inspection_terms(env, &details)?;
+    milestones::check_payment_schedule(&details)?;
+    bond::check_bond_rate(details.performance_bond_rate)?;
+    deferred::check_payment_terms(&details)?;
+
+    // Verify broker is a registered third party and work out their commission
+    let broker_commission_rate = broker
+        .as_ref()
+        .map_or(0, |introduction| introduction.commission_rate);
+    let broker_commission = match &broker {
+        Some(introduction) => {
+            if &introduction.broker == buyer || &introduction.broker == seller {
+                return Err(ContractError::BrokerCannotBeParty);
+            }
+            // The commission comes out of the seller's payout, so the seller
+            // signs off on the broker and rate
+            seller.require_auth();
+            is_broker_active(env, &introduction.broker)?;
+            fees::broker_commission(total_price, introduction.commission_rate)?
+        }
+        None => 0,
+    };
+
     // Get LEI IDs from registry
     let buyer_info = get_buyer_info(env, buyer)?;
     let seller_info = get_seller_info(env, seller)?;
@@ -51,27 +103,65 @@ pub fn create_trade(
         .unwrap_or(1);
 
     // Create trade escrow
-    let trade = TradeEscrow {
+    let mut trade = TradeEscrow {
         trade_id,
         buyer: buyer.clone(),
         seller: seller.clone(),
         amount: total_price,
-        state: ORDERED,
+        state: TradeState::Ordered,
         created_at: env.ledger().timestamp(),
         fulfilled_at: 0,
         settled_at: 0,
+        updated_at: env.ledger().timestamp(),
         marketplace_fee: 0,
         escrow_balance: 0,
+        payment_token: settlement::get_payment_token(env),
+        fee_payer: fees::resolve_fee_payer(env, seller),
+        seller_fee: 0,
+        broker: broker.map(|introduction| introduction.broker),
+        broker_commission_rate,
+        broker_commission,
+        po_version: 1,
+        milestones_released: 0,
+        released_to_seller: 0,
+        advance_paid: 0,
+        bond_balance: 0,
+        bond_deadline: 0,
+        credit_reserved: 0,
+        payment_due: 0,
+        early_payment_discount: 0,
+        late_payment_penalty: 0,
+        receivable_contract: None,
+        receivable_token_id: 0,
+        title_receipt_contract: None,
+        title_receipt_token_id: 0,
     };
 
+    // Open account trades draw on the buyer's credit limit instead of escrow
+    if details.payment_terms_days > 0 {
+        deferred::reserve_credit(env, &mut trade)?;
+    }
+
     // Create purchase order
     let po = PurchaseOrder {
-        po_description,
-        quantity,
-        unit_price,
+        po_description: details.po_description,
+        product: details.product,
+        quantity: details.quantity,
+        unit_price: details.unit_price,
         total_price,
-        po_json_ipfs_hash,
-        created_by: buyer.clone(),
+        po_json_ipfs_hash: details.po_json_ipfs_hash,
+        po_json_hash: details.po_json_hash,
+        delivery: details.delivery,
+        requires_inspection: details.requires_inspection,
+        inspection_agency: details.inspection_agency,
+        payment_schedule: details.payment_schedule,
+        performance_bond_rate: details.performance_bond_rate,
+        payment_terms_days: details.payment_terms_days,
+        early_payment_discount_bps: details.early_payment_discount_bps,
+        discount_window_days: details.discount_window_days,
+        late_penalty_bps_per_day: details.late_penalty_bps_per_day,
+        requires_title_receipt: details.requires_title_receipt,
+        created_by: caller.clone(),
         created_at: env.ledger().timestamp(),
     };
 
@@ -90,6 +180,7 @@ pub fn create_trade(
     env.storage()
         .instance()
         .set(&DataKey::Trade(trade_id), &trade);
+    state::record_created(env, &trade, caller);
 
     // Store purchase order
     env.storage()
@@ -112,10 +203,18 @@ pub fn create_trade(
         .instance()
         .set(&DataKey::NextTradeId, &(trade_id + 1));
 
+    // Trades over the buyer's threshold wait for its approvers
+    if approval::requires_approval(env, &trade) {
+        approval::hold(env, trade_id, ApprovalAction::CreateTrade)?;
+    }
+
     Ok(trade_id)
 }
 
 /// Fund escrow (buyer adds amount + marketplace fee)
+///
+/// `payment_amount` is the most the buyer is willing to pay; exactly the
+/// required funding is pulled and nothing more.
 pub fn fund_escrow(
     env: &Env,
     buyer: &Address,
@@ -134,9 +233,13 @@ pub fn fund_escrow(
         return Err(ContractError::NotBuyer);
     }
 
-    // Verify trade is in ORDERED state
-    if trade.state != ORDERED {
-        return Err(ContractError::InvalidTradeState);
+    // Verify trade is in Ordered state
+    state::require_state(&trade, TradeState::Ordered)?;
+    approval::require_none_pending(env, trade_id)?;
+
+    // Open account trades are paid after settlement, not escrowed
+    if deferred::is_deferred(&trade) {
+        return Err(ContractError::InvalidPaymentTerms);
     }
 
     // Verify not already funded
@@ -144,25 +247,33 @@ pub fn fund_escrow(
         return Err(ContractError::EscrowAlreadyFunded);
     }
 
-    // Calculate marketplace fee
-    let marketplace_fee = (trade.amount as i128)
-        .checked_mul(marketplace_fee_rate as i128)
-        .ok_or(ContractError::OverflowError)?
-        / 10000;
-
-    let total_required = trade
-        .amount
-        .checked_add(marketplace_fee)
-        .ok_or(ContractError::OverflowError)?;
+    // Resolve the marketplace fee and who bears it
+    let cost = fees::escrow_cost(
+        env,
+        &trade.buyer,
+        &trade.seller,
+        trade.amount,
+        marketplace_fee_rate,
+    )?;
 
     // Verify payment amount is sufficient
-    if payment_amount < total_required {
+    if payment_amount < cost.total_required {
         return Err(ContractError::InsufficientEscrowFunding);
     }
 
-    // Update trade
-    trade.escrow_balance = payment_amount;
-    trade.marketplace_fee = marketplace_fee;
+    // Seller's share of the fee and the broker's commission both come out of the payout
+    if cost.seller_payout < trade.broker_commission {
+        return Err(ContractError::InvalidAmount);
+    }
+
+    // Move exactly the required funds into escrow
+    settlement::collect(env, &trade, cost.total_required)?;
+
+    // Over the buyer's threshold the funds stay parked until approved
+    if approval::requires_approval(env, &trade) {
+        return approval::hold(env, trade_id, ApprovalAction::FundEscrow(cost));
+    }
+    credit_escrow(env, &mut trade, &cost);
 
     env.storage()
         .instance()
@@ -171,40 +282,123 @@ pub fn fund_escrow(
     Ok(())
 }
 
-/// Fulfill order by seller (add CI and WR)
-pub fn fulfill_order(
+/// Record funds collected for a trade as its escrow
+pub fn credit_escrow(env: &Env, trade: &mut TradeEscrow, cost: &EscrowCost) {
+    trade.escrow_balance = cost.total_required;
+    trade.marketplace_fee = cost.marketplace_fee;
+    trade.fee_payer = cost.fee_payer;
+    trade.seller_fee = cost.seller_fee;
+    trade.updated_at = env.ledger().timestamp();
+}
+
+/// Top up a funded trade whose escrow no longer covers its required funding
+///
+/// `max_payment` caps what the buyer is willing to pay. Over the buyer's
+/// approval threshold the top-up is parked until its approvers sign off.
+/// Returns the amount collected.
+pub fn top_up_escrow(
     env: &Env,
-    seller: &Address,
+    buyer: &Address,
     trade_id: u64,
-    ci_description: String,
-    ci_quantity: u64,
-    ci_unit_price: i128,
-    ci_total_price: i128,
-    ci_json_ipfs_hash: String,
-    wr_description: String,
-    wr_quantity: u64,
-    wr_unit_price: i128,
-) -> Result<(), ContractError> {
+    max_payment: i128,
+) -> Result<i128, ContractError> {
     let mut trade: TradeEscrow = env
         .storage()
         .instance()
         .get(&DataKey::Trade(trade_id))
         .ok_or(ContractError::TradeNotFound)?;
 
-    // Verify caller is seller
-    if &trade.seller != seller {
-        return Err(ContractError::NotSeller);
+    // Verify caller is buyer
+    if &trade.buyer != buyer {
+        return Err(ContractError::NotBuyer);
     }
 
-    // Verify trade is in ORDERED state
-    if trade.state != ORDERED {
-        return Err(ContractError::InvalidTradeState);
+    // Verify trade is in Ordered state
+    state::require_state(&trade, TradeState::Ordered)?;
+    approval::require_none_pending(env, trade_id)?;
+
+    // Only trades already funded once can be topped up
+    if trade.escrow_balance == 0 {
+        return Err(ContractError::EscrowNotFunded);
+    }
+
+    let shortfall = required_escrow(&trade) - trade.escrow_balance;
+    if shortfall <= 0 {
+        return Err(ContractError::EscrowAlreadyFunded);
+    }
+    if max_payment < shortfall {
+        return Err(ContractError::InsufficientEscrowFunding);
+    }
+
+    settlement::collect(env, &trade, shortfall)?;
+
+    // Over the buyer's threshold the funds stay parked until approved
+    if approval::requires_approval(env, &trade) {
+        approval::hold(env, trade_id, ApprovalAction::TopUpEscrow(shortfall))?;
+        return Ok(shortfall);
     }
+    trade.escrow_balance += shortfall;
+    trade.updated_at = env.ledger().timestamp();
+
+    env.storage()
+        .instance()
+        .set(&DataKey::Trade(trade_id), &trade);
+
+    Ok(shortfall)
+}
 
-    // Verify escrow is funded
+/// Funding a trade's escrow must hold: the amount plus the buyer's share of the
+/// fee, less any milestones and advance already paid to the seller
+pub fn required_escrow(trade: &TradeEscrow) -> i128 {
+    trade.amount + trade.marketplace_fee
+        - trade.seller_fee
+        - trade.released_to_seller
+        - trade.advance_paid
+}
+
+/// Fail unless the buyer has covered the trade's required escrow
+///
+/// Open account trades hold no escrow and always pass.
+pub fn require_covered_escrow(trade: &TradeEscrow) -> Result<(), ContractError> {
+    if deferred::is_deferred(trade) {
+        return Ok(());
+    }
     if trade.escrow_balance == 0 {
         return Err(ContractError::EscrowNotFunded);
     }
+    if trade.escrow_balance < required_escrow(trade) {
+        return Err(ContractError::InsufficientEscrowFunding);
+    }
+
+    Ok(())
+}
+
+/// Fulfill order by seller or one of its operators (add CI and WR)
+pub fn fulfill_order(
+    env: &Env,
+    caller: &Address,
+    trade_id: u64,
+    invoice: CustomerInvoiceDetails,
+    receipt: WarehouseReceiptDetails,
+) -> Result<(), ContractError> {
+    let mut trade: TradeEscrow = env
+        .storage()
+        .instance()
+        .get(&DataKey::Trade(trade_id))
+        .ok_or(ContractError::TradeNotFound)?;
+
+    // Verify caller is seller or acts for it
+    if !operators::acts_for(env, caller, &trade.seller, OperatorAction::FulfillOrder)? {
+        return Err(ContractError::NotSeller);
+    }
+
+    // Verify trade is in Ordered state
+    state::require_state(&trade, TradeState::Ordered)?;
+    approval::require_none_pending(env, trade_id)?;
+
+    // Verify escrow is funded and still covers the order after any
+    // repricing, unless the buyer pays on open account
+    require_covered_escrow(&trade)?;
 
     // Verify buyer vLEI is validated
     let vlei_docs: VLEIDocuments = env
@@ -217,26 +411,55 @@ pub fn fulfill_order(
         return Err(ContractError::BuyerVLEINotValidated);
     }
 
+    // Verify each document identifies the product, adds up and references its content
+    matching::check_document_identity(&invoice.ci_description, &invoice.product)?;
+    matching::check_document_totals(invoice.quantity, invoice.unit_price, invoice.total_price)?;
+    matching::check_document_identity(&receipt.wr_description, &receipt.product)?;
+    matching::check_document_totals(receipt.quantity, receipt.unit_price, receipt.total_price)?;
+    documents::check_document_reference(&invoice.ci_json_ipfs_hash, &invoice.ci_json_hash)?;
+    documents::check_document_reference(&receipt.wr_json_ipfs_hash, &receipt.wr_json_hash)?;
+
+    // Verify the invoice restates the PO's delivery terms
+    let po: PurchaseOrder = env
+        .storage()
+        .instance()
+        .get(&DataKey::PurchaseOrder(trade_id))
+        .ok_or(ContractError::PurchaseOrderNotFound)?;
+    delivery::check_invoice_terms(&po, invoice.incoterm, &invoice.named_place)?;
+    delivery::check_delivery_window(env, &po)?;
+
+    // Verify the seller has put up any performance bond the PO asks for
+    if po.performance_bond_rate > 0 && trade.bond_balance == 0 {
+        return Err(ContractError::PerformanceBondRequired);
+    }
+
     // Create customer invoice
     let ci = CustomerInvoice {
-        ci_description,
-        quantity: ci_quantity,
-        unit_price: ci_unit_price,
-        total_price: ci_total_price,
-        ci_json_ipfs_hash,
-        created_by: seller.clone(),
+        ci_description: invoice.ci_description,
+        product: invoice.product,
+        quantity: invoice.quantity,
+        unit_price: invoice.unit_price,
+        total_price: invoice.total_price,
+        ci_json_ipfs_hash: invoice.ci_json_ipfs_hash,
+        ci_json_hash: invoice.ci_json_hash,
+        incoterm: invoice.incoterm,
+        named_place: invoice.named_place,
+        created_by: caller.clone(),
         created_at: env.ledger().timestamp(),
     };
 
     // Create warehouse receipt
     let wr = WarehouseReceipt {
-        wr_description,
-        quantity: wr_quantity,
-        unit_price: wr_unit_price,
-        total_price: ci_total_price, // Use CI total price since we removed wr_total_price
-        wr_json_ipfs_hash: String::from_str(env, ""), // Empty IPFS hash since we removed it
-        warehouse_location: String::from_str(env, ""), // Empty location since we removed it
-        created_by: seller.clone(),
+        wr_description: receipt.wr_description,
+        product: receipt.product,
+        quantity: receipt.quantity,
+        unit_price: receipt.unit_price,
+        total_price: receipt.total_price,
+        wr_json_ipfs_hash: receipt.wr_json_ipfs_hash,
+        wr_json_hash: receipt.wr_json_hash,
+        warehouse_location: receipt.warehouse_location,
+        lot: receipt.lot,
+        created_by: caller.clone(),
         created_at: env.ledger().timestamp(),
     };
 
@@ -249,7 +472,7 @@ pub fn fulfill_order(
         .set(&DataKey::WarehouseReceipt(trade_id), &wr);
 
     // Update trade state
-    trade.state = FULFILLED;
+    state::transition(env, &mut trade, TradeState::Fulfilled, caller)?;
     trade.fulfilled_at = env.ledger().timestamp();
 
     env.storage()
@@ -276,20 +499,35 @@ pub fn reject_order(
         return Err(ContractError::NotSeller);
     }
 
-    // Verify trade is in ORDERED state
-    if trade.state != ORDERED {
-        return Err(ContractError::InvalidTradeState);
-    }
-
     // Update trade state
-    trade.state = REJECTED;
+    state::transition(env, &mut trade, TradeState::Rejected, seller)?;
+
+    // A seller walking away from a bonded order forfeits the bond
+    bond::forfeit(env, &mut trade)?;
+    refund_buyer(env, &mut trade)?;
 
     env.storage()
         .instance()
         .set(&DataKey::Trade(trade_id), &trade);
 
-    // Note: Actual refund would happen via payment transaction
-    // This just updates the state
+    Ok(())
+}
+
+/// Refund the whole escrow to the buyer
+///
+/// An advance or milestones the seller already received become their debt to
+/// the buyer, any credit a deferred trade held is freed, a deposited
+/// warehouse receipt goes back to the seller, and funds parked awaiting
+/// approval are returned.
+pub fn refund_buyer(env: &Env, trade: &mut TradeEscrow) -> Result<(), ContractError> {
+    if trade.escrow_balance > 0 {
+        settlement::disburse(env, trade, &trade.buyer, trade.escrow_balance)?;
+    }
+    trade.escrow_balance = 0;
+    advance::record_debt(env, trade);
+    deferred::release_credit(env, trade);
+    title_receipt::return_to_seller(env, trade);
+    approval::cancel_pending(env, trade)?;
 
     Ok(())
 }
@@ -311,27 +549,23 @@ pub fn cancel_trade(
         return Err(ContractError::NotBuyer);
     }
 
-    // Verify trade is in ORDERED state
-    if trade.state != ORDERED {
-        return Err(ContractError::InvalidTradeState);
-    }
-
     // Update trade state
-    trade.state = CANCELLED;
+    state::transition(env, &mut trade, TradeState::Cancelled, buyer)?;
+
+    bond::release(env, &mut trade)?;
+    refund_buyer(env, &mut trade)?;
 
     env.storage()
         .instance()
         .set(&DataKey::Trade(trade_id), &trade);
 
-    // Note: Actual refund would happen via payment transaction
-
     Ok(())
 }
 
-/// Accept trade and settle (buyer triggers DvP)
+/// Accept trade and settle (buyer or one of its operators triggers DvP)
 pub fn accept_trade(
     env: &Env,
-    buyer: &Address,
+    caller: &Address,
     trade_id: u64,
     platform_treasury: &Address,
 ) -> Result<(), ContractError> {
@@ -341,33 +575,105 @@ pub fn accept_trade(
         .get(&DataKey::Trade(trade_id))
         .ok_or(ContractError::TradeNotFound)?;
 
-    // Verify caller is buyer
-    if &trade.buyer != buyer {
+    // Verify caller is buyer or acts for it
+    if !operators::acts_for(env, caller, &trade.buyer, OperatorAction::AcceptTrade)? {
         return Err(ContractError::NotBuyer);
     }
 
-    // Verify trade is in FULFILLED state
-    if trade.state != FULFILLED {
-        return Err(ContractError::TradeNotFulfilled);
-    }
+    approval::require_none_pending(env, trade_id)?;
 
     // Call DvP check (which calls three_way_match internally)
     crate::matching::dvp_check(env, trade_id)?;
 
     // If we reach here, DvP check passed
+    title_receipt::require_deposited(env, &trade)?;
+    if approval::requires_approval(env, &trade) {
+        return approval::hold(env, trade_id, ApprovalAction::AcceptTrade);
+    }
+
+    settle(env, &mut trade, caller, platform_treasury)
+}
+
+/// Settle a trade that passed its DvP check
+///
+/// Pays the seller or defers the payment, returns the bond, passes title
+/// to any deposited warehouse receipt and marks every milestone released.
+pub fn settle(
+    env: &Env,
+    trade: &mut TradeEscrow,
+    actor: &Address,
+    platform_treasury: &Address,
+) -> Result<(), ContractError> {
+    let po: PurchaseOrder = env
+        .storage()
+        .instance()
+        .get(&DataKey::PurchaseOrder(trade.trade_id))
+        .ok_or(ContractError::PurchaseOrderNotFound)?;
+    if po.payment_terms_days > 0 {
+        // Goods are accepted now and paid for by the due date
+        deferred::defer_payment(env, trade, po.payment_terms_days);
+    } else {
+        release_payment(env, trade, platform_treasury)?;
+    }
+    bond::release(env, trade)?;
+    // Title to the goods passes in the same step as payment
+    title_receipt::deliver(env, trade);
+    trade.milestones_released = po.payment_schedule.len();
+
     // Update trade state
-    trade.state = SETTLED;
+    state::transition(env, trade, TradeState::Settled, actor)?;
     trade.settled_at = env.ledger().timestamp();
 
     env.storage()
         .instance()
-        .set(&DataKey::Trade(trade_id), &trade);
+        .set(&DataKey::Trade(trade.trade_id), trade);
+
+    Ok(())
+}
+
+/// Pay a trade's escrow out to the seller, treasury and broker
+///
+/// The seller gets the amount less their share of the fee and any broker
+/// commission and whatever milestones or advance already paid them, adjusted
+/// for any early-payment discount or late penalty on a deferred trade. If the
+/// seller minted a receivable, its holder is paid in the seller's place. The
+/// broker gets the commission and the treasury gets the fee.
+pub fn release_payment(
+    env: &Env,
+    trade: &mut TradeEscrow,
+    platform_treasury: &Address,
+) -> Result<(), ContractError> {
+    let seller_payout = trade.amount
+        - trade.seller_fee
+        - trade.broker_commission
+        - trade.released_to_seller
+        - trade.advance_paid
+        - trade.early_payment_discount
+        + trade.late_payment_penalty;
+    // A factor holding the receivable is paid instead of the seller
+    match receivable::holder(env, trade) {
+        Some(holder) if holder != trade.seller => {
+            settlement::disburse(env, trade, &holder, seller_payout)?
+        }
+        _ => advance::pay_seller(env, trade, seller_payout)?,
+    }
+    if treasury::retains_fees(env) {
+        treasury::retain_fee(env, trade);
+    } else {
+        settlement::disburse(env, trade, platform_treasury, trade.marketplace_fee)?;
+    }
+    treasury::record_fee_earned(env, trade);
+
+    if let Some(broker) = &trade.broker {
+        settlement::disburse(env, trade, broker, trade.broker_commission)?;
+        fees::record_broker_earnings(env, broker, trade.broker_commission);
+    }
+
+    // Count the trade towards both parties' fee tiers
+    fees::record_settled_volume(env, &trade.buyer, trade.amount);
+    fees::record_settled_volume(env, &trade.seller, trade.amount);
 
-    // Note: Actual payment transfers would happen via separate payment transactions
-    // The contract just validates and updates state
-    // In a full implementation, this would trigger internal transfers to:
-    // - seller (trade.amount)
-    // - platform_treasury (trade.marketplace_fee)
+    trade.escrow_balance -= seller_payout + trade.marketplace_fee + trade.broker_commission;
 
     Ok(())
 }
@@ -0,0 +1,129 @@
+//! Fee ledger, fee retention and treasury withdrawals
+
+use soroban_sdk::{Address, Env, Vec};
+
+use crate::errors::ContractError;
+use crate::settlement;
+use crate::storage::{self, DataKey};
+use crate::types::{FeeLedger, FeePeriodTotals, TradeEscrow};
+
+/// Length of a reporting period in the fee ledger (one day)
+pub const FEE_PERIOD_SECONDS: u64 = 86400;
+
+/// Longest range of periods a single fee totals query may cover
+pub const MAX_FEE_PERIODS: u64 = 366;
+
+/// Get all-time fee totals for an asset
+pub fn get_fee_ledger(env: &Env, asset: &Address) -> FeeLedger {
+    storage::get_persistent(env, &DataKey::FeeLedger(asset.clone())).unwrap_or(empty_ledger())
+}
+
+/// Get fee totals for each period in `from_period..=to_period` with activity
+pub fn get_fee_totals(
+    env: &Env,
+    asset: &Address,
+    from_period: u64,
+    to_period: u64,
+) -> Result<Vec<FeePeriodTotals>, ContractError> {
+    if to_period < from_period || to_period - from_period >= MAX_FEE_PERIODS {
+        return Err(ContractError::InvalidPeriodRange);
+    }
+
+    let mut totals = Vec::new(env);
+    for period in from_period..=to_period {
+        if let Some(ledger) =
+            storage::get_persistent::<FeeLedger>(env, &DataKey::FeePeriod(asset.clone(), period))
+        {
+            totals.push_back(FeePeriodTotals { period, ledger });
+        }
+    }
+    Ok(totals)
+}
+
+/// Get fees held by the contract and available to withdraw for an asset
+pub fn get_retained_fees(env: &Env, asset: &Address) -> i128 {
+    env.storage()
+        .instance()
+        .get(&DataKey::RetainedFees(asset.clone()))
+        .unwrap_or(0)
+}
+
+/// Whether settled fees stay in the contract instead of going to the treasury
+pub fn retains_fees(env: &Env) -> bool {
+    env.storage()
+        .instance()
+        .get(&DataKey::RetainFees)
+        .unwrap_or(false)
+}
+
+/// Get the account allowed to withdraw retained fees besides the owner
+pub fn get_fee_manager(env: &Env) -> Option<Address> {
+    env.storage().instance().get(&DataKey::FeeManager)
+}
+
+/// Book the fee on a trade as it settles
+///
+/// Fees are only earned once paid out, so trades that are refunded,
+/// cancelled or repriced before settlement never need reversing. Trades
+/// settled off-chain have no asset and are not ledgered.
+pub fn record_fee_earned(env: &Env, trade: &TradeEscrow) {
+    if let Some(asset) = &trade.payment_token {
+        update_ledgers(env, asset, |ledger| ledger.earned += trade.marketplace_fee);
+    }
+}
+
+/// Keep a settled trade's fee in the contract for later withdrawal
+pub fn retain_fee(env: &Env, trade: &TradeEscrow) {
+    if let Some(asset) = &trade.payment_token {
+        let retained = get_retained_fees(env, asset) + trade.marketplace_fee;
+        env.storage()
+            .instance()
+            .set(&DataKey::RetainedFees(asset.clone()), &retained);
+    }
+}
+
+/// Withdraw retained fees to a recipient
+pub fn withdraw_fees(
+    env: &Env,
+    asset: &Address,
+    amount: i128,
+    recipient: &Address,
+) -> Result<(), ContractError> {
+    if amount <= 0 {
+        return Err(ContractError::InvalidAmount);
+    }
+
+    let retained = get_retained_fees(env, asset);
+    if amount > retained {
+        return Err(ContractError::InsufficientRetainedFees);
+    }
+
+    settlement::transfer(env, asset, &env.current_contract_address(), recipient, amount)?;
+
+    env.storage()
+        .instance()
+        .set(&DataKey::RetainedFees(asset.clone()), &(retained - amount));
+    update_ledgers(env, asset, |ledger| ledger.withdrawn += amount);
+
+    Ok(())
+}
+
+/// Apply a fee movement to the all-time and current-period ledgers
+fn update_ledgers(env: &Env, asset: &Address, apply: impl Fn(&mut FeeLedger)) {
+    let period = env.ledger().timestamp() / FEE_PERIOD_SECONDS;
+    for key in [
+        DataKey::FeeLedger(asset.clone()),
+        DataKey::FeePeriod(asset.clone(), period),
+    ] {
+        let mut ledger: FeeLedger = storage::get_persistent(env, &key).unwrap_or(empty_ledger());
+        apply(&mut ledger);
+        storage::set_persistent(env, &key, &ledger);
+    }
+}
+
+fn empty_ledger() -> FeeLedger {
+    FeeLedger {
+        earned: 0,
+        withdrawn: 0,
+    }
+}
@@ -1,13 +1,81 @@
 //! Data structures for the MarketplaceEscrowV1 contract
 
-use soroban_sdk::{contracttype, Address, String};
+use soroban_sdk::{contracttype, Address, BytesN, String, Vec};
 
-/// Trade state constants
-pub const ORDERED: u32 = 0;
-pub const FULFILLED: u32 = 1;
-pub const SETTLED: u32 = 2;
-pub const REJECTED: u32 = 3;
-pub const CANCELLED: u32 = 4;
+/// Lifecycle state of a trade
+///
+/// Stored as a `u32`, the same encoding as the state constants used by
+/// contract version 1.
+#[contracttype]
+#[derive(Clone, Copy, Debug, Eq, PartialEq)]
+#[repr(u32)]
+pub enum TradeState {
+    /// Buyer created PO and funded escrow
+    Ordered = 0,
+    /// Seller shipped goods and submitted CI + WR
+    Fulfilled = 1,
+    /// 3-way match passed, payment released
+    Settled = 2,
+    /// Seller rejected the order
+    Rejected = 3,
+    /// Buyer cancelled before fulfillment
+    Cancelled = 4,
+    /// Goods failed inspection and the escrow went back to the buyer
+    Refunded = 5,
+}
+
+/// Entry in a trade's state-history log
+#[contracttype]
+#[derive(Clone, Debug, Eq, PartialEq)]
+pub struct StateChange {
+    /// Previous state, equal to `to` for the entry logged at creation
+    pub from: TradeState,
+    pub to: TradeState,
+    pub timestamp: u64,
+    pub actor: Address,
+}
+
+/// Who bears the marketplace fee on a trade
+#[contracttype]
+#[derive(Clone, Copy, Debug, Eq, PartialEq)]
+pub enum FeePayer {
+    /// Fee is added on top of the buyer's escrow funding
+    Buyer,
+    /// Fee is deducted from the seller's payout
+    Seller,
+    /// Buyer and seller each bear half of the fee
+    Split,
+}
+
+/// Fee rate that applies from a trailing settled volume upwards
+#[contracttype]
+#[derive(Clone, Debug, Eq, PartialEq)]
+pub struct FeeTier {
+    pub min_volume: i128,
+    pub fee_rate: u32,
+}
+
+/// Volume-tiered fee schedule with per-trade fee caps
+#[contracttype]
+#[derive(Clone, Debug, Eq, PartialEq)]
+pub struct FeeSchedule {
+    /// Tiers in ascending `min_volume` order, the first starting at 0
+    pub tiers: Vec<FeeTier>,
+    /// Number of days of settled volume that count towards a tier
+    pub volume_window_days: u32,
+    /// Smallest fee charged on a trade
+    pub min_fee: i128,
+    /// Largest fee charged on a trade (0 for no cap)
+    pub max_fee: i128,
+}
+
+/// Settled volume of a participant on one day
+#[contracttype]
+#[derive(Clone, Debug, Eq, PartialEq)]
+pub struct VolumeBucket {
+    pub day: u64,
+    pub volume: i128,
+}
 
 /// Buyer information stored in registry
 #[contracttype]
@@ -31,6 +99,86 @@ pub struct SellerInfo {
     pub is_active: bool,
 }
 
+/// Carrier information stored in registry
+#[contracttype]
+#[derive(Clone, Debug, Eq, PartialEq)]
+pub struct CarrierInfo {
+    pub name: String,
+    pub lei_id: String,
+    pub wallet_address: Address,
+    pub registered_at: u64,
+    pub is_active: bool,
+}
+
+/// Inspection agency information stored in registry
+#[contracttype]
+#[derive(Clone, Debug, Eq, PartialEq)]
+pub struct InspectionAgencyInfo {
+    pub name: String,
+    pub lei_id: String,
+    pub wallet_address: Address,
+    pub registered_at: u64,
+    pub is_active: bool,
+}
+
+/// Running fee totals for one asset
+#[contracttype]
+#[derive(Clone, Debug, Eq, PartialEq)]
+pub struct FeeLedger {
+    /// Fees booked when trades settled
+    pub earned: i128,
+    /// Retained fees paid out with `withdraw_fees`
+    pub withdrawn: i128,
+}
+
+/// Fee totals booked during one ledger period
+#[contracttype]
+#[derive(Clone, Debug, Eq, PartialEq)]
+pub struct FeePeriodTotals {
+    pub period: u64,
+    pub ledger: FeeLedger,
+}
+
+/// Broker information stored in registry
+#[contracttype]
+#[derive(Clone, Debug, Eq, PartialEq)]
+pub struct BrokerInfo {
+    pub name: String,
+    pub lei_id: String,
+    pub wallet_address: Address,
+    pub registered_at: u64,
+    pub is_active: bool,
+}
+
+/// Broker introducing a trade and their commission in basis points
+#[contracttype]
+#[derive(Clone, Debug, Eq, PartialEq)]
+pub struct BrokerCommission {
+    pub broker: Address,
+    pub commission_rate: u32,
+}
+
+/// What paying a deferred trade now would cost the buyer
+#[contracttype]
+#[derive(Clone, Debug, Eq, PartialEq)]
+pub struct DeferredPaymentQuote {
+    pub discount: i128,
+    pub penalty: i128,
+    /// Amount less discount plus penalty and the buyer's share of the fee
+    pub total_due: i128,
+}
+
+/// Payout a seller still owes after its trade was unwound
+#[contracttype]
+#[derive(Clone, Debug, Eq, PartialEq)]
+pub struct SellerDebt {
+    pub trade_id: u64,
+    /// Buyer whose escrow funded the advance or milestones
+    pub creditor: Address,
+    pub payment_token: Option<Address>,
+    pub outstanding: i128,
+}
+
 /// Core trade escrow record
 #[contracttype]
 #[derive(Clone, Debug, Eq, PartialEq)]
@@ -39,12 +187,123 @@ pub struct TradeEscrow {
     pub buyer: Address,
     pub seller: Address,
     pub amount: i128,
-    pub state: u32,
+    pub state: TradeState,
     pub created_at: u64,
     pub fulfilled_at: u64,
     pub settled_at: u64,
+    pub updated_at: u64,
     pub marketplace_fee: i128,
     pub escrow_balance: i128,
+    pub payment_token: Option<Address>,
+    pub fee_payer: FeePayer,
+    pub seller_fee: i128,
+    pub broker: Option<Address>,
+    pub broker_commission_rate: u32,
+    pub broker_commission: i128,
+    /// Version of the purchase order currently in force, starting at 1
+    pub po_version: u32,
+    /// Payment milestones paid out so far, in schedule order
+    pub milestones_released: u32,
+    /// Seller payout already released through milestones
+    pub released_to_seller: i128,
+    /// Working-capital advance paid to the seller before settlement
+    pub advance_paid: i128,
+    /// Seller performance bond held in escrow
+    pub bond_balance: i128,
+    /// Delivery date recorded with the bond; missing it forfeits the bond
+    pub bond_deadline: u64,
+    /// Buyer credit held by a deferred trade until it is paid
+    pub credit_reserved: i128,
+    /// When a deferred trade must be paid, set at settlement
+    pub payment_due: u64,
+    /// Discount taken for paying a deferred trade early
+    pub early_payment_discount: i128,
+    /// Penalty charged for paying a deferred trade late
+    pub late_payment_penalty: i128,
+    /// NFT contract a receivable for the payout was minted on, if any; the
+    /// token's holder is paid the seller's share at settlement
+    pub receivable_contract: Option<Address>,
+    /// Token ID of the receivable, when one was minted
+    pub receivable_token_id: u32,
+    /// NFT contract of the warehouse receipt the seller deposited, if any
+    pub title_receipt_contract: Option<Address>,
+    /// Token ID of the deposited warehouse receipt
+    pub title_receipt_token_id: u32,
+}
+
+/// Officers who must sign off on a participant's high-value trades
+#[contracttype]
+#[derive(Clone, Debug, Eq, PartialEq)]
+pub struct ApprovalPolicy {
+    /// Trade amount above which actions need approval
+    pub threshold: i128,
+    pub approvers: Vec<Address>,
+    /// Approvals needed out of `approvers` (the M of M-of-N)
+    pub required: u32,
+}
+
+/// Buyer action held until its approvers sign off
+#[contracttype]
+#[derive(Clone, Debug, Eq, PartialEq)]
+pub enum ApprovalAction {
+    /// The trade itself, which can't progress until approved
+    CreateTrade,
+    /// Funding collected from the buyer and parked outside the escrow
+    FundEscrow(EscrowCost),
+    /// Settlement of a trade that passed its DvP check
+    AcceptTrade,
+    /// Advance of the given amount to the seller
+    AuthorizeAdvance(i128),
+    /// Release of the milestone at the given schedule index
+    ReleaseMilestone(u32),
+    /// Top-up of the given amount collected and parked outside the escrow
+    TopUpEscrow(i128),
+    /// Acceptance of the numbered change order, with the amount the buyer
+    /// paid towards it parked outside the escrow
+    AcceptChangeOrder(u32, i128),
+}
+
+/// Action on a trade waiting for approval, and who has approved it so far
+#[contracttype]
+#[derive(Clone, Debug, Eq, PartialEq)]
+pub struct PendingApproval {
+    pub action: ApprovalAction,
+    pub approvals: Vec<Address>,
+    pub requested_at: u64,
+}
+
+/// Trade actions a participant delegates to an operator
+#[contracttype]
+#[derive(Clone, Debug, Eq, PartialEq)]
+pub struct OperatorPermissions {
+    /// Largest trade the operator may create for a buyer; 0 for none
+    pub max_trade_amount: i128,
+    pub fulfill_orders: bool,
+    pub accept_trades: bool,
+    /// Ledger timestamp the delegation lapses at
+    pub expires_at: u64,
+}
+
+/// Staff address acting on trades for a participant
+#[contracttype]
+#[derive(Clone, Debug, Eq, PartialEq)]
+pub struct OperatorGrant {
+    pub participant: Address,
+    pub permissions: OperatorPermissions,
+    pub granted_at: u64,
+}
+
+/// Breakdown of what a trade costs each party
+#[contracttype]
+#[derive(Clone, Debug, Eq, PartialEq)]
+pub struct EscrowCost {
+    pub fee_payer: FeePayer,
+    pub fee_rate: u32,
+    pub marketplace_fee: i128,
+    pub buyer_fee: i128,
+    pub seller_fee: i128,
+    pub total_required: i128,
+    pub seller_payout: i128,
 }
 
 /// Purchase Order created by buyer
@@ -52,23 +311,307 @@ pub struct TradeEscrow {
 #[derive(Clone, Debug, Eq, PartialEq)]
 pub struct PurchaseOrder {
     pub po_description: String,
+    pub product: ProductId,
     pub quantity: u64,
     pub unit_price: i128,
     pub total_price: i128,
     pub po_json_ipfs_hash: String,
+    /// SHA-256 of the canonical PO JSON
+    pub po_json_hash: BytesN<32>,
+    pub delivery: DeliveryTerms,
+    /// Whether settlement needs a passing quality inspection certificate
+    pub requires_inspection: bool,
+    /// Agency the parties agreed will inspect the goods; required when
+    /// `requires_inspection` is set
+    pub inspection_agency: Option<Address>,
+    /// Staged payments in release order; empty pays everything at settlement
+    pub payment_schedule: Vec<Milestone>,
+    /// Seller performance bond in basis points of the order value; 0 for none
+    pub performance_bond_rate: u32,
+    /// Days after settlement the buyer has to pay (Net-30, Net-60); 0 pays
+    /// into escrow up front
+    pub payment_terms_days: u32,
+    /// Discount in basis points for paying a deferred trade early ("2/10")
+    pub early_payment_discount_bps: u32,
+    /// Days after settlement the early-payment discount is available
+    pub discount_window_days: u32,
+    /// Penalty in basis points for each day a deferred payment is late
+    pub late_penalty_bps_per_day: u32,
+    /// Whether settlement needs the seller's warehouse receipt NFT, which
+    /// passes to the buyer as title to the goods
+    pub requires_title_receipt: bool,
     pub created_by: Address,
     pub created_at: u64,
 }
 
+/// Field compared during three-way matching
+#[contracttype]
+#[derive(Clone, Copy, Debug, Eq, PartialEq)]
+pub enum MatchField {
+    ProductId,
+    Description,
+    Quantity,
+    TotalPrice,
+    UnitPrice,
+}
+
+/// Pair of documents compared during three-way matching
+#[contracttype]
+#[derive(Clone, Copy, Debug, Eq, PartialEq)]
+pub enum DocumentPair {
+    /// Purchase order against customer invoice
+    PoCi,
+    /// Purchase order against warehouse receipt
+    PoWr,
+    /// Customer invoice against warehouse receipt
+    CiWr,
+    /// Purchase order against bill of lading
+    PoBl,
+    /// Customer invoice against bill of lading
+    CiBl,
+    /// Warehouse receipt against bill of lading
+    WrBl,
+}
+
+/// Outcome of one pairwise comparison
+#[contracttype]
+#[derive(Clone, Debug, Eq, PartialEq)]
+pub struct MatchCheck {
+    pub field: MatchField,
+    pub pair: DocumentPair,
+    /// Variance of the second document from the first (10000 when identity differs)
+    pub variance_bps: u32,
+    pub tolerance_bps: u32,
+    pub passed: bool,
+}
+
+/// Every comparison three-way matching makes on a trade's documents
+#[contracttype]
+#[derive(Clone, Debug, Eq, PartialEq)]
+pub struct MatchReport {
+    pub trade_id: u64,
+    pub checks: Vec<MatchCheck>,
+    /// Documents the match needs that are not on file yet, left unmatched
+    pub missing: Vec<DocumentKind>,
+    pub passed: bool,
+}
+
+/// Incoterms 2020 rule setting where delivery and risk pass to the buyer
+#[contracttype]
+#[derive(Clone, Copy, Debug, Eq, PartialEq)]
+pub enum Incoterm {
+    /// Ex Works
+    Exw,
+    /// Free Carrier
+    Fca,
+    /// Carriage Paid To
+    Cpt,
+    /// Carriage and Insurance Paid To
+    Cip,
+    /// Delivered at Place
+    Dap,
+    /// Delivered at Place Unloaded
+    Dpu,
+    /// Delivered Duty Paid
+    Ddp,
+    /// Free Alongside Ship
+    Fas,
+    /// Free on Board
+    Fob,
+    /// Cost and Freight
+    Cfr,
+    /// Cost, Insurance and Freight
+    Cif,
+}
+
+/// Delivery terms agreed on a purchase order
+#[contracttype]
+#[derive(Clone, Debug, Eq, PartialEq)]
+pub struct DeliveryTerms {
+    pub incoterm: Incoterm,
+    /// Place the Incoterm refers to, e.g. "Port of Rotterdam"
+    pub named_place: String,
+    /// Earliest delivery date (ledger timestamp)
+    pub window_start: u64,
+    /// Latest delivery date (ledger timestamp)
+    pub window_end: u64,
+}
+
+/// How much of the order a milestone pays
+#[contracttype]
+#[derive(Clone, Debug, Eq, PartialEq)]
+pub enum MilestoneAmount {
+    /// Share of the order total in basis points
+    Percentage(u32),
+    /// Fixed amount in the payment token
+    Fixed(i128),
+}
+
+/// Stage of a payment schedule and the documents that release it
+#[contracttype]
+#[derive(Clone, Debug, Eq, PartialEq)]
+pub struct Milestone {
+    /// e.g. "deposit", "production complete", "shipped", "delivered"
+    pub label: String,
+    pub amount: MilestoneAmount,
+    /// Documents that must be on file, and match each other, before release
+    pub evidence: Vec<DocumentKind>,
+}
+
+/// Trade document whose content hash can be verified
+#[contracttype]
+#[derive(Clone, Copy, Debug, Eq, PartialEq)]
+pub enum DocumentKind {
+    PurchaseOrder,
+    CustomerInvoice,
+    WarehouseReceipt,
+    BillOfLading,
+    InspectionCertificate,
+}
+
+/// Structured identifiers for the traded product
+///
+/// At least one identifier is required on new documents. Identifiers both
+/// documents carry must be equal for them to match.
+#[contracttype]
+#[derive(Clone, Debug, Eq, PartialEq)]
+pub struct ProductId {
+    /// GS1 Global Trade Item Number
+    pub gtin: Option<String>,
+    /// Harmonized System tariff code
+    pub hs_code: Option<String>,
+    /// Seller's stock-keeping unit
+    pub sku: Option<String>,
+}
+
+/// Purchase order details submitted by the buyer, or proposed in a change order
+#[contracttype]
+#[derive(Clone, Debug, Eq, PartialEq)]
+pub struct PurchaseOrderDetails {
+    pub po_description: String,
+    pub product: ProductId,
+    pub quantity: u64,
+    pub unit_price: i128,
+    pub total_price: i128,
+    pub po_json_ipfs_hash: String,
+    /// SHA-256 of the canonical PO JSON
+    pub po_json_hash: BytesN<32>,
+    pub delivery: DeliveryTerms,
+    /// Whether settlement needs a passing quality inspection certificate
+    pub requires_inspection: bool,
+    /// Agency the parties agreed will inspect the goods; required when
+    /// `requires_inspection` is set
+    pub inspection_agency: Option<Address>,
+    /// Staged payments in release order; empty pays everything at settlement
+    pub payment_schedule: Vec<Milestone>,
+    /// Seller performance bond in basis points of the order value; 0 for none
+    pub performance_bond_rate: u32,
+    /// Days after settlement the buyer has to pay (Net-30, Net-60); 0 pays
+    /// into escrow up front
+    pub payment_terms_days: u32,
+    /// Discount in basis points for paying a deferred trade early ("2/10")
+    pub early_payment_discount_bps: u32,
+    /// Days after settlement the early-payment discount is available
+    pub discount_window_days: u32,
+    /// Penalty in basis points for each day a deferred payment is late
+    pub late_penalty_bps_per_day: u32,
+    /// Whether settlement needs the seller's warehouse receipt NFT, which
+    /// passes to the buyer as title to the goods
+    pub requires_title_receipt: bool,
+}
+
+/// Customer invoice details submitted by the seller at fulfillment
+#[contracttype]
+#[derive(Clone, Debug, Eq, PartialEq)]
+pub struct CustomerInvoiceDetails {
+    pub ci_description: String,
+    pub product: ProductId,
+    pub quantity: u64,
+    pub unit_price: i128,
+    pub total_price: i128,
+    pub ci_json_ipfs_hash: String,
+    /// SHA-256 of the canonical CI JSON
+    pub ci_json_hash: BytesN<32>,
+    /// Incoterm stated on the invoice, must match the PO
+    pub incoterm: Incoterm,
+    /// Named place stated on the invoice, must match the PO
+    pub named_place: String,
+}
+
+/// Warehouse receipt details submitted by the seller at fulfillment
+#[contracttype]
+#[derive(Clone, Debug, Eq, PartialEq)]
+pub struct WarehouseReceiptDetails {
+    pub wr_description: String,
+    pub product: ProductId,
+    pub quantity: u64,
+    pub unit_price: i128,
+    pub total_price: i128,
+    pub wr_json_ipfs_hash: String,
+    /// SHA-256 of the canonical WR JSON
+    pub wr_json_hash: BytesN<32>,
+    /// Warehouse the goods are stored at
+    pub warehouse_location: String,
+    /// Warehouse lot number the goods are stored under
+    pub lot: String,
+}
+
+/// Bill of lading details submitted by the carrier
+#[contracttype]
+#[derive(Clone, Debug, Eq, PartialEq)]
+pub struct BillOfLadingDetails {
+    pub vessel: String,
+    pub voyage: String,
+    pub port_of_loading: String,
+    pub port_of_discharge: String,
+    pub quantity: u64,
+    pub container_ids: Vec<String>,
+    pub bl_json_ipfs_hash: String,
+    /// SHA-256 of the canonical BL JSON
+    pub bl_json_hash: BytesN<32>,
+}
+
+/// Inspection certificate details submitted by the agency
+#[contracttype]
+#[derive(Clone, Debug, Eq, PartialEq)]
+pub struct InspectionCertificateDetails {
+    pub passed: bool,
+    pub grade: String,
+    pub sampled_quantity: u64,
+    pub report_ipfs_hash: String,
+    /// SHA-256 of the inspection report
+    pub report_hash: BytesN<32>,
+}
+
+/// Change order awaiting the counterparty's consent
+#[contracttype]
+#[derive(Clone, Debug, Eq, PartialEq)]
+pub struct ChangeOrder {
+    /// PO version this change order would create
+    pub version: u32,
+    /// Counts every proposal on the trade, so acceptance names the one reviewed
+    pub proposal: u32,
+    pub terms: PurchaseOrderDetails,
+    pub proposed_by: Address,
+    pub proposed_at: u64,
+}
+
 /// Customer Invoice generated by seller
 #[contracttype]
 #[derive(Clone, Debug, Eq, PartialEq)]
 pub struct CustomerInvoice {
     pub ci_description: String,
+    pub product: ProductId,
     pub quantity: u64,
     pub unit_price: i128,
     pub total_price: i128,
     pub ci_json_ipfs_hash: String,
+    /// SHA-256 of the canonical CI JSON
+    pub ci_json_hash: BytesN<32>,
+    /// Incoterm stated on the invoice, must match the PO
+    pub incoterm: Incoterm,
+    /// Named place stated on the invoice, must match the PO
+    pub named_place: String,
     pub created_by: Address,
     pub created_at: u64,
 }
@@ -78,15 +621,59 @@ pub struct CustomerInvoice {
 #[derive(Clone, Debug, Eq, PartialEq)]
 pub struct WarehouseReceipt {
     pub wr_description: String,
+    pub product: ProductId,
     pub quantity: u64,
     pub unit_price: i128,
     pub total_price: i128,
     pub wr_json_ipfs_hash: String,
+    /// SHA-256 of the canonical WR JSON
+    pub wr_json_hash: BytesN<32>,
     pub warehouse_location: String,
+    pub lot: String,
     pub created_by: Address,
     pub created_at: u64,
 }
 
+/// Goods a warehouse receipt NFT gives title to, as its contract records them
+#[contracttype]
+#[derive(Clone, Debug, Eq, PartialEq)]
+pub struct ReceiptMetadata {
+    pub warehouse: Address,
+    pub lot: String,
+    pub quantity: u64,
+    pub location: String,
+    pub issued_at: u64,
+}
+
+/// Bill of Lading issued by the carrier as shipment evidence
+#[contracttype]
+#[derive(Clone, Debug, Eq, PartialEq)]
+pub struct BillOfLading {
+    pub carrier: Address,
+    pub vessel: String,
+    pub voyage: String,
+    pub port_of_loading: String,
+    pub port_of_discharge: String,
+    pub quantity: u64,
+    pub container_ids: Vec<String>,
+    pub bl_json_ipfs_hash: String,
+    pub bl_json_hash: BytesN<32>,
+    pub issued_at: u64,
+}
+
+/// Quality inspection certificate issued by a registered agency
+#[contracttype]
+#[derive(Clone, Debug, Eq, PartialEq)]
+pub struct InspectionCertificate {
+    pub agency: Address,
+    pub passed: bool,
+    pub grade: String,
+    pub sampled_quantity: u64,
+    pub report_ipfs_hash: String,
+    pub report_hash: BytesN<32>,
+    pub issued_at: u64,
+}
+
 /// vLEI documents for GLEIF validation
 #[contracttype]
 #[derive(Clone, Debug, Eq, PartialEq)]
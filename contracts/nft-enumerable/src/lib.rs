@@ -3,3 +3,5 @@
 
 mod contract;
 mod test;
+
+pub use contract::{ExampleContract, ExampleContractClient};
@@ -0,0 +1,130 @@
+//! Warehouse Receipt Contract.
+//!
+//! Warehouses approved by the owner issue negotiable warehouse receipts as
+//! non-fungible tokens. Each receipt is a document of title to a stored lot
+//! of goods, so transferring the token passes title to the goods. The lot,
+//! quantity and storage location are recorded with the token when it is
+//! issued and never change.
+
+use soroban_sdk::{
+    contract, contracterror, contractimpl, contracttype, panic_with_error, Address, Env, String,
+};
+use stellar_macros::default_impl;
+use stellar_tokens::non_fungible::{
+    enumerable::{Enumerable, NonFungibleEnumerable},
+    Base, NonFungibleToken,
+};
+
+#[contracttype]
+pub enum DataKey {
+    Owner,
+    Warehouse(Address),
+    Receipt(u32),
+}
+
+#[contracterror]
+#[derive(Copy, Clone, Debug, Eq, PartialEq, PartialOrd, Ord)]
+#[repr(u32)]
+pub enum WarehouseReceiptError {
+    NotWarehouse = 1,
+    InvalidQuantity = 2,
+    ReceiptNotFound = 3,
+}
+
+/// Goods a receipt gives title to
+#[contracttype]
+#[derive(Clone, Debug, Eq, PartialEq)]
+pub struct ReceiptMetadata {
+    pub warehouse: Address,
+    pub lot: String,
+    pub quantity: u64,
+    pub location: String,
+    pub issued_at: u64,
+}
+
+#[contract]
+pub struct WarehouseReceiptContract;
+
+#[contractimpl]
+impl WarehouseReceiptContract {
+    pub fn __constructor(e: &Env, owner: Address) {
+        e.storage().instance().set(&DataKey::Owner, &owner);
+        Base::set_metadata(
+            e,
+            String::from_str(e, "ipfs://"),
+            String::from_str(e, "Warehouse Receipt"),
+            String::from_str(e, "WHR"),
+        );
+    }
+
+    /// Allow a warehouse to issue receipts (owner only)
+    pub fn add_warehouse(e: &Env, warehouse: Address) {
+        Self::require_owner(e);
+        e.storage().instance().set(&DataKey::Warehouse(warehouse), &true);
+    }
+
+    /// Stop a warehouse issuing receipts; those it issued stay valid (owner only)
+    pub fn remove_warehouse(e: &Env, warehouse: Address) {
+        Self::require_owner(e);
+        e.storage().instance().remove(&DataKey::Warehouse(warehouse));
+    }
+
+    pub fn is_warehouse(e: &Env, warehouse: Address) -> bool {
+        e.storage().instance().has(&DataKey::Warehouse(warehouse))
+    }
+
+    /// Issue a receipt for a lot of goods the warehouse holds for `to`
+    ///
+    /// Returns the token ID.
+    pub fn issue(
+        e: &Env,
+        warehouse: Address,
+        to: Address,
+        lot: String,
+        quantity: u64,
+        location: String,
+    ) -> u32 {
+        warehouse.require_auth();
+        if !Self::is_warehouse(e, warehouse.clone()) {
+            panic_with_error!(e, WarehouseReceiptError::NotWarehouse);
+        }
+        if quantity == 0 {
+            panic_with_error!(e, WarehouseReceiptError::InvalidQuantity);
+        }
+
+        let token_id = Enumerable::sequential_mint(e, &to);
+        let receipt = ReceiptMetadata {
+            warehouse,
+            lot,
+            quantity,
+            location,
+            issued_at: e.ledger().timestamp(),
+        };
+        e.storage().persistent().set(&DataKey::Receipt(token_id), &receipt);
+        token_id
+    }
+
+    /// Goods a receipt gives title to
+    pub fn receipt(e: &Env, token_id: u32) -> ReceiptMetadata {
+        e.storage()
+            .persistent()
+            .get(&DataKey::Receipt(token_id))
+            .unwrap_or_else(|| panic_with_error!(e, WarehouseReceiptError::ReceiptNotFound))
+    }
+
+    fn require_owner(e: &Env) {
+        let owner: Address =
+            e.storage().instance().get(&DataKey::Owner).expect("owner should be set");
+        owner.require_auth();
+    }
+}
+
+#[default_impl]
+#[contractimpl]
+impl NonFungibleToken for WarehouseReceiptContract {
+    type ContractType = Enumerable;
+}
+
+#[default_impl]
+#[contractimpl]
+impl NonFungibleEnumerable for WarehouseReceiptContract {}
@@ -0,0 +1,7 @@
+#![no_std]
+#![allow(dead_code)]
+
+mod contract;
+mod test;
+
+pub use contract::{ReceiptMetadata, WarehouseReceiptContract, WarehouseReceiptContractClient};
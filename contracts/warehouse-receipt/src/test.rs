@@ -0,0 +1,68 @@
+#![cfg(test)]
+
+extern crate std;
+
+use soroban_sdk::{testutils::Address as _, Address, Env, String};
+
+use crate::contract::{WarehouseReceiptContract, WarehouseReceiptContractClient};
+
+fn create_client<'a>(e: &Env, owner: &Address) -> WarehouseReceiptContractClient<'a> {
+    let address = e.register(WarehouseReceiptContract, (owner,));
+    WarehouseReceiptContractClient::new(e, &address)
+}
+
+#[test]
+fn issued_receipt_carries_title_metadata() {
+    let e = Env::default();
+    let owner = Address::generate(&e);
+    let warehouse = Address::generate(&e);
+    let depositor = Address::generate(&e);
+    let buyer = Address::generate(&e);
+    let client = create_client(&e, &owner);
+
+    e.mock_all_auths();
+    client.add_warehouse(&warehouse);
+    let token_id = client.issue(
+        &warehouse,
+        &depositor,
+        &String::from_str(&e, "LOT-2024-0117"),
+        &1000,
+        &String::from_str(&e, "Port of Rotterdam, Bay 12"),
+    );
+    assert_eq!(client.owner_of(&token_id), depositor);
+
+    let receipt = client.receipt(&token_id);
+    assert_eq!(receipt.warehouse, warehouse);
+    assert_eq!(receipt.quantity, 1000);
+
+    client.transfer(&depositor, &buyer, &token_id);
+    assert_eq!(client.owner_of(&token_id), buyer);
+    assert_eq!(client.get_owner_token_id(&buyer, &0), token_id);
+}
+
+#[test]
+fn only_approved_warehouses_issue() {
+    let e = Env::default();
+    let owner = Address::generate(&e);
+    let warehouse = Address::generate(&e);
+    let client = create_client(&e, &owner);
+
+    e.mock_all_auths();
+    let issue = || {
+        client.try_issue(
+            &warehouse,
+            &warehouse,
+            &String::from_str(&e, "LOT-2024-0118"),
+            &500,
+            &String::from_str(&e, "Port of Rotterdam, Bay 12"),
+        )
+    };
+    assert!(issue().is_err());
+
+    client.add_warehouse(&warehouse);
+    assert!(issue().is_ok());
+
+    client.remove_warehouse(&warehouse);
+    assert!(!client.is_warehouse(&warehouse));
+    assert!(issue().is_err());
+}